@@ -1,196 +1,1718 @@
 use anyhow::{Context, Result};
+use cargo_metadata::MetadataCommand;
 use globset::{Glob, GlobSet, GlobSetBuilder};
-use serde::Deserialize;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
-    time::Duration,
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-#[derive(Debug, Clone, Deserialize, Default)]
+/// Errors from the library API, for embedders that need to tell failure kinds apart.
+/// `main` works with `anyhow` and picks these up through `?`.
+#[derive(Debug)]
+pub enum RairError {
+    /// A config file couldn't be read.
+    ConfigRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A config file or `.rairignore` couldn't be parsed.
+    ConfigParse {
+        path: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A config file whose format needs a cargo feature rair was built without.
+    UnsupportedFormat {
+        path: PathBuf,
+        feature: &'static str,
+    },
+    /// A pattern in `ignore`, `trigger_globs`, `always_rebuild` or a service's `ignore`.
+    BadGlob {
+        glob: String,
+        source: globset::Error,
+    },
+    /// A setting that's out of range or conflicts with another.
+    InvalidConfig(String),
+    CurrentDir(std::io::Error),
+    /// A watch path that exists but couldn't be registered.
+    WatchSetup {
+        path: PathBuf,
+        message: String,
+    },
+    /// Nothing could be watched and nothing else can trigger a build.
+    NoWatchPaths,
+    /// A build step's command couldn't be started or waited on.
+    BuildSpawn {
+        program: String,
+        source: std::io::Error,
+    },
+    Metadata(cargo_metadata::Error),
+}
+
+impl std::fmt::Display for RairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RairError::ConfigRead { path, .. } => write!(f, "read config {:?}", path),
+            RairError::ConfigParse { path, .. } => write!(f, "parse {:?}", path),
+            RairError::UnsupportedFormat { path, feature } => write!(
+                f,
+                "{:?}: {} config support requires the `{}` feature",
+                path,
+                feature.to_ascii_uppercase(),
+                feature
+            ),
+            RairError::BadGlob { glob, .. } => write!(f, "bad glob: {}", glob),
+            RairError::InvalidConfig(msg) => f.write_str(msg),
+            RairError::CurrentDir(_) => f.write_str("cwd"),
+            RairError::WatchSetup { path, message } => write!(f, "watch {:?}: {}", path, message),
+            RairError::NoWatchPaths => f.write_str("no watch paths exist"),
+            RairError::BuildSpawn { program, .. } => write!(f, "spawn {:?}", program),
+            RairError::Metadata(_) => f.write_str("cargo metadata"),
+        }
+    }
+}
+
+impl std::error::Error for RairError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RairError::ConfigRead { source, .. } | RairError::BuildSpawn { source, .. } => {
+                Some(source)
+            }
+            RairError::ConfigParse { source, .. } => Some(source.as_ref()),
+            RairError::BadGlob { source, .. } => Some(source),
+            RairError::CurrentDir(e) => Some(e),
+            RairError::Metadata(e) => Some(e),
+            RairError::UnsupportedFormat { .. }
+            | RairError::InvalidConfig(_)
+            | RairError::WatchSetup { .. }
+            | RairError::NoWatchPaths => None,
+        }
+    }
+}
+
+/// `anyhow::ensure!` for config validation, failing with [`RairError::InvalidConfig`].
+macro_rules! ensure_config {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err(RairError::InvalidConfig(format!($($arg)+)));
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
 pub struct Config {
-    pub watch: Option<Vec<String>>,
+    /// Another config file (relative to this one) loaded first, with this file merged over it.
+    /// Resolved by `load_config`, so it's always None afterwards.
+    pub extends: Option<String>,
+    pub watch: Option<Vec<WatchEntry>>,
     pub ignore: Option<Vec<String>>,
+    /// "glob" (default): only `ignore` globs and `.rairignore`. "ignore": also every
+    /// `.gitignore` and `.ignore` below the root, `.git/info/exclude` and the global gitignore.
+    pub ignore_engine: Option<String>,
+    /// Extensions that trigger a rebuild. On the command line they add to the list.
     pub include_ext: Option<Vec<String>>,
+    /// Extensions that never trigger a rebuild. On the command line they add to the list.
     pub exclude_ext: Option<Vec<String>>,
+    /// Skip editor swap/backup/atomic-save files (`*.swp`, `4913`, `*~`, ...). Default: true.
+    pub ignore_editor_temp: Option<bool>,
+    /// React to hidden files and directories (`.config/`, `.env.toml`); `.git` stays ignored.
+    pub include_hidden: Option<bool>,
+    /// Also watch the targets of symlinked directories found under the watch paths.
+    pub follow_symlinks: Option<bool>,
+    /// Replaces the include list entirely (override semantics for `include_ext`).
+    pub only_ext: Option<Vec<String>>,
+    /// Globs matched against the full path that also trigger a rebuild (e.g. `**/*.proto`).
+    pub trigger_globs: Option<Vec<String>>,
+    /// Globs for paths that always rebuild, whatever the extension, hidden and editor-temp
+    /// filters say (`ignore` still applies). A leading `/` anchors to the project root.
+    pub always_rebuild: Option<Vec<String>>,
+    /// Which changes trigger a rebuild: "create", "modify", "remove", "rename". Default: all.
+    pub events: Option<Vec<String>>,
+    /// Treat metadata-only changes (permissions, timestamps) as modifications. Default: false.
+    pub watch_metadata: Option<bool>,
     pub debounce_ms: Option<u64>,
+    /// "leading" (default: build on the first change of a burst), "trailing" (build once
+    /// changes stop for `debounce_ms`) or "both" (build on the first, and again at the end
+    /// if more changes came).
+    pub debounce_mode: Option<String>,
+    /// This many changes arriving together (a checkout or pull) are built once, after they
+    /// settle, with a one-line digest. Default: 50; 0 turns it off.
+    pub burst_threshold: Option<usize>,
+    /// Log filter decisions and other debugging detail.
+    pub verbose: Option<bool>,
+    /// Log how long each phase of a cycle took (also on with `verbose`).
+    pub timings: Option<bool>,
+    /// chrono format string for log timestamps (default `%Y-%m-%d %H:%M:%S`).
+    pub timestamp_format: Option<String>,
+    /// Stamp log lines in UTC instead of local time.
+    pub timestamp_utc: Option<bool>,
+    /// Ignore a path that already triggered within this many ms (separate from debounce).
+    pub per_path_cooldown_ms: Option<u64>,
+    /// Start file-triggered builds at most once per this many ms; changes in between are
+    /// built when it's up.
+    pub min_build_interval_ms: Option<u64>,
+    /// Only react to files tracked by git (`git ls-files`).
+    pub git_tracked_only: Option<bool>,
+    /// With `git_tracked_only`, also react to untracked files that aren't gitignored.
+    pub git_include_untracked: Option<bool>,
     pub clear: Option<bool>,
+    /// Clear the screen before each build so a failing build's errors start on a clean screen.
+    pub clear_on_fail: Option<bool>,
+    /// When `clear` clears: "run" (default, right before the new run starts), "build"
+    /// (before each build, keeping the run's output up until then) or "never".
+    pub clear_before: Option<String>,
+    /// Ring the terminal bell after a build: "off" (default), "on_fail", "on_success" or "always".
+    pub bell: Option<String>,
+    /// Show a single in-place status line instead of per-step log lines (TTY only).
+    pub status_line: Option<bool>,
+    /// Log one summary line per rebuild cycle instead of each step (unless a step fails).
+    pub concise: Option<bool>,
+    /// Exit non-zero on shutdown if the last build failed.
+    pub strict_exit: Option<bool>,
+    /// Skip watch paths that fail to register instead of aborting startup.
+    pub keep_going: Option<bool>,
+    /// How many times in a row the watcher is recreated after it stops. Default: 3.
+    pub watcher_restarts: Option<u32>,
+    /// Unix socket on which a running rair accepts commands (see `rair trigger`).
+    pub control_socket: Option<String>,
+    /// Serve `POST /trigger` on this port, rebuilding like `rair trigger` (needs the
+    /// `http-trigger` feature).
+    pub http_trigger_port: Option<u16>,
+    /// Address for `http_trigger_port` (default `127.0.0.1`).
+    pub http_trigger_host: Option<String>,
+    /// Require `Authorization: Bearer <token>` on `/trigger`; also RAIR_HTTP_TRIGGER_TOKEN.
+    pub http_trigger_token: Option<String>,
+    /// Read key commands from stdin (`p` + Enter pauses/resumes watching); the run process
+    /// gets no stdin.
+    pub interactive: Option<bool>,
+    /// Sentinel file; touching it rebuilds immediately, bypassing debounce and filters.
+    pub trigger_file: Option<String>,
+    /// Re-read the config file when it changes instead of rebuilding. Default: true.
+    pub reload_config_on_change: Option<bool>,
+    /// Hold build output back and show it (after clearing) only if the build succeeds.
+    pub buffer_output: Option<bool>,
+    /// Log a repeated, identical build failure as one line instead of the full output.
+    pub collapse_repeated_errors: Option<bool>,
+    /// Show only the last N lines of a failed build's output. Default: all of it.
+    pub fail_tail_lines: Option<usize>,
+    /// Also watch the files build scripts list with `cargo:rerun-if-changed`.
+    pub watch_build_script_inputs: Option<bool>,
+    /// Log the binaries and libraries each successful build produced.
+    pub list_artifacts: Option<bool>,
+    /// Build with cargo's JSON diagnostics and log `build ok (N warnings)` after a
+    /// successful build.
+    pub parse_diagnostics: Option<bool>,
+    /// Run the process on a pseudo-terminal so it keeps its colours while rair reads its
+    /// output (requires the `pty` feature, unix only).
+    pub pty: Option<bool>,
+    /// Periodically log the run process's CPU and memory (requires the `monitor` feature).
+    pub monitor: Option<bool>,
+    pub monitor_interval_ms: Option<u64>,
+    /// Periodically log watcher event counts and why events were filtered out.
+    pub watch_stats: Option<bool>,
+    pub watch_stats_interval_ms: Option<u64>,
+    /// Log a notice at startup when crates.io has a newer rair (requires the `update-check` feature).
+    pub check_updates: Option<bool>,
 
     /// Optional explicit build argv; if omitted, derived from cargo flags.
     pub build: Option<Vec<String>>,
+    /// Working directory for the build; defaults to rair's cwd.
+    pub build_cwd: Option<String>,
 
     /// Optional explicit run argv; if omitted, rair runs the built binary via cargo metadata.
     pub run: Option<Vec<String>>,
+    /// Arguments appended to the run argv, explicit or derived (`rair -- <args>`).
+    pub run_args: Option<Vec<String>>,
+
+    /// Working directory for the run process; defaults to rair's cwd.
+    pub run_cwd: Option<String>,
+    /// `RUST_BACKTRACE` for the run process: "0", "1" or "full". Unset: inherited.
+    pub backtrace: Option<String>,
+    /// The run process's stdin: "inherit", "null" or "pipe" (open, but never written).
+    /// Default: "inherit", or "null" with `interactive`.
+    pub run_stdin: Option<String>,
+    /// Variables for the run process, relative to the project root. Default: `.env` if
+    /// present; "" for none.
+    pub env_file: Option<String>,
+    /// Build (and run) once, then exit with the run's exit code instead of watching.
+    pub once: Option<bool>,
+    /// With `once`, stop after `post_build` and exit with the build's status.
+    pub build_only: Option<bool>,
+    /// Build (and run the hooks) on startup, but start the run process only after the
+    /// first change.
+    pub defer_run: Option<bool>,
+    /// Build once on startup, without hooks or the run process, before watching starts,
+    /// to fill cargo's incremental cache.
+    pub warm: Option<bool>,
+    /// Keep building on every change but never start a run process; `run` is ignored.
+    pub no_run: Option<bool>,
+    /// Kill the run process (in once-mode) or the tests (in test mode) after this long.
+    pub run_timeout_ms: Option<u64>,
+    /// Retry a failed first build this many times before waiting for changes.
+    pub initial_build_retries: Option<u32>,
+
+    /// Wrapper prepended to the run argv, e.g. `["gdb", "--args"]` or `["valgrind"]`.
+    pub runner: Option<Vec<String>>,
+
+    /// Start the new process (and wait for `health_check`/`ready_port`) before stopping the old one.
+    pub overlap_restart: Option<bool>,
+    /// Restart the run process when it exits on its own.
+    pub restart_on_exit: Option<bool>,
+    /// What a failed build does to the run process: "keep" (default) it running, "stop" it,
+    /// or "restart_last_good" to start the last successfully built binary afresh.
+    pub on_fail_action: Option<String>,
+    /// With `restart_on_exit`, wait this long after an exit before restarting. Default: 1000.
+    pub crash_cooldown_ms: Option<u64>,
+    /// Skip saves that don't change a file's contents, and keep the run process when a
+    /// build produces the same binary (see `restart_decision`).
+    pub skip_unchanged: Option<bool>,
+    /// With `skip_unchanged`, still run post_build/post_run/post_ready when a build
+    /// produces the binary that is already running. Default: false.
+    pub hooks_on_noop: Option<bool>,
+
+    /// What to do on change: "run" (default: build then run the binary), "bench", "test"
+    /// or "doc".
+    pub mode: Option<String>,
+    /// What runs the tests in test mode: "cargo-test" (default) or "nextest"
+    /// (`cargo nextest run`).
+    pub test_runner: Option<String>,
 
     // Cargo-related options
+    /// Set to false for non-Rust projects: no cargo calls, `build` and `run` must be explicit.
+    pub cargo: Option<bool>,
     pub manifest_path: Option<String>,
+    /// Manifests of other crates developed alongside this one (a `Cargo.toml` or its
+    /// directory). They and their `Cargo.lock` are watched and always relevant.
+    pub extra_manifests: Option<Vec<String>>,
+    /// Cargo `--target-dir`; also where rair looks for the built binary (skips cargo metadata).
+    pub target_dir: Option<String>,
     pub package: Option<String>,
+    /// Binary to build and run. A glob (`"worker-*"`) builds every matching binary target.
     pub bin: Option<String>,
     pub features: Option<Vec<String>>,
     pub all_features: Option<bool>,
     pub no_default_features: Option<bool>,
     pub workspace: Option<bool>,
     pub release: Option<bool>,
+    /// Pass `--locked` to the derived cargo commands.
+    pub locked: Option<bool>,
+    /// Pass `--frozen` to the derived cargo commands.
+    pub frozen: Option<bool>,
+    /// Pass `--offline` to the derived cargo commands.
+    pub offline: Option<bool>,
+    /// Extra cargo flags (e.g. `--offline`, `--jobs 4`) appended to a derived build argv.
+    pub cargo_extra_args: Option<Vec<String>>,
+    /// Add every workspace member's `src` and `Cargo.toml` to the watch list.
+    pub watch_workspace: Option<bool>,
+    /// Bench target for `mode = "bench"` (`cargo bench --bench <name>`).
+    pub bench: Option<String>,
+    /// Document dependencies too in `mode = "doc"` (by default it passes `--no-deps`).
+    pub doc_deps: Option<bool>,
+    /// Extra `cargo doc` flags for `mode = "doc"`, e.g. `--document-private-items`.
+    pub doc_args: Option<Vec<String>>,
+    /// In `mode = "doc"`, open the docs in the browser after the first successful build.
+    pub open_docs: Option<bool>,
+    /// Run `cargo fmt --check` after `pre_build`; unformatted code skips the build.
+    pub fmt_check: Option<bool>,
+    /// Run `cargo clippy` after `pre_build`; any warning skips the build.
+    pub clippy: Option<bool>,
+
+    // Hooks: list of argv commands or `{ parallel = [...] }` groups
+    pub pre_build: Option<Vec<Hook>>,
+    pub post_build: Option<Vec<Hook>>,
+    pub pre_run: Option<Vec<Hook>>,
+    pub post_run: Option<Vec<Hook>>,
+    pub on_build_fail: Option<Vec<Hook>>,
+    /// Runs once the run process is ready (see `health_check`, `ready_port`), while it keeps running.
+    pub post_ready: Option<Vec<Hook>>,
+
+    /// Command polled after starting the run process; exit 0 means ready.
+    pub health_check: Option<Vec<String>>,
+    /// TCP port probed after starting the run process; accepting a connection means ready.
+    pub ready_port: Option<u16>,
+    /// Host for `ready_port` (default `127.0.0.1`).
+    pub ready_host: Option<String>,
+    pub health_interval_ms: Option<u64>,
+    pub health_retries: Option<u32>,
 
-    // Hooks: list of argv commands (each command is Vec<String>)
-    pub pre_build: Option<Vec<Vec<String>>>,
-    pub post_build: Option<Vec<Vec<String>>>,
-    pub pre_run: Option<Vec<Vec<String>>>,
-    pub post_run: Option<Vec<Vec<String>>>,
-    pub on_build_fail: Option<Vec<Vec<String>>>,
+    /// Independent watch+build+run pipelines (`[[services]]`), e.g. a frontend dev server.
+    pub services: Option<Vec<ServiceConfig>>,
+
+    /// Files copied to an output directory when they change (`[[asset_sync]]`); a change
+    /// that only touches them restarts the run process without a build.
+    pub asset_sync: Option<Vec<AssetSyncConfig>>,
+
+    /// Commands run when an input matching their glob changes (`[[transform]]`), e.g. to
+    /// extract generated sources; the files they write then trigger the rebuild.
+    pub transform: Option<Vec<TransformConfig>>,
 }
 
-#[derive(Debug, Clone)]
+/// A `watch` entry: a path, or `{ path = "config", recursive = false }` to watch a
+/// directory without its subdirectories.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum WatchEntry {
+    Path(String),
+    Table {
+        path: String,
+        /// Default: true.
+        recursive: Option<bool>,
+    },
+}
+
+impl WatchEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            WatchEntry::Path(path) | WatchEntry::Table { path, .. } => path,
+        }
+    }
+
+    pub fn recursive(&self) -> bool {
+        match self {
+            WatchEntry::Path(_) => true,
+            WatchEntry::Table { recursive, .. } => recursive.unwrap_or(true),
+        }
+    }
+}
+
+impl From<&str> for WatchEntry {
+    fn from(path: &str) -> Self {
+        WatchEntry::Path(path.to_string())
+    }
+}
+
+impl From<String> for WatchEntry {
+    fn from(path: String) -> Self {
+        WatchEntry::Path(path)
+    }
+}
+
+/// One `[[services]]` entry: a separate command with its own watch paths and rebuilds.
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+pub struct ServiceConfig {
+    /// Shown in front of the service's log lines.
+    pub name: String,
+    pub watch: Vec<String>,
+    /// Extensions that trigger a rebuild; any file does if omitted.
+    pub include_ext: Option<Vec<String>>,
+    /// Ignore globs on top of the top-level `ignore`.
+    pub ignore: Option<Vec<String>>,
+    pub build: Option<Vec<String>>,
+    pub run: Option<Vec<String>>,
+    /// Working directory for `build` and `run`.
+    pub cwd: Option<String>,
+    /// Debounce for this service; the top-level `debounce_ms` if omitted. A `--debounce-ms`
+    /// given on the command line wins over both.
+    pub debounce_ms: Option<u64>,
+}
+
+/// One `[[asset_sync]]` entry: files matching `glob` (relative to the project root) are
+/// copied under `dest`, keeping their path below the glob's leading directories.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AssetSyncConfig {
+    pub glob: String,
+    pub dest: String,
+}
+
+/// One `[[transform]]` entry: `run` is run whenever a file matching `glob` (relative to
+/// the project root) changes, before the change goes through the relevance filters.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TransformConfig {
+    pub glob: String,
+    pub run: Vec<String>,
+}
+
+/// One entry in a hook list.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Hook {
+    /// A single argv command.
+    Command(Vec<String>),
+    /// Commands started together; the group fails if any of them fails.
+    Parallel { parallel: Vec<Vec<String>> },
+}
+
+impl From<Vec<String>> for Hook {
+    fn from(argv: Vec<String>) -> Self {
+        Hook::Command(argv)
+    }
+}
+
+/// Placeholder in `post_build` hooks for the built binary's path.
+pub const EXE_PLACEHOLDER: &str = "{exe}";
+
+impl Hook {
+    fn argvs(&self) -> impl Iterator<Item = &Vec<String>> {
+        match self {
+            Hook::Command(argv) => std::slice::from_ref(argv).iter(),
+            Hook::Parallel { parallel } => parallel.iter(),
+        }
+    }
+
+    /// Whether any argument contains `placeholder`.
+    pub fn uses(&self, placeholder: &str) -> bool {
+        self.argvs().flatten().any(|a| a.contains(placeholder))
+    }
+
+    /// This hook with `placeholder` replaced by `value` in every argument.
+    pub fn expand(&self, placeholder: &str, value: &str) -> Hook {
+        let expand = |argv: &Vec<String>| -> Vec<String> {
+            argv.iter().map(|a| a.replace(placeholder, value)).collect()
+        };
+        match self {
+            Hook::Command(argv) => Hook::Command(expand(argv)),
+            Hook::Parallel { parallel } => Hook::Parallel {
+                parallel: parallel.iter().map(expand).collect(),
+            },
+        }
+    }
+}
+
+/// Placeholders in the `build` argv, filled in before each build (see `expand_build_argv`).
+pub const CHANGED_PLACEHOLDER: &str = "{changed}";
+pub const CHANGED_LIST_PLACEHOLDER: &str = "{changed_list}";
+pub const PROJECT_ROOT_PLACEHOLDER: &str = "{project_root}";
+
+/// The build argv for a build triggered by `changed`. An argument that is exactly
+/// `{changed}` becomes one argument per path (none on startup or a manual trigger);
+/// `{changed_list}` elsewhere is replaced by the paths joined with spaces, and
+/// `{project_root}` by the project root. A derived cargo build has no placeholders.
+pub fn expand_build_argv(argv: &[String], changed: &[PathBuf], project_root: &Path) -> Vec<String> {
+    let paths: Vec<String> = changed
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    let list = paths.join(" ");
+    let root = project_root.to_string_lossy();
+    let mut out = Vec::with_capacity(argv.len());
+    for a in argv {
+        if a == CHANGED_PLACEHOLDER {
+            out.extend(paths.iter().cloned());
+            continue;
+        }
+        out.push(
+            a.replace(CHANGED_LIST_PLACEHOLDER, &list)
+                .replace(PROJECT_ROOT_PLACEHOLDER, &root),
+        );
+    }
+    out
+}
+
+impl Config {
+    /// Reads `RAIR_*` environment variables (e.g. `RAIR_DEBOUNCE_MS`, `RAIR_WATCH=src,tests`).
+    pub fn from_env() -> Result<Config> {
+        env_config(std::env::vars())
+    }
+}
+
+fn parse_env_bool(key: &str, v: &str) -> Result<bool> {
+    match v.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => anyhow::bail!("{}: expected a boolean, got {:?}", key, v),
+    }
+}
+
+fn parse_env_list(v: &str) -> Vec<String> {
+    v.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Builds a `Config` from `RAIR_*` variables. Lists are comma-separated and
+/// `RAIR_BUILD`/`RAIR_RUN` argv are whitespace-separated. Unknown keys are ignored.
+pub fn env_config<I>(vars: I) -> Result<Config>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut cfg = Config::default();
+    for (key, v) in vars {
+        let Some(name) = key.strip_prefix("RAIR_") else {
+            continue;
+        };
+        let argv = || v.split_whitespace().map(String::from).collect::<Vec<_>>();
+        match name {
+            "WATCH" => {
+                cfg.watch = Some(
+                    parse_env_list(&v)
+                        .into_iter()
+                        .map(WatchEntry::from)
+                        .collect(),
+                )
+            }
+            "IGNORE" => cfg.ignore = Some(parse_env_list(&v)),
+            "INCLUDE_EXT" => cfg.include_ext = Some(parse_env_list(&v)),
+            "EXCLUDE_EXT" => cfg.exclude_ext = Some(parse_env_list(&v)),
+            "ONLY_EXT" => cfg.only_ext = Some(parse_env_list(&v)),
+            "DEBOUNCE_MS" => {
+                cfg.debounce_ms = Some(
+                    v.trim()
+                        .parse()
+                        .with_context(|| format!("{}: expected milliseconds, got {:?}", key, v))?,
+                )
+            }
+            "CLEAR" => cfg.clear = Some(parse_env_bool(&key, &v)?),
+            "TIMESTAMP_FORMAT" => cfg.timestamp_format = Some(v),
+            "TIMESTAMP_UTC" => cfg.timestamp_utc = Some(parse_env_bool(&key, &v)?),
+            "BUILD" => cfg.build = Some(argv()),
+            "RUN" => cfg.run = Some(argv()),
+            "BUILD_CWD" => cfg.build_cwd = Some(v),
+            "RUN_CWD" => cfg.run_cwd = Some(v),
+            "MODE" => cfg.mode = Some(v),
+            "CARGO" => cfg.cargo = Some(parse_env_bool(&key, &v)?),
+            "MANIFEST_PATH" => cfg.manifest_path = Some(v),
+            "TARGET_DIR" => cfg.target_dir = Some(v),
+            "PACKAGE" => cfg.package = Some(v),
+            "BIN" => cfg.bin = Some(v),
+            "FEATURES" => cfg.features = Some(parse_env_list(&v)),
+            "ALL_FEATURES" => cfg.all_features = Some(parse_env_bool(&key, &v)?),
+            "NO_DEFAULT_FEATURES" => cfg.no_default_features = Some(parse_env_bool(&key, &v)?),
+            "WORKSPACE" => cfg.workspace = Some(parse_env_bool(&key, &v)?),
+            "RELEASE" => cfg.release = Some(parse_env_bool(&key, &v)?),
+            "LOCKED" => cfg.locked = Some(parse_env_bool(&key, &v)?),
+            "FROZEN" => cfg.frozen = Some(parse_env_bool(&key, &v)?),
+            "OFFLINE" => cfg.offline = Some(parse_env_bool(&key, &v)?),
+            "CARGO_EXTRA_ARGS" => cfg.cargo_extra_args = Some(argv()),
+            "BENCH" => cfg.bench = Some(v),
+            "HTTP_TRIGGER_TOKEN" => cfg.http_trigger_token = Some(v),
+            _ => {}
+        }
+    }
+    Ok(cfg)
+}
+
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How log lines are stamped (`timestamp_format`, `timestamp_utc`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TimestampFormat {
+    format: String,
+    utc: bool,
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat {
+            format: DEFAULT_TIMESTAMP_FORMAT.to_string(),
+            utc: false,
+        }
+    }
+}
+
+impl TimestampFormat {
+    /// Errors if `format` isn't a valid chrono format string.
+    pub fn new(format: &str, utc: bool) -> Result<Self, RairError> {
+        use std::fmt::Write;
+
+        let mut probe = String::new();
+        write!(probe, "{}", chrono::Utc::now().format(format)).map_err(|_| {
+            RairError::InvalidConfig(format!("invalid timestamp_format: {:?}", format))
+        })?;
+        Ok(TimestampFormat {
+            format: format.to_string(),
+            utc,
+        })
+    }
+
+    /// `t` formatted in UTC or local time, as configured.
+    pub fn format(&self, t: chrono::DateTime<chrono::Utc>) -> String {
+        if self.utc {
+            t.format(&self.format).to_string()
+        } else {
+            t.with_timezone(&chrono::Local)
+                .format(&self.format)
+                .to_string()
+        }
+    }
+
+    pub fn now(&self) -> String {
+        self.format(chrono::Utc::now())
+    }
+}
+
+/// What rair does with each change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Build, then (re)start the binary.
+    #[default]
+    Run,
+    /// Run `cargo bench`; there is no run step.
+    Bench,
+    /// Run `cargo test`; there is no run step.
+    Test,
+    /// Run `cargo doc`; there is no run step.
+    Doc,
+}
+
+impl Mode {
+    pub fn parse(s: &str) -> Result<Mode, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "run" => Ok(Mode::Run),
+            "bench" => Ok(Mode::Bench),
+            "test" => Ok(Mode::Test),
+            "doc" => Ok(Mode::Doc),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown mode {:?} (expected \"run\", \"bench\", \"test\" or \"doc\")",
+                other
+            ))),
+        }
+    }
+
+    /// The cargo subcommand used to derive the build argv.
+    pub fn cargo_subcommand(self) -> &'static [&'static str] {
+        match self {
+            Mode::Run => &["build"],
+            Mode::Bench => &["bench"],
+            Mode::Test => &["test"],
+            Mode::Doc => &["doc"],
+        }
+    }
+
+    /// Whether a successful build is followed by starting the run process.
+    pub fn has_run_step(self) -> bool {
+        self == Mode::Run
+    }
+}
+
+/// What runs the tests in test mode (`test_runner`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestRunner {
+    #[default]
+    CargoTest,
+    /// `cargo nextest run`, from the cargo-nextest plugin.
+    Nextest,
+}
+
+impl TestRunner {
+    pub fn parse(s: &str) -> Result<TestRunner, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cargo-test" => Ok(TestRunner::CargoTest),
+            "nextest" => Ok(TestRunner::Nextest),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown test_runner {:?} (expected \"cargo-test\" or \"nextest\")",
+                other
+            ))),
+        }
+    }
+
+    /// The cargo subcommand for test mode; nextest's needs its own `run`.
+    pub fn cargo_subcommand(self) -> &'static [&'static str] {
+        match self {
+            TestRunner::CargoTest => &["test"],
+            TestRunner::Nextest => &["nextest", "run"],
+        }
+    }
+}
+
+/// The resolved configuration. Serializing it (`--print-config`) leaves out the compiled
+/// matchers; `ignore_globs` and `trigger_globs` stand in for them.
+#[derive(Debug, Clone, Serialize)]
 pub struct EffectiveConfig {
     pub watch: Vec<PathBuf>,
+    /// `watch` entries with `recursive = false`.
+    pub non_recursive: Vec<PathBuf>,
     pub ignore_globs: Vec<String>,
+    #[serde(skip)]
     pub ignore_set: GlobSet,
+    /// Patterns from `.rairignore` at the project root, if present.
+    #[serde(skip)]
+    pub rairignore: Option<Gitignore>,
+    pub ignore_engine: IgnoreEngine,
+    /// The gitignore hierarchy, loaded with `ignore_engine = "ignore"`.
+    #[serde(skip)]
+    pub ignore_files: Option<IgnoreFiles>,
+    /// The directory rair runs in; hidden paths are judged relative to it.
+    pub root: PathBuf,
+    /// `Cargo.toml` and `Cargo.lock` of each `extra_manifests` entry, canonicalized.
+    #[serde(serialize_with = "serialize_sorted")]
+    pub extra_manifests: HashSet<PathBuf>,
 
+    #[serde(serialize_with = "serialize_sorted")]
     pub include_ext: HashSet<String>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub exclude_ext: HashSet<String>,
+    pub trigger_globs: Vec<String>,
+    #[serde(skip)]
+    pub trigger_set: GlobSet,
+    pub always_rebuild: Vec<String>,
+    #[serde(skip)]
+    pub always_rebuild_set: GlobSet,
+    #[serde(serialize_with = "serialize_sorted")]
+    pub events: HashSet<EventOp>,
+    pub watch_metadata: bool,
+    pub ignore_editor_temp: bool,
+    pub include_hidden: bool,
+    pub follow_symlinks: bool,
 
     pub debounce: Duration,
+    pub debounce_mode: DebounceMode,
+    /// None with `burst_threshold = 0`.
+    pub burst_threshold: Option<usize>,
+    pub verbose: bool,
+    pub timings: bool,
+    pub timestamp: TimestampFormat,
+    pub per_path_cooldown: Option<Duration>,
+    pub min_build_interval: Option<Duration>,
+    pub git_tracked_only: bool,
+    pub git_include_untracked: bool,
     pub clear: bool,
+    pub clear_on_fail: bool,
+    pub clear_before: ClearBefore,
+    pub bell: Bell,
+    pub status_line: bool,
+    pub concise: bool,
+    pub strict_exit: bool,
+    pub keep_going: bool,
+    pub watcher_restarts: u32,
+    pub control_socket: Option<PathBuf>,
+    pub http_trigger_port: Option<u16>,
+    pub http_trigger_host: String,
+    #[serde(skip)]
+    pub http_trigger_token: Option<String>,
+    pub interactive: bool,
+    /// Absolute path of `trigger_file`.
+    pub trigger_file: Option<PathBuf>,
+    pub reload_config_on_change: bool,
+    pub buffer_output: bool,
+    pub collapse_repeated_errors: bool,
+    pub fail_tail_lines: Option<usize>,
+    pub watch_build_script_inputs: bool,
+    pub list_artifacts: bool,
+    pub parse_diagnostics: bool,
+    pub pty: bool,
+    pub monitor: bool,
+    pub monitor_interval: Duration,
+    pub watch_stats: bool,
+    pub watch_stats_interval: Duration,
+    pub check_updates: bool,
 
     /// Build argv (always present)
     pub build: Vec<String>,
+    /// Working directory for the build (validated to exist).
+    pub build_cwd: Option<PathBuf>,
 
     /// Optional explicit run argv; if None => run built binary via metadata.
     pub run: Option<Vec<String>>,
+    pub run_args: Vec<String>,
+
+    /// Working directory for the run process (validated to exist).
+    pub run_cwd: Option<PathBuf>,
+    pub backtrace: Option<String>,
+    pub run_stdin: RunStdin,
+    /// Read again for every start of the run process; changing it restarts the process.
+    pub env_file: Option<PathBuf>,
+    pub once: bool,
+    pub build_only: bool,
+    pub defer_run: bool,
+    pub warm: bool,
+    pub no_run: bool,
+    pub run_timeout: Option<Duration>,
+    pub initial_build_retries: u32,
+    /// Wrapper prepended to the run argv (not applied to hooks).
+    pub runner: Vec<String>,
+    pub overlap_restart: bool,
+    pub restart_on_exit: bool,
+    pub on_fail_action: OnFailAction,
+    pub crash_cooldown: Duration,
+    pub skip_unchanged: bool,
+    pub hooks_on_noop: bool,
+
+    pub mode: Mode,
+    pub test_runner: TestRunner,
 
     // Cargo selection
+    pub cargo: bool,
     pub manifest_path: Option<PathBuf>,
+    pub target_dir: Option<PathBuf>,
     pub package: Option<String>,
     pub bin: Option<String>,
+    /// The binary targets a `bin` glob matched; empty for a plain name.
+    pub bins: Vec<String>,
     pub features: Vec<String>,
     pub all_features: bool,
     pub no_default_features: bool,
     pub workspace: bool,
     pub release: bool,
+    pub locked: bool,
+    pub frozen: bool,
+    pub offline: bool,
+    pub cargo_extra_args: Vec<String>,
+    pub watch_workspace: bool,
+    pub bench: Option<String>,
+    pub doc_deps: bool,
+    pub doc_args: Vec<String>,
+    pub open_docs: bool,
+    pub fmt_check: bool,
+    pub clippy: bool,
 
     // Hooks
-    pub pre_build: Vec<Vec<String>>,
-    pub post_build: Vec<Vec<String>>,
-    pub pre_run: Vec<Vec<String>>,
-    pub post_run: Vec<Vec<String>>,
-    pub on_build_fail: Vec<Vec<String>>,
+    pub pre_build: Vec<Hook>,
+    pub post_build: Vec<Hook>,
+    pub pre_run: Vec<Hook>,
+    pub post_run: Vec<Hook>,
+    pub on_build_fail: Vec<Hook>,
+    pub post_ready: Vec<Hook>,
+
+    // Readiness
+    pub health_check: Option<Vec<String>>,
+    pub ready_port: Option<u16>,
+    pub ready_host: String,
+    pub health_interval: Duration,
+    pub health_retries: u32,
+
+    pub services: Vec<Service>,
+    pub asset_sync: Vec<AssetSync>,
+    pub transform: Vec<Transform>,
+}
+
+/// Sets are written sorted so `--print-config` output is stable.
+fn serialize_sorted<T: Ord + Serialize, S: serde::Serializer>(
+    set: &HashSet<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut items: Vec<&T> = set.iter().collect();
+    items.sort();
+    serializer.collect_seq(items)
+}
+
+/// A resolved `[[services]]` entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct Service {
+    pub name: String,
+    pub watch: Vec<PathBuf>,
+    /// Empty means every extension.
+    #[serde(serialize_with = "serialize_sorted")]
+    pub include_ext: HashSet<String>,
+    #[serde(skip)]
+    pub ignore_set: GlobSet,
+    pub build: Option<Vec<String>>,
+    pub run: Option<Vec<String>>,
+    pub cwd: Option<PathBuf>,
+    pub debounce: Duration,
+}
+
+impl Service {
+    /// Returns true if a change to `path` should rebuild this service. The top-level
+    /// ignore rules, editor temp and hidden file handling apply as well.
+    pub fn is_relevant(&self, path: &Path, eff: &EffectiveConfig) -> bool {
+        if eff.is_ignored(path) || self.ignore_set.is_match(path) {
+            return false;
+        }
+        if eff.ignore_editor_temp && is_editor_temp(path) {
+            return false;
+        }
+        if path.components().any(|c| c.as_os_str() == ".git")
+            || (!eff.include_hidden && is_hidden(path, &eff.root))
+        {
+            return false;
+        }
+        self.include_ext.is_empty()
+            || path
+                .extension()
+                .is_some_and(|x| self.include_ext.contains(&norm_ext(&x.to_string_lossy())))
+    }
+}
+
+/// Resolves the `[[services]]` entries. Each one's debounce is `cli_debounce_ms` if set,
+/// else its own `debounce_ms`, else `default_debounce` (the top-level one).
+fn resolve_services(
+    services: Vec<ServiceConfig>,
+    root: &Path,
+    cli_debounce_ms: Option<u64>,
+    default_debounce: Duration,
+) -> Result<Vec<Service>, RairError> {
+    let mut names = HashSet::new();
+    services
+        .into_iter()
+        .map(|s| {
+            ensure_config!(!s.name.trim().is_empty(), "service without a name");
+            ensure_config!(
+                names.insert(s.name.clone()),
+                "duplicate service name: {:?}",
+                s.name
+            );
+            ensure_config!(
+                !s.watch.is_empty(),
+                "service {:?}: `watch` is required",
+                s.name
+            );
+            ensure_config!(
+                s.build.is_some() || s.run.is_some(),
+                "service {:?}: needs a `build` or `run` command",
+                s.name
+            );
+            let cwd = s.cwd.map(PathBuf::from);
+            if let Some(dir) = &cwd {
+                ensure_config!(
+                    dir.is_dir(),
+                    "service {:?}: cwd is not a directory: {:?}",
+                    s.name,
+                    dir
+                );
+            }
+            let ignore = s.ignore.unwrap_or_default();
+            Ok(Service {
+                watch: s.watch.into_iter().map(PathBuf::from).collect(),
+                include_ext: s
+                    .include_ext
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|e| norm_ext(e))
+                    .collect(),
+                ignore_set: build_globset(&anchor_globs(&ignore, root))?,
+                build: s.build,
+                run: s.run,
+                cwd,
+                debounce: cli_debounce_ms
+                    .or(s.debounce_ms)
+                    .map_or(default_debounce, Duration::from_millis),
+                name: s.name,
+            })
+        })
+        .collect()
+}
+
+/// Loads a config file, picking the format from its extension.
+/// `.json` and `.yaml`/`.yml` require the `json`/`yaml` features; anything else is TOML.
+/// A file that `extends` another is merged over it (and that one over its own base).
+pub fn load_config(path: &Path) -> Result<Config, RairError> {
+    // canonical paths, so `a/../b.toml` and `b.toml` are seen as the same file
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| absolute_path(p));
+    let mut chain = vec![canonical(path)];
+    let mut cfg = parse_config_file(path)?;
+    while let Some(base) = cfg.extends.take() {
+        let from = chain.last().unwrap();
+        let base = canonical(&from.parent().unwrap_or(Path::new(".")).join(base));
+        if chain.contains(&base) {
+            chain.push(base);
+            let cycle: Vec<_> = chain.iter().map(|p| p.display().to_string()).collect();
+            return Err(RairError::InvalidConfig(format!(
+                "config extends itself: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        // the merged config keeps the base's own `extends`, continuing the chain
+        cfg = merge_config(parse_config_file(&base)?, cfg);
+        chain.push(base);
+    }
+    Ok(cfg)
 }
 
-pub fn load_config(path: &Path) -> Result<Config> {
-    let s = std::fs::read_to_string(path).with_context(|| format!("read config {:?}", path))?;
-    let cfg: Config = toml::from_str(&s).with_context(|| format!("parse toml {:?}", path))?;
+fn parse_config_file(path: &Path) -> Result<Config, RairError> {
+    let s = std::fs::read_to_string(path).map_err(|source| RairError::ConfigRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let parse_err = |source: Box<dyn std::error::Error + Send + Sync>| RairError::ConfigParse {
+        path: path.to_path_buf(),
+        source,
+    };
+    let ext = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let cfg: Config = match ext.as_str() {
+        #[cfg(feature = "json")]
+        "json" => serde_json::from_str(&s).map_err(|e| parse_err(e.into()))?,
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => serde_yaml::from_str(&s).map_err(|e| parse_err(e.into()))?,
+        #[cfg(not(feature = "json"))]
+        "json" => {
+            return Err(RairError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                feature: "json",
+            })
+        }
+        #[cfg(not(feature = "yaml"))]
+        "yaml" | "yml" => {
+            return Err(RairError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                feature: "yaml",
+            })
+        }
+        _ => toml::from_str(&s).map_err(|e| parse_err(e.into()))?,
+    };
     Ok(cfg)
 }
 
-pub fn build_globset(globs: &[String]) -> Result<GlobSet> {
+/// Where the effective config came from, so it can be resolved again when the config
+/// file changes (`reload_config_on_change`).
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    cli: Config,
+    /// Absolute path of the config file, if one is used.
+    path: Option<PathBuf>,
+    /// The file's contents as last loaded.
+    file: Config,
+}
+
+impl ConfigSource {
+    pub fn new(cli: Config, path: Option<&Path>, file: Config) -> Self {
+        ConfigSource {
+            cli,
+            path: path.map(absolute_path),
+            file,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns true if `path` is the config file.
+    pub fn is_config_file(&self, path: &Path) -> bool {
+        self.path
+            .as_deref()
+            .is_some_and(|c| absolute_path(path) == c || same_path(path, c))
+    }
+
+    /// Re-reads the config file and resolves the config again, with the same CLI flags
+    /// and `RAIR_*` variables on top. `Ok(None)` when the file's settings didn't change
+    /// (one save often produces several events).
+    pub fn reload(&mut self) -> Result<Option<EffectiveConfig>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let file = load_config(path)?;
+        if file == self.file {
+            return Ok(None);
+        }
+        let merged = merge_config(file.clone(), Config::from_env()?);
+        let mut eff = effective_config(self.cli.clone(), Some(merged))?;
+        if eff.watch_workspace {
+            let members = workspace_watch_paths(eff.manifest_path.as_deref())?;
+            add_watch_paths(&mut eff.watch, members);
+        }
+        self.file = file;
+        Ok(Some(eff))
+    }
+}
+
+pub fn build_globset(globs: &[String]) -> Result<GlobSet, RairError> {
     let mut b = GlobSetBuilder::new();
     for g in globs {
-        b.add(Glob::new(g).with_context(|| format!("bad glob: {}", g))?);
+        b.add(Glob::new(g).map_err(|source| RairError::BadGlob {
+            glob: g.clone(),
+            source,
+        })?);
+    }
+    b.build().map_err(|source| RairError::BadGlob {
+        glob: globs.join(", "),
+        source,
+    })
+}
+
+/// Rewrites root-anchored patterns (leading `/`, e.g. `/logs/**`) to absolute patterns under
+/// `root`, since watcher paths are absolute. Other patterns are returned unchanged.
+pub fn anchor_globs(globs: &[String], root: &Path) -> Vec<String> {
+    let root = globset::escape(root.to_string_lossy().trim_end_matches('/'));
+    globs
+        .iter()
+        .map(|g| match g.strip_prefix('/') {
+            Some(rest) => format!("{}/{}", root, rest),
+            None => g.clone(),
+        })
+        .collect()
+}
+
+/// Loads `<root>/.rairignore` (gitignore syntax), or None if there isn't one.
+pub fn load_rairignore(root: &Path) -> Result<Option<Gitignore>, RairError> {
+    let path = root.join(".rairignore");
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let parse_err = |e: ignore::Error| RairError::ConfigParse {
+        path: path.clone(),
+        source: e.into(),
+    };
+    let mut b = GitignoreBuilder::new(root);
+    if let Some(e) = b.add(&path) {
+        return Err(parse_err(e));
+    }
+    let gi = b.build().map_err(parse_err)?;
+    Ok(Some(gi))
+}
+
+/// Which ignore rules apply besides the `ignore` globs and `.rairignore` (`ignore_engine`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IgnoreEngine {
+    #[default]
+    Glob,
+    /// The `ignore` crate's gitignore rules, as ripgrep applies them.
+    Ignore,
+}
+
+impl IgnoreEngine {
+    pub fn parse(s: &str) -> Result<IgnoreEngine, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "glob" => Ok(IgnoreEngine::Glob),
+            "ignore" => Ok(IgnoreEngine::Ignore),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown ignore_engine {:?} (expected \"glob\" or \"ignore\")",
+                other
+            ))),
+        }
+    }
+}
+
+/// The gitignore hierarchy under a root: every `.gitignore` and `.ignore` (the latter
+/// taking precedence in the same directory), then `.git/info/exclude`, then the global
+/// gitignore. A deeper file overrides a shallower one, `!` patterns included.
+#[derive(Debug, Clone)]
+pub struct IgnoreFiles {
+    /// Per-directory matchers, deepest first.
+    dirs: Vec<Gitignore>,
+    exclude: Gitignore,
+    global: Gitignore,
+}
+
+impl IgnoreFiles {
+    /// Walks `root` for ignore files, skipping directories they already ignore.
+    /// Files that can't be read or parsed are left out.
+    pub fn scan(root: &Path) -> Self {
+        let build = |dir: &Path, files: &[PathBuf]| {
+            let mut b = GitignoreBuilder::new(dir);
+            for f in files.iter().filter(|f| f.is_file()) {
+                let _ = b.add(f);
+            }
+            b.build().unwrap_or_else(|_| Gitignore::empty())
+        };
+        let mut dirs: Vec<Gitignore> = ignore::WalkBuilder::new(root)
+            .hidden(false)
+            .require_git(false)
+            .filter_entry(|e| e.file_name() != ".git")
+            .build()
+            .flatten()
+            .filter(|e| e.file_type().is_some_and(|t| t.is_dir()))
+            .map(|e| {
+                build(
+                    e.path(),
+                    &[e.path().join(".gitignore"), e.path().join(".ignore")],
+                )
+            })
+            .filter(|gi| !gi.is_empty())
+            .collect();
+        dirs.sort_by_key(|gi| std::cmp::Reverse(gi.path().components().count()));
+        let exclude = build(root, &[root.join(".git").join("info").join("exclude")]);
+        let global = GitignoreBuilder::new(root).build_global().0;
+        IgnoreFiles {
+            dirs,
+            exclude,
+            global,
+        }
     }
-    Ok(b.build()?)
+
+    /// Whether a change to `path` may change these rules.
+    pub fn needs_rescan(path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|n| n == ".gitignore" || n == ".ignore")
+            || path.ends_with(".git/info/exclude")
+    }
+
+    /// The pattern (and the file it's from) that ignores `path`, if any.
+    pub fn matched(&self, path: &Path) -> Option<String> {
+        let is_dir = path.is_dir();
+        let layers = self.dirs.iter().chain([&self.exclude, &self.global]);
+        for gi in layers.filter(|gi| path.starts_with(gi.path())) {
+            match gi.matched_path_or_any_parents(path, is_dir) {
+                ignore::Match::Ignore(glob) => {
+                    let from = glob
+                        .from()
+                        .map_or("the global gitignore".into(), |f| f.display().to_string());
+                    return Some(format!("{} in {}", glob.original(), from));
+                }
+                ignore::Match::Whitelist(_) => return None,
+                ignore::Match::None => {}
+            }
+        }
+        None
+    }
+}
+
+/// Returns the first pattern in `globs` that matches `path`.
+/// `set` must have been built from `globs` (as `ignore_set` is from `ignore_globs`).
+pub fn explain_match(path: &Path, globs: &[String], set: &GlobSet) -> Option<String> {
+    set.matches(path)
+        .into_iter()
+        .min()
+        .and_then(|i| globs.get(i).cloned())
 }
 
-fn merge_config(mut base: Config, overlay: Config) -> Config {
+fn default_include_ext() -> Vec<String> {
+    vec!["rs".into(), "toml".into()]
+}
+
+/// Overlays `overlay` onto `base`: set fields in `overlay` win.
+pub fn merge_config(mut base: Config, overlay: Config) -> Config {
     if overlay.watch.is_some() {
         base.watch = overlay.watch;
     }
     if overlay.ignore.is_some() {
         base.ignore = overlay.ignore;
     }
+    if overlay.ignore_engine.is_some() {
+        base.ignore_engine = overlay.ignore_engine;
+    }
     if overlay.include_ext.is_some() {
         base.include_ext = overlay.include_ext;
     }
     if overlay.exclude_ext.is_some() {
         base.exclude_ext = overlay.exclude_ext;
     }
+    if overlay.only_ext.is_some() {
+        base.only_ext = overlay.only_ext;
+    }
+    if overlay.ignore_editor_temp.is_some() {
+        base.ignore_editor_temp = overlay.ignore_editor_temp;
+    }
+    if overlay.include_hidden.is_some() {
+        base.include_hidden = overlay.include_hidden;
+    }
+    if overlay.follow_symlinks.is_some() {
+        base.follow_symlinks = overlay.follow_symlinks;
+    }
+    if overlay.trigger_globs.is_some() {
+        base.trigger_globs = overlay.trigger_globs;
+    }
+    if overlay.always_rebuild.is_some() {
+        base.always_rebuild = overlay.always_rebuild;
+    }
+    if overlay.events.is_some() {
+        base.events = overlay.events;
+    }
+    if overlay.watch_metadata.is_some() {
+        base.watch_metadata = overlay.watch_metadata;
+    }
     if overlay.debounce_ms.is_some() {
         base.debounce_ms = overlay.debounce_ms;
     }
+    if overlay.debounce_mode.is_some() {
+        base.debounce_mode = overlay.debounce_mode;
+    }
+    if overlay.burst_threshold.is_some() {
+        base.burst_threshold = overlay.burst_threshold;
+    }
+    if overlay.verbose.is_some() {
+        base.verbose = overlay.verbose;
+    }
+    if overlay.timings.is_some() {
+        base.timings = overlay.timings;
+    }
+    if overlay.timestamp_format.is_some() {
+        base.timestamp_format = overlay.timestamp_format;
+    }
+    if overlay.timestamp_utc.is_some() {
+        base.timestamp_utc = overlay.timestamp_utc;
+    }
+    if overlay.per_path_cooldown_ms.is_some() {
+        base.per_path_cooldown_ms = overlay.per_path_cooldown_ms;
+    }
+    if overlay.min_build_interval_ms.is_some() {
+        base.min_build_interval_ms = overlay.min_build_interval_ms;
+    }
+    if overlay.git_tracked_only.is_some() {
+        base.git_tracked_only = overlay.git_tracked_only;
+    }
+    if overlay.git_include_untracked.is_some() {
+        base.git_include_untracked = overlay.git_include_untracked;
+    }
     if overlay.clear.is_some() {
         base.clear = overlay.clear;
     }
-    if overlay.build.is_some() {
-        base.build = overlay.build;
+    if overlay.clear_on_fail.is_some() {
+        base.clear_on_fail = overlay.clear_on_fail;
     }
-    if overlay.run.is_some() {
-        base.run = overlay.run;
+    if overlay.clear_before.is_some() {
+        base.clear_before = overlay.clear_before;
     }
-
-    if overlay.manifest_path.is_some() {
-        base.manifest_path = overlay.manifest_path;
+    if overlay.bell.is_some() {
+        base.bell = overlay.bell;
     }
-    if overlay.package.is_some() {
-        base.package = overlay.package;
+    if overlay.status_line.is_some() {
+        base.status_line = overlay.status_line;
     }
-    if overlay.bin.is_some() {
-        base.bin = overlay.bin;
+    if overlay.concise.is_some() {
+        base.concise = overlay.concise;
     }
-    if overlay.features.is_some() {
-        base.features = overlay.features;
+    if overlay.strict_exit.is_some() {
+        base.strict_exit = overlay.strict_exit;
     }
-    if overlay.all_features.is_some() {
-        base.all_features = overlay.all_features;
+    if overlay.keep_going.is_some() {
+        base.keep_going = overlay.keep_going;
     }
-    if overlay.no_default_features.is_some() {
-        base.no_default_features = overlay.no_default_features;
+    if overlay.watcher_restarts.is_some() {
+        base.watcher_restarts = overlay.watcher_restarts;
     }
-    if overlay.workspace.is_some() {
-        base.workspace = overlay.workspace;
+    if overlay.control_socket.is_some() {
+        base.control_socket = overlay.control_socket;
     }
-    if overlay.release.is_some() {
-        base.release = overlay.release;
+    if overlay.http_trigger_port.is_some() {
+        base.http_trigger_port = overlay.http_trigger_port;
     }
-
-    if overlay.pre_build.is_some() {
-        base.pre_build = overlay.pre_build;
+    if overlay.http_trigger_host.is_some() {
+        base.http_trigger_host = overlay.http_trigger_host;
     }
-    if overlay.post_build.is_some() {
-        base.post_build = overlay.post_build;
+    if overlay.http_trigger_token.is_some() {
+        base.http_trigger_token = overlay.http_trigger_token;
     }
-    if overlay.pre_run.is_some() {
-        base.pre_run = overlay.pre_run;
+    if overlay.interactive.is_some() {
+        base.interactive = overlay.interactive;
     }
-    if overlay.post_run.is_some() {
-        base.post_run = overlay.post_run;
+    if overlay.trigger_file.is_some() {
+        base.trigger_file = overlay.trigger_file;
     }
-    if overlay.on_build_fail.is_some() {
-        base.on_build_fail = overlay.on_build_fail;
+    if overlay.reload_config_on_change.is_some() {
+        base.reload_config_on_change = overlay.reload_config_on_change;
+    }
+    if overlay.buffer_output.is_some() {
+        base.buffer_output = overlay.buffer_output;
+    }
+    if overlay.collapse_repeated_errors.is_some() {
+        base.collapse_repeated_errors = overlay.collapse_repeated_errors;
+    }
+    if overlay.fail_tail_lines.is_some() {
+        base.fail_tail_lines = overlay.fail_tail_lines;
+    }
+    if overlay.watch_build_script_inputs.is_some() {
+        base.watch_build_script_inputs = overlay.watch_build_script_inputs;
+    }
+    if overlay.list_artifacts.is_some() {
+        base.list_artifacts = overlay.list_artifacts;
+    }
+    if overlay.parse_diagnostics.is_some() {
+        base.parse_diagnostics = overlay.parse_diagnostics;
+    }
+    if overlay.pty.is_some() {
+        base.pty = overlay.pty;
+    }
+    if overlay.monitor.is_some() {
+        base.monitor = overlay.monitor;
+    }
+    if overlay.monitor_interval_ms.is_some() {
+        base.monitor_interval_ms = overlay.monitor_interval_ms;
+    }
+    if overlay.watch_stats.is_some() {
+        base.watch_stats = overlay.watch_stats;
+    }
+    if overlay.watch_stats_interval_ms.is_some() {
+        base.watch_stats_interval_ms = overlay.watch_stats_interval_ms;
+    }
+    if overlay.check_updates.is_some() {
+        base.check_updates = overlay.check_updates;
+    }
+    if overlay.build.is_some() {
+        base.build = overlay.build;
+    }
+    if overlay.build_cwd.is_some() {
+        base.build_cwd = overlay.build_cwd;
+    }
+    if overlay.run.is_some() {
+        base.run = overlay.run;
+    }
+    if overlay.run_args.is_some() {
+        base.run_args = overlay.run_args;
+    }
+    if overlay.run_cwd.is_some() {
+        base.run_cwd = overlay.run_cwd;
+    }
+    if overlay.backtrace.is_some() {
+        base.backtrace = overlay.backtrace;
+    }
+    if overlay.run_stdin.is_some() {
+        base.run_stdin = overlay.run_stdin;
+    }
+    if overlay.env_file.is_some() {
+        base.env_file = overlay.env_file;
+    }
+    if overlay.once.is_some() {
+        base.once = overlay.once;
+    }
+    if overlay.build_only.is_some() {
+        base.build_only = overlay.build_only;
+    }
+    if overlay.defer_run.is_some() {
+        base.defer_run = overlay.defer_run;
+    }
+    if overlay.warm.is_some() {
+        base.warm = overlay.warm;
+    }
+    if overlay.no_run.is_some() {
+        base.no_run = overlay.no_run;
+    }
+    if overlay.run_timeout_ms.is_some() {
+        base.run_timeout_ms = overlay.run_timeout_ms;
+    }
+    if overlay.initial_build_retries.is_some() {
+        base.initial_build_retries = overlay.initial_build_retries;
+    }
+    if overlay.runner.is_some() {
+        base.runner = overlay.runner;
+    }
+    if overlay.overlap_restart.is_some() {
+        base.overlap_restart = overlay.overlap_restart;
+    }
+    if overlay.restart_on_exit.is_some() {
+        base.restart_on_exit = overlay.restart_on_exit;
+    }
+    if overlay.on_fail_action.is_some() {
+        base.on_fail_action = overlay.on_fail_action;
+    }
+    if overlay.crash_cooldown_ms.is_some() {
+        base.crash_cooldown_ms = overlay.crash_cooldown_ms;
+    }
+    if overlay.skip_unchanged.is_some() {
+        base.skip_unchanged = overlay.skip_unchanged;
+    }
+    if overlay.hooks_on_noop.is_some() {
+        base.hooks_on_noop = overlay.hooks_on_noop;
+    }
+    if overlay.mode.is_some() {
+        base.mode = overlay.mode;
+    }
+    if overlay.test_runner.is_some() {
+        base.test_runner = overlay.test_runner;
+    }
+
+    if overlay.cargo.is_some() {
+        base.cargo = overlay.cargo;
+    }
+    if overlay.manifest_path.is_some() {
+        base.manifest_path = overlay.manifest_path;
+    }
+    if overlay.extra_manifests.is_some() {
+        base.extra_manifests = overlay.extra_manifests;
+    }
+    if overlay.target_dir.is_some() {
+        base.target_dir = overlay.target_dir;
+    }
+    if overlay.package.is_some() {
+        base.package = overlay.package;
+    }
+    if overlay.bin.is_some() {
+        base.bin = overlay.bin;
+    }
+    if overlay.features.is_some() {
+        base.features = overlay.features;
+    }
+    if overlay.cargo_extra_args.is_some() {
+        base.cargo_extra_args = overlay.cargo_extra_args;
+    }
+    if overlay.all_features.is_some() {
+        base.all_features = overlay.all_features;
+    }
+    if overlay.no_default_features.is_some() {
+        base.no_default_features = overlay.no_default_features;
+    }
+    if overlay.workspace.is_some() {
+        base.workspace = overlay.workspace;
+    }
+    if overlay.release.is_some() {
+        base.release = overlay.release;
+    }
+    if overlay.locked.is_some() {
+        base.locked = overlay.locked;
+    }
+    if overlay.frozen.is_some() {
+        base.frozen = overlay.frozen;
+    }
+    if overlay.offline.is_some() {
+        base.offline = overlay.offline;
+    }
+    if overlay.watch_workspace.is_some() {
+        base.watch_workspace = overlay.watch_workspace;
+    }
+    if overlay.bench.is_some() {
+        base.bench = overlay.bench;
+    }
+    if overlay.doc_deps.is_some() {
+        base.doc_deps = overlay.doc_deps;
+    }
+    if overlay.doc_args.is_some() {
+        base.doc_args = overlay.doc_args;
+    }
+    if overlay.open_docs.is_some() {
+        base.open_docs = overlay.open_docs;
+    }
+    if overlay.fmt_check.is_some() {
+        base.fmt_check = overlay.fmt_check;
+    }
+    if overlay.clippy.is_some() {
+        base.clippy = overlay.clippy;
+    }
+
+    if overlay.pre_build.is_some() {
+        base.pre_build = overlay.pre_build;
+    }
+    if overlay.post_build.is_some() {
+        base.post_build = overlay.post_build;
+    }
+    if overlay.pre_run.is_some() {
+        base.pre_run = overlay.pre_run;
+    }
+    if overlay.post_run.is_some() {
+        base.post_run = overlay.post_run;
+    }
+    if overlay.on_build_fail.is_some() {
+        base.on_build_fail = overlay.on_build_fail;
+    }
+    if overlay.post_ready.is_some() {
+        base.post_ready = overlay.post_ready;
     }
 
-    base
-}
-
+    if overlay.health_check.is_some() {
+        base.health_check = overlay.health_check;
+    }
+    if overlay.ready_port.is_some() {
+        base.ready_port = overlay.ready_port;
+    }
+    if overlay.ready_host.is_some() {
+        base.ready_host = overlay.ready_host;
+    }
+    if overlay.health_interval_ms.is_some() {
+        base.health_interval_ms = overlay.health_interval_ms;
+    }
+    if overlay.health_retries.is_some() {
+        base.health_retries = overlay.health_retries;
+    }
+    if overlay.services.is_some() {
+        base.services = overlay.services;
+    }
+    if overlay.asset_sync.is_some() {
+        base.asset_sync = overlay.asset_sync;
+    }
+    if overlay.transform.is_some() {
+        base.transform = overlay.transform;
+    }
+
+    base
+}
+
 fn norm_ext(s: &str) -> String {
     s.trim().trim_start_matches('.').to_ascii_lowercase()
 }
 
-pub fn effective_config(cli: Config, file: Option<Config>) -> Result<EffectiveConfig> {
-    let merged = merge_config(file.unwrap_or_default(), cli);
+pub fn effective_config(cli: Config, file: Option<Config>) -> Result<EffectiveConfig, RairError> {
+    // A debounce from the command line also overrides the services' own
+    let cli_debounce_ms = cli.debounce_ms;
+    let mut file = file.unwrap_or_default();
+    // `--include-ext`/`--exclude-ext` add to the file's lists (or the default) instead
+    if let Some(ext) = cli.include_ext.clone() {
+        file.include_ext
+            .get_or_insert_with(default_include_ext)
+            .extend(ext);
+    }
+    if let Some(ext) = cli.exclude_ext.clone() {
+        file.exclude_ext.get_or_insert_with(Vec::new).extend(ext);
+    }
+    let merged = merge_config(
+        file,
+        Config {
+            include_ext: None,
+            exclude_ext: None,
+            ..cli
+        },
+    );
+
+    let cargo = merged.cargo.unwrap_or(true);
 
     // Smart default watch paths: if Cargo.toml exists, use Cargo defaults, else use current dir
-    let default_watch = if PathBuf::from("Cargo.toml").exists() {
+    let default_watch = if cargo && PathBuf::from("Cargo.toml").exists() {
         vec!["src".into(), "Cargo.toml".into(), "Cargo.lock".into()]
     } else {
         vec![".".into()]
     };
 
     let default_ignore = vec!["**/target/**".into(), "**/.git/**".into()];
-    let default_include_ext = vec!["rs".into(), "toml".into()];
 
-    let watch = merged
-        .watch
-        .unwrap_or(default_watch)
-        .into_iter()
-        .map(PathBuf::from)
+    let watch_entries = merged.watch.unwrap_or(default_watch);
+    let mut watch = watch_entries
+        .iter()
+        .map(|w| PathBuf::from(w.path()))
         .collect::<Vec<_>>();
+    let mut extra_manifests = HashSet::new();
+    for entry in merged.extra_manifests.unwrap_or_default() {
+        let files = extra_manifest_files(Path::new(&entry))?;
+        extra_manifests.extend(files.iter().map(|f| path_key(f)));
+        add_watch_paths(
+            &mut watch,
+            files.into_iter().filter(|f| f.exists()).collect(),
+        );
+    }
+    let non_recursive = watch_entries
+        .iter()
+        .filter(|w| !w.recursive())
+        .map(|w| PathBuf::from(w.path()))
+        .collect::<Vec<_>>();
+
+    let timestamp = TimestampFormat::new(
+        merged
+            .timestamp_format
+            .as_deref()
+            .unwrap_or(DEFAULT_TIMESTAMP_FORMAT),
+        merged.timestamp_utc.unwrap_or(false),
+    )?;
+
+    let target_dir = merged.target_dir.map(PathBuf::from);
+    if let Some(dir) = &target_dir {
+        ensure_config!(
+            !dir.exists() || dir.is_dir(),
+            "target_dir is not a directory: {:?}",
+            dir
+        );
+    }
 
-    let ignore_globs = merged.ignore.unwrap_or(default_ignore);
-    let ignore_set = build_globset(&ignore_globs)?;
+    let mut ignore_globs = merged.ignore.unwrap_or(default_ignore);
+    let root = std::env::current_dir().map_err(RairError::CurrentDir)?;
+    let mut compiled_ignore = anchor_globs(&ignore_globs, &root);
+    // A custom target dir isn't covered by `**/target/**`; never rebuild on build output
+    if let Some(dir) = &target_dir {
+        let glob = format!(
+            "{}/**",
+            globset::escape(absolute_path(dir).to_string_lossy().trim_end_matches('/'))
+        );
+        ignore_globs.push(glob.clone());
+        compiled_ignore.push(glob);
+    }
+    // `ignore_globs` keeps the patterns as written so `explain_match` reports them verbatim
+    let ignore_set = build_globset(&compiled_ignore)?;
+    let rairignore = load_rairignore(&root)?;
+    let ignore_engine = merged
+        .ignore_engine
+        .as_deref()
+        .map(IgnoreEngine::parse)
+        .transpose()?
+        .unwrap_or_default();
+    let ignore_files = (ignore_engine == IgnoreEngine::Ignore).then(|| IgnoreFiles::scan(&root));
 
-    let include_ext_list = merged.include_ext.unwrap_or(default_include_ext);
+    let include_ext_list = merged
+        .only_ext
+        .or(merged.include_ext)
+        .unwrap_or_else(default_include_ext);
     let include_ext: HashSet<String> = include_ext_list.into_iter().map(|e| norm_ext(&e)).collect();
 
+    let trigger_globs = merged.trigger_globs.unwrap_or_default();
+    let trigger_set = build_globset(&trigger_globs)?;
+    let always_rebuild = merged.always_rebuild.unwrap_or_default();
+    let always_rebuild_set = build_globset(&anchor_globs(&always_rebuild, &root))?;
+    let events = match merged.events {
+        Some(names) => names
+            .iter()
+            .map(|n| EventOp::parse(n))
+            .collect::<Result<_, _>>()?,
+        None => EventOp::ALL.into_iter().collect(),
+    };
+
     let exclude_ext: HashSet<String> = merged
         .exclude_ext
         .unwrap_or_default()
@@ -199,11 +1721,76 @@ pub fn effective_config(cli: Config, file: Option<Config>) -> Result<EffectiveCo
         .collect();
 
     let debounce_ms = merged.debounce_ms.unwrap_or(250);
+    let services = resolve_services(
+        merged.services.unwrap_or_default(),
+        &root,
+        cli_debounce_ms,
+        Duration::from_millis(debounce_ms),
+    )?;
+    let asset_sync = merged
+        .asset_sync
+        .unwrap_or_default()
+        .iter()
+        .map(|a| AssetSync::new(&a.glob, &a.dest))
+        .collect::<Result<Vec<_>, _>>()?;
+    let transform = merged
+        .transform
+        .unwrap_or_default()
+        .into_iter()
+        .map(|t| Transform::new(&t.glob, t.run))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let build_cwd = merged.build_cwd.map(PathBuf::from);
+    if let Some(dir) = &build_cwd {
+        ensure_config!(dir.is_dir(), "build_cwd is not a directory: {:?}", dir);
+    }
+    let run_cwd = merged.run_cwd.map(PathBuf::from);
+    if let Some(dir) = &run_cwd {
+        ensure_config!(dir.is_dir(), "run_cwd is not a directory: {:?}", dir);
+    }
+    let backtrace = merged.backtrace.map(|b| b.trim().to_ascii_lowercase());
+    let interactive = merged.interactive.unwrap_or(false);
+    let run_stdin = match merged
+        .run_stdin
+        .as_deref()
+        .map(RunStdin::parse)
+        .transpose()?
+    {
+        Some(stdin) => stdin,
+        None if interactive => RunStdin::Null,
+        None => RunStdin::Inherit,
+    };
+    ensure_config!(
+        !(interactive && run_stdin == RunStdin::Inherit),
+        "run_stdin = \"inherit\" conflicts with interactive, which reads stdin itself"
+    );
+    let env_file = merged.env_file;
+    if let Some(b) = &backtrace {
+        ensure_config!(
+            matches!(b.as_str(), "0" | "1" | "full"),
+            "unknown backtrace {:?} (expected \"0\", \"1\" or \"full\")",
+            b
+        );
+    }
+
     let clear = merged.clear.unwrap_or(true);
 
     let manifest_path = merged.manifest_path.map(PathBuf::from);
+    let no_run = merged.no_run.unwrap_or(false);
     let package = merged.package;
     let bin = merged.bin;
+    let bins = match &bin {
+        Some(pattern) if is_target_glob(pattern) => {
+            let names = expand_bin_glob(manifest_path.as_deref(), pattern)?;
+            ensure_config!(
+                !names.is_empty(),
+                "bin {:?} matches no binary target",
+                pattern
+            );
+            names
+        }
+        _ => Vec::new(),
+    };
 
     let features = merged.features.unwrap_or_default();
     let all_features = merged.all_features.unwrap_or(false);
@@ -211,135 +1798,3806 @@ pub fn effective_config(cli: Config, file: Option<Config>) -> Result<EffectiveCo
     let workspace = merged.workspace.unwrap_or(false);
     let release = merged.release.unwrap_or(false);
 
-    let build = merged.build.unwrap_or_else(|| {
-        let mut v = vec!["cargo".into(), "build".into()];
-        if release {
-            v.push("--release".into());
-        }
-        if let Some(mp) = &manifest_path {
-            v.push("--manifest-path".into());
-            v.push(mp.to_string_lossy().to_string());
-        }
-        if workspace {
-            v.push("--workspace".into());
-        }
-        if let Some(p) = &package {
-            v.push("-p".into());
-            v.push(p.clone());
-        }
-        if let Some(b) = &bin {
-            v.push("--bin".into());
-            v.push(b.clone());
-        }
-        if all_features {
-            v.push("--all-features".into());
-        }
-        if no_default_features {
-            v.push("--no-default-features".into());
-        }
-        if !features.is_empty() {
-            v.push("--features".into());
-            v.push(features.join(","));
-        }
-        v
-    });
+    let mode = merged
+        .mode
+        .as_deref()
+        .map(Mode::parse)
+        .transpose()?
+        .unwrap_or_default();
+    let bench = merged.bench;
 
     let pre_build = merged.pre_build.unwrap_or_default();
     let post_build = merged.post_build.unwrap_or_default();
     let pre_run = merged.pre_run.unwrap_or_default();
     let post_run = merged.post_run.unwrap_or_default();
     let on_build_fail = merged.on_build_fail.unwrap_or_default();
+    let post_ready = merged.post_ready.unwrap_or_default();
 
-    Ok(EffectiveConfig {
+    let mut eff = EffectiveConfig {
         watch,
+        non_recursive,
         ignore_globs,
         ignore_set,
+        rairignore,
+        ignore_engine,
+        ignore_files,
+        root,
+        extra_manifests,
         include_ext,
         exclude_ext,
+        trigger_globs,
+        trigger_set,
+        always_rebuild,
+        always_rebuild_set,
+        events,
+        watch_metadata: merged.watch_metadata.unwrap_or(false),
+        ignore_editor_temp: merged.ignore_editor_temp.unwrap_or(true),
+        include_hidden: merged.include_hidden.unwrap_or(false),
+        follow_symlinks: merged.follow_symlinks.unwrap_or(false),
         debounce: Duration::from_millis(debounce_ms),
+        debounce_mode: merged
+            .debounce_mode
+            .as_deref()
+            .map(DebounceMode::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        burst_threshold: Some(merged.burst_threshold.unwrap_or(50)).filter(|&n| n > 0),
+        verbose: merged.verbose.unwrap_or(false),
+        timings: merged.timings.unwrap_or(false),
+        timestamp,
+        per_path_cooldown: merged
+            .per_path_cooldown_ms
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+        min_build_interval: merged
+            .min_build_interval_ms
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+        git_tracked_only: merged.git_tracked_only.unwrap_or(false),
+        git_include_untracked: merged.git_include_untracked.unwrap_or(false),
         clear,
-        build,
-        run: merged.run,
+        clear_on_fail: merged.clear_on_fail.unwrap_or(false),
+        clear_before: merged
+            .clear_before
+            .as_deref()
+            .map(ClearBefore::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        bell: merged
+            .bell
+            .as_deref()
+            .map(Bell::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        status_line: merged.status_line.unwrap_or(false),
+        concise: merged.concise.unwrap_or(false),
+        strict_exit: merged.strict_exit.unwrap_or(false),
+        keep_going: merged.keep_going.unwrap_or(false),
+        watcher_restarts: merged.watcher_restarts.unwrap_or(3),
+        control_socket: merged.control_socket.map(PathBuf::from),
+        http_trigger_port: merged.http_trigger_port,
+        http_trigger_host: merged
+            .http_trigger_host
+            .unwrap_or_else(|| "127.0.0.1".to_string()),
+        http_trigger_token: merged.http_trigger_token.filter(|t| !t.is_empty()),
+        interactive,
+        buffer_output: merged.buffer_output.unwrap_or(false),
+        collapse_repeated_errors: merged.collapse_repeated_errors.unwrap_or(false),
+        fail_tail_lines: merged.fail_tail_lines,
+        watch_build_script_inputs: merged.watch_build_script_inputs.unwrap_or(false),
+        list_artifacts: merged.list_artifacts.unwrap_or(false),
+        parse_diagnostics: merged.parse_diagnostics.unwrap_or(false),
+        pty: merged.pty.unwrap_or(false),
+        monitor: merged.monitor.unwrap_or(false),
+        monitor_interval: Duration::from_millis(merged.monitor_interval_ms.unwrap_or(10_000)),
+        watch_stats: merged.watch_stats.unwrap_or(false),
+        watch_stats_interval: Duration::from_millis(
+            merged.watch_stats_interval_ms.unwrap_or(10_000),
+        ),
+        check_updates: merged.check_updates.unwrap_or(false),
+        trigger_file: merged.trigger_file.map(|p| absolute_path(Path::new(&p))),
+        reload_config_on_change: merged.reload_config_on_change.unwrap_or(true),
+        build: Vec::new(),
+        build_cwd,
+        run: merged.run.filter(|_| !no_run),
+        run_args: merged.run_args.unwrap_or_default(),
+        run_cwd,
+        backtrace,
+        run_stdin,
+        env_file: None,
+        once: merged.once.unwrap_or(false),
+        build_only: merged.build_only.unwrap_or(false),
+        defer_run: merged.defer_run.unwrap_or(false),
+        warm: merged.warm.unwrap_or(false),
+        no_run,
+        run_timeout: merged.run_timeout_ms.map(Duration::from_millis),
+        initial_build_retries: merged.initial_build_retries.unwrap_or(0),
+        runner: merged.runner.unwrap_or_default(),
+        overlap_restart: merged.overlap_restart.unwrap_or(false),
+        restart_on_exit: merged.restart_on_exit.unwrap_or(false),
+        on_fail_action: merged
+            .on_fail_action
+            .as_deref()
+            .map(OnFailAction::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        crash_cooldown: Duration::from_millis(merged.crash_cooldown_ms.unwrap_or(1000)),
+        skip_unchanged: merged.skip_unchanged.unwrap_or(false),
+        hooks_on_noop: merged.hooks_on_noop.unwrap_or(false),
+        mode,
+        test_runner: merged
+            .test_runner
+            .as_deref()
+            .map(TestRunner::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        cargo,
         manifest_path,
+        target_dir,
         package,
         bin,
+        bins,
         features,
         all_features,
         no_default_features,
+        cargo_extra_args: merged.cargo_extra_args.unwrap_or_default(),
         workspace,
         release,
+        locked: merged.locked.unwrap_or(false),
+        frozen: merged.frozen.unwrap_or(false),
+        offline: merged.offline.unwrap_or(false),
+        watch_workspace: merged.watch_workspace.unwrap_or(false),
+        bench,
+        doc_deps: merged.doc_deps.unwrap_or(false),
+        doc_args: merged.doc_args.unwrap_or_default(),
+        open_docs: merged.open_docs.unwrap_or(false),
+        fmt_check: merged.fmt_check.unwrap_or(false),
+        clippy: merged.clippy.unwrap_or(false),
         pre_build,
         post_build,
         pre_run,
         post_run,
         on_build_fail,
-    })
+        post_ready,
+        health_check: merged.health_check,
+        ready_port: merged.ready_port,
+        ready_host: merged.ready_host.unwrap_or_else(|| "127.0.0.1".to_string()),
+        health_interval: Duration::from_millis(merged.health_interval_ms.unwrap_or(500)),
+        health_retries: merged.health_retries.unwrap_or(20),
+        services,
+        asset_sync,
+        transform,
+    };
+
+    let derived_build = merged.build.is_none();
+    eff.build = match merged.build {
+        Some(build) => build,
+        None if !cargo => {
+            return Err(RairError::InvalidConfig(
+                "cargo = false requires an explicit `build` command".into(),
+            ))
+        }
+        None => derive_build_argv(&eff),
+    };
+    ensure_config!(
+        !eff.build_only || eff.once,
+        "build_only only applies to once-mode; add --once"
+    );
+    ensure_config!(
+        eff.bins.len() <= 1 || eff.run.is_some() || !eff.has_run_step(),
+        "bin {:?} matches {} binaries ({}); set `run` to choose the one to start",
+        eff.bin.as_deref().unwrap_or_default(),
+        eff.bins.len(),
+        eff.bins.join(", ")
+    );
+    ensure_config!(
+        !eff.defer_run || !eff.once,
+        "defer_run waits for a file change, which once-mode never sees; drop one of them"
+    );
+    if !cargo {
+        // the default run argv comes from cargo metadata
+        ensure_config!(
+            eff.run.is_some() || !eff.has_run_step(),
+            "cargo = false requires an explicit `run` command"
+        );
+        ensure_config!(
+            !eff.watch_workspace,
+            "watch_workspace uses cargo metadata and can't be combined with cargo = false"
+        );
+        ensure_config!(
+            !eff.fmt_check && !eff.clippy,
+            "fmt_check and clippy run cargo and can't be combined with cargo = false"
+        );
+    }
+    // build script and artifact messages only come with cargo's JSON output; an explicit
+    // build is left as is. Diagnostics are only in the JSON when cargo doesn't render them.
+    if eff.reads_cargo_messages() && derived_build {
+        eff.build.push(if eff.parse_diagnostics {
+            JSON_DIAGNOSTICS_FORMAT.into()
+        } else {
+            "--message-format=json-render-diagnostics".into()
+        });
+    }
+    // `./scripts/launch.sh` means the project's script, wherever rair was started from
+    let project_root = eff.project_root();
+    eff.build = resolve_program(std::mem::take(&mut eff.build), &project_root);
+    eff.run = eff
+        .run
+        .take()
+        .map(|run| resolve_program(run, &project_root));
+    eff.env_file = match env_file.as_deref() {
+        Some("") => None,
+        Some(p) => {
+            let path = project_root.join(p);
+            ensure_config!(path.is_file(), "env_file {:?} doesn't exist", path);
+            Some(path)
+        }
+        None => Some(project_root.join(".env")).filter(|p| p.is_file()),
+    };
+    if let Some(path) = &eff.env_file {
+        add_watch_paths(&mut eff.watch, vec![path.clone()]);
+    }
+
+    Ok(eff)
 }
 
-/// Returns true if this path should trigger rebuild/restart.
-pub fn is_relevant_path(
-    path: &Path,
-    include_ext: &HashSet<String>,
-    exclude_ext: &HashSet<String>,
-) -> bool {
-    // Always treat Cargo manifest/lock as relevant.
-    if path.ends_with("Cargo.toml") || path.ends_with("Cargo.lock") {
-        return true;
+/// A problem `validate` found in a config (`rair check-config`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The setting it concerns, e.g. `ignore` or `post_build`.
+    pub setting: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.setting, self.message)
     }
+}
 
-    let ext = path
-        .extension()
-        .and_then(|x| x.to_str())
-        .unwrap_or("")
-        .to_ascii_lowercase();
+/// Lints a (merged) config without running anything: globs that don't compile, watch
+/// paths that don't exist, hooks whose program isn't found and values `mode`,
+/// `debounce_mode`, `on_fail_action` and friends don't accept. Unlike
+/// `effective_config`, which stops at the first error, every problem is listed; when
+/// none of these checks finds one, `effective_config`'s own error (if any) is the list.
+pub fn validate(cfg: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut push = |setting: &str, message: String| {
+        problems.push(Problem {
+            setting: setting.to_string(),
+            message,
+        })
+    };
 
-    if ext.is_empty() {
-        return false;
+    let globs = [
+        ("ignore", cfg.ignore.as_deref()),
+        ("trigger_globs", cfg.trigger_globs.as_deref()),
+        ("always_rebuild", cfg.always_rebuild.as_deref()),
+    ];
+    for (setting, list) in globs {
+        for glob in list.unwrap_or_default() {
+            if let Err(e) = Glob::new(glob) {
+                push(setting, format!("bad glob {:?}: {}", glob, e));
+            }
+        }
     }
-    if exclude_ext.contains(&ext) {
+    for service in cfg.services.iter().flatten() {
+        for glob in service.ignore.iter().flatten() {
+            if let Err(e) = Glob::new(glob) {
+                push(
+                    "services",
+                    format!("{}: bad glob {:?}: {}", service.name, glob, e),
+                );
+            }
+        }
+    }
+    let patterns = cfg
+        .asset_sync
+        .iter()
+        .flatten()
+        .map(|a| ("asset_sync", &a.glob));
+    let patterns = patterns.chain(
+        cfg.transform
+            .iter()
+            .flatten()
+            .map(|t| ("transform", &t.glob)),
+    );
+    for (setting, glob) in patterns {
+        if let Err(e) = Glob::new(glob) {
+            push(setting, format!("bad glob {:?}: {}", glob, e));
+        }
+    }
+
+    for entry in cfg.watch.iter().flatten() {
+        if !Path::new(entry.path()).exists() {
+            push("watch", format!("{:?} doesn't exist", entry.path()));
+        }
+    }
+
+    let hooks = [
+        ("pre_build", &cfg.pre_build),
+        ("post_build", &cfg.post_build),
+        ("pre_run", &cfg.pre_run),
+        ("post_run", &cfg.post_run),
+        ("on_build_fail", &cfg.on_build_fail),
+        ("post_ready", &cfg.post_ready),
+    ];
+    for (setting, list) in hooks {
+        for argv in list.iter().flatten().flat_map(Hook::argvs) {
+            match argv.first() {
+                None => push(setting, "empty command".into()),
+                // `{exe}` only exists once built
+                Some(program) if program.contains('{') => {}
+                Some(program) if !program_exists(program) => {
+                    push(setting, format!("program {:?} not found", program));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    let values = [
+        (
+            "mode",
+            cfg.mode.as_deref().map(Mode::parse).and_then(Result::err),
+        ),
+        (
+            "debounce_mode",
+            cfg.debounce_mode
+                .as_deref()
+                .map(DebounceMode::parse)
+                .and_then(Result::err),
+        ),
+        (
+            "on_fail_action",
+            cfg.on_fail_action
+                .as_deref()
+                .map(OnFailAction::parse)
+                .and_then(Result::err),
+        ),
+        (
+            "test_runner",
+            cfg.test_runner
+                .as_deref()
+                .map(TestRunner::parse)
+                .and_then(Result::err),
+        ),
+        (
+            "run_stdin",
+            cfg.run_stdin
+                .as_deref()
+                .map(RunStdin::parse)
+                .and_then(Result::err),
+        ),
+        (
+            "clear_before",
+            cfg.clear_before
+                .as_deref()
+                .map(ClearBefore::parse)
+                .and_then(Result::err),
+        ),
+        (
+            "bell",
+            cfg.bell.as_deref().map(Bell::parse).and_then(Result::err),
+        ),
+        (
+            "ignore_engine",
+            cfg.ignore_engine
+                .as_deref()
+                .map(IgnoreEngine::parse)
+                .and_then(Result::err),
+        ),
+    ];
+    for (setting, err) in values {
+        if let Some(e) = err {
+            push(setting, e.to_string());
+        }
+    }
+    for op in cfg.events.iter().flatten() {
+        if let Err(e) = EventOp::parse(op) {
+            push("events", e.to_string());
+        }
+    }
+
+    if problems.is_empty() {
+        if let Err(e) = effective_config(cfg.clone(), None) {
+            problems.push(Problem {
+                setting: "config".into(),
+                message: e.to_string(),
+            });
+        }
+    }
+    problems
+}
+
+/// Whether `program` names a file: a path as written (relative to the current
+/// directory), or a bare name found on PATH.
+fn program_exists(program: &str) -> bool {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file();
+    }
+    let Some(dirs) = std::env::var_os("PATH") else {
         return false;
+    };
+    std::env::split_paths(&dirs).any(|dir| {
+        let candidate = dir.join(program);
+        candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+    })
+}
+
+/// Resolves a program written as a relative path (`./x`, `../x`) against `root`. Bare
+/// names like `cargo` are looked up on PATH and stay as they are.
+pub fn resolve_program(mut argv: Vec<String>, root: &Path) -> Vec<String> {
+    if let Some(program) = argv.first_mut() {
+        let resolved = if let Some(rest) = program.strip_prefix("./") {
+            Some(root.join(rest))
+        } else if program.starts_with("../") {
+            Some(root.join(&*program))
+        } else {
+            None
+        };
+        if let Some(path) = resolved {
+            *program = path.to_string_lossy().into_owned();
+        }
     }
-    include_ext.contains(&ext)
+    argv
 }
 
-pub fn exe_name(bin: &str) -> String {
-    #[cfg(windows)]
-    {
-        format!("{}.exe", bin)
+/// Builds `cargo <subcommand>` with the configured package/target/feature selection.
+pub fn cargo_argv(eff: &EffectiveConfig, subcommand: &[&str]) -> Vec<String> {
+    let mut v: Vec<String> = vec!["cargo".into()];
+    v.extend(subcommand.iter().map(|s| s.to_string()));
+    if eff.release {
+        v.push("--release".into());
+    }
+    if let Some(mp) = &eff.manifest_path {
+        v.push("--manifest-path".into());
+        v.push(mp.to_string_lossy().to_string());
     }
+    if let Some(td) = &eff.target_dir {
+        v.push("--target-dir".into());
+        v.push(td.to_string_lossy().to_string());
+    }
+    if eff.workspace {
+        v.push("--workspace".into());
+    }
+    if let Some(p) = &eff.package {
+        v.push("-p".into());
+        v.push(p.clone());
+    }
+    if !eff.bins.is_empty() {
+        for b in &eff.bins {
+            v.push("--bin".into());
+            v.push(b.clone());
+        }
+    } else if let Some(b) = &eff.bin {
+        v.push("--bin".into());
+        v.push(b.clone());
+    }
+    if eff.all_features {
+        v.push("--all-features".into());
+    }
+    if eff.no_default_features {
+        v.push("--no-default-features".into());
+    }
+    if !eff.features.is_empty() {
+        v.push("--features".into());
+        v.push(eff.features.join(","));
+    }
+    for (set, flag) in [
+        (eff.locked, "--locked"),
+        (eff.frozen, "--frozen"),
+        (eff.offline, "--offline"),
+    ] {
+        if set {
+            v.push(flag.into());
+        }
+    }
+    v
+}
 
-    #[cfg(not(windows))]
-    {
-        bin.to_string()
+/// A check run between `pre_build` and the build; a failing check skips the build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// `cargo fmt --check` (`fmt_check`).
+    Fmt,
+    /// `cargo clippy` (`clippy`).
+    Clippy,
+}
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Fmt => "fmt_check",
+            Check::Clippy => "clippy",
+        }
+    }
+
+    /// The command, with the same package (and, for clippy, feature) selection as the build.
+    pub fn argv(self, eff: &EffectiveConfig) -> Vec<String> {
+        match self {
+            Check::Fmt => {
+                let mut v: Vec<String> = vec!["cargo".into(), "fmt".into(), "--check".into()];
+                if let Some(mp) = &eff.manifest_path {
+                    v.push("--manifest-path".into());
+                    v.push(mp.to_string_lossy().to_string());
+                }
+                if let Some(p) = &eff.package {
+                    v.push("-p".into());
+                    v.push(p.clone());
+                } else if eff.workspace {
+                    v.push("--all".into());
+                }
+                v
+            }
+            Check::Clippy => {
+                let mut v = cargo_argv(eff, &["clippy"]);
+                v.extend(eff.cargo_extra_args.iter().cloned());
+                v
+            }
+        }
+    }
+
+    /// Why the build is skipped after this check ran, or None if it passed.
+    pub fn failure(self, succeeded: bool, output: &OutputBuffer) -> Option<String> {
+        match self {
+            Check::Fmt if !succeeded => {
+                Some("skipping build: cargo fmt --check found unformatted code".into())
+            }
+            Check::Fmt => None,
+            Check::Clippy => match clippy_warning_count(output) {
+                _ if !succeeded => Some("skipping build: clippy failed".into()),
+                0 => None,
+                1 => Some("skipping build: clippy reported 1 warning".into()),
+                n => Some(format!("skipping build: clippy reported {} warnings", n)),
+            },
+        }
     }
 }
 
-pub fn exe_path(target_dir: &Path, release: bool, bin: &str) -> PathBuf {
-    let profile = if release { "release" } else { "debug" };
-    target_dir.join(profile).join(exe_name(bin))
+impl EffectiveConfig {
+    /// The enabled checks, in the order they run.
+    pub fn checks(&self) -> Vec<Check> {
+        let mut v = Vec::new();
+        if self.fmt_check {
+            v.push(Check::Fmt);
+        }
+        if self.clippy {
+            v.push(Check::Clippy);
+        }
+        v
+    }
 }
 
-/// Runs a list of hook commands, each an argv vector.
-/// Returns Ok(true) if all commands succeed, Ok(false) if any fails.
-pub fn run_hook_list(name: &str, hooks: &[Vec<String>]) -> Result<bool> {
-    if hooks.is_empty() {
-        return Ok(true);
-    }
-    for (i, argv) in hooks.iter().enumerate() {
-        anyhow::ensure!(!argv.is_empty(), "hook {}[{}] argv is empty", name, i);
-        let mut c = Command::new(&argv[0]);
-        if argv.len() > 1 {
-            c.args(&argv[1..]);
-        }
-        let status = c
-            .stdin(Stdio::null())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .status()
-            .with_context(|| format!("hook {}[{}]: {:?}", name, i, argv))?;
-        if !status.success() {
-            return Ok(false);
+/// Sums cargo's per-crate `... generated N warnings` summaries.
+pub fn clippy_warning_count(output: &OutputBuffer) -> usize {
+    output
+        .lines
+        .iter()
+        .filter_map(|l| {
+            let rest = l.text.split_once(" generated ")?.1;
+            let (n, what) = rest.split_once(' ')?;
+            what.starts_with("warning")
+                .then(|| n.parse::<usize>().ok())?
+        })
+        .sum()
+}
+
+/// The build argv used when no explicit `build` is configured, based on the mode.
+/// `cargo_extra_args` go last, after the flags rair knows about.
+pub fn derive_build_argv(eff: &EffectiveConfig) -> Vec<String> {
+    let subcommand = match eff.mode {
+        Mode::Test => eff.test_runner.cargo_subcommand(),
+        mode => mode.cargo_subcommand(),
+    };
+    let mut v = cargo_argv(eff, subcommand);
+    if eff.mode == Mode::Bench {
+        if let Some(b) = &eff.bench {
+            v.push("--bench".into());
+            v.push(b.clone());
         }
     }
-    Ok(true)
+    if eff.mode == Mode::Doc {
+        if !eff.doc_deps {
+            v.push("--no-deps".into());
+        }
+        v.extend(eff.doc_args.iter().cloned());
+    }
+    v.extend(eff.cargo_extra_args.iter().cloned());
+    v
+}
+
+impl EffectiveConfig {
+    /// With `open_docs` in doc mode, the derived `cargo doc` argv with `--open` added,
+    /// run once after the first successful build.
+    pub fn open_docs_argv(&self) -> Option<Vec<String>> {
+        if self.mode != Mode::Doc || !self.open_docs {
+            return None;
+        }
+        let mut v = derive_build_argv(self);
+        v.insert(2, "--open".into());
+        Some(v)
+    }
+}
+
+impl EffectiveConfig {
+    /// Returns true if this path should trigger rebuild/restart: it matches an
+    /// `always_rebuild` or trigger glob, or passes the extension filters.
+    pub fn is_relevant(&self, path: &Path) -> bool {
+        self.relevance_filter(path).is_none()
+    }
+
+    /// The filter that makes `is_relevant` false, if any. `always_rebuild` globs pass
+    /// before any filter; then in order: editor temp files, anything in `.git`, trigger
+    /// globs (which match hidden files too), hidden paths unless `include_hidden`, then
+    /// the extension filters.
+    pub fn relevance_filter(&self, path: &Path) -> Option<FilterReason> {
+        if self.always_rebuild_set.is_match(path) {
+            return None;
+        }
+        if self.ignore_editor_temp && is_editor_temp(path) {
+            return Some(FilterReason::EditorTemp);
+        }
+        if path.components().any(|c| c.as_os_str() == ".git") {
+            return Some(FilterReason::Hidden);
+        }
+        if self.trigger_set.is_match(path) {
+            return None;
+        }
+        if !self.include_hidden && is_hidden(path, &self.root) {
+            return Some(FilterReason::Hidden);
+        }
+        if is_relevant_path(
+            path,
+            &self.extra_manifests,
+            &self.include_ext,
+            &self.exclude_ext,
+        ) {
+            None
+        } else {
+            Some(FilterReason::Extension)
+        }
+    }
+
+    /// Returns true if `path` matches an `ignore` glob or a `.rairignore` pattern, or
+    /// with `ignore_engine = "ignore"`, the gitignore hierarchy.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.ignore_set.is_match(path)
+            || self.rairignore_match(path).is_some()
+            || self.ignore_files_match(path).is_some()
+    }
+
+    /// The pattern that makes `is_ignored` true, for verbose logging.
+    pub fn explain_ignore(&self, path: &Path) -> Option<String> {
+        explain_match(path, &self.ignore_globs, &self.ignore_set).or_else(|| {
+            self.rairignore_match(path)
+                .map(|pat| format!("{} in .rairignore", pat))
+                .or_else(|| self.ignore_files_match(path))
+        })
+    }
+
+    fn ignore_files_match(&self, path: &Path) -> Option<String> {
+        self.ignore_files.as_ref()?.matched(path)
+    }
+
+    fn rairignore_match(&self, path: &Path) -> Option<String> {
+        let gi = self.rairignore.as_ref()?;
+        if !path.starts_with(gi.path()) {
+            return None;
+        }
+        match gi.matched_path_or_any_parents(path, path.is_dir()) {
+            ignore::Match::Ignore(glob) => Some(glob.original().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns true if `path` is the configured `trigger_file`.
+    pub fn is_trigger_file(&self, path: &Path) -> bool {
+        self.trigger_file
+            .as_deref()
+            .is_some_and(|t| absolute_path(path) == t)
+    }
+
+    /// Directory to watch (non-recursively) so `trigger_file` changes are seen,
+    /// or None if a watch path already covers it.
+    pub fn trigger_watch_dir(&self) -> Option<PathBuf> {
+        let dir = self.trigger_file.as_deref()?.parent()?;
+        if self.is_watched(dir) {
+            None
+        } else {
+            Some(dir.to_path_buf())
+        }
+    }
+
+    /// Whether `path` is the `env_file`, whose changes restart the run without a build.
+    pub fn is_env_file(&self, path: &Path) -> bool {
+        self.env_file.as_deref().is_some_and(|f| same_path(f, path))
+    }
+
+    /// The directory of `manifest_path`, or `root` without one.
+    pub fn project_root(&self) -> PathBuf {
+        match self.manifest_path.as_deref().and_then(Path::parent) {
+            Some(dir) if !dir.as_os_str().is_empty() => self.root.join(dir),
+            _ => self.root.clone(),
+        }
+    }
+
+    /// Copies a changed asset into place (or removes the copy of a deleted one) with the
+    /// first `asset_sync` rule that matches `path`. Returns the destination it synced.
+    pub fn sync_asset(&self, path: &Path) -> Result<Option<PathBuf>> {
+        for rule in &self.asset_sync {
+            if let Some(dest) = rule.sync(&self.root, path)? {
+                return Ok(Some(dest));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Runs each `transform` rule whose glob matches one of `paths`, at most once, with
+    /// `RAIR_TRANSFORM_PATH` set to the first matching path. Returns the glob of every rule
+    /// that ran and whether its command succeeded.
+    pub fn run_transforms<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a Path>,
+    ) -> Result<Vec<(&str, bool)>> {
+        let paths: Vec<&Path> = paths.into_iter().collect();
+        let mut ran = Vec::new();
+        for rule in &self.transform {
+            let Some(path) = paths
+                .iter()
+                .find(|p| rule.matches(p.strip_prefix(&self.root).unwrap_or(p)))
+            else {
+                continue;
+            };
+            let env = [(
+                "RAIR_TRANSFORM_PATH".to_string(),
+                path.to_string_lossy().into_owned(),
+            )];
+            let hook = Hook::Command(rule.run.clone());
+            let ok = run_hook_list_with_env("transform", &[hook], &env)?;
+            ran.push((rule.glob.as_str(), ok));
+        }
+        Ok(ran)
+    }
+
+    /// Whether to clear the screen at this point of the cycle, with `clear`'s moment
+    /// moved by `clear_before`. `never` turns off `clear_on_fail` as well.
+    pub fn clears_at(&self, outcome: BuildOutcome) -> bool {
+        match self.clear_before {
+            ClearBefore::Run => should_clear(self.clear, self.clear_on_fail, outcome),
+            ClearBefore::Build => should_clear(false, self.clear || self.clear_on_fail, outcome),
+            ClearBefore::Never => false,
+        }
+    }
+
+    /// Whether readiness is probed at all (`health_check` or `ready_port`).
+    pub fn has_readiness_probe(&self) -> bool {
+        self.health_check.is_some() || self.ready_port.is_some()
+    }
+
+    /// Polls `health_check` and `ready_port` every `health_interval`, up to `health_retries`
+    /// times, until all that are set pass. True straight away if neither is set.
+    pub fn wait_until_ready(&self) -> bool {
+        self.wait_until_ready_while(|| true)
+    }
+
+    /// Like `wait_until_ready`, but gives up (false) as soon as `current` turns false.
+    pub fn wait_until_ready_while(&self, current: impl Fn() -> bool) -> bool {
+        wait_until_ready(
+            || {
+                !current()
+                    || self.ready_port.is_none_or(|port| {
+                        probe_tcp_port(&self.ready_host, port, self.health_interval)
+                    }) && self.health_check.as_deref().is_none_or(run_health_check)
+            },
+            self.health_interval,
+            self.health_retries,
+        ) && current()
+    }
+
+    /// Whether the build's stdout is read for cargo's JSON messages
+    /// (`watch_build_script_inputs`, `list_artifacts`, `parse_diagnostics`).
+    pub fn reads_cargo_messages(&self) -> bool {
+        self.watch_build_script_inputs || self.list_artifacts || self.parse_diagnostics
+    }
+
+    /// Whether the build reports its diagnostics as JSON, so warnings can be counted:
+    /// `parse_diagnostics` with a cargo build rair derived. An explicit `build` is left as is.
+    pub fn counts_warnings(&self) -> bool {
+        self.parse_diagnostics && self.build.iter().any(|a| a == JSON_DIAGNOSTICS_FORMAT)
+    }
+
+    /// Whether a cycle ends by starting a process: not in bench/test/doc mode, `build_only`
+    /// or `no_run`.
+    pub fn has_run_step(&self) -> bool {
+        self.mode.has_run_step() && !self.build_only && !self.no_run
+    }
+
+    /// Whether the `initial` (startup) cycle or a later one starts the run process after a
+    /// successful build. With `defer_run` the startup build stops short of it.
+    pub fn runs_after_build(&self, initial: bool) -> bool {
+        self.has_run_step() && !(initial && self.defer_run)
+    }
+
+    /// Whether startup begins with a `warm` build, before the watch paths are registered
+    /// and the first cycle runs. Once-mode builds a single time anyway, so it never warms.
+    pub fn warms_before_watching(&self) -> bool {
+        self.warm && !self.once
+    }
+
+    /// Whether a watcher event of this kind is one of the configured `events`.
+    /// Metadata-only changes (a chmod, a bare `touch`) count only with `watch_metadata`.
+    pub fn allows_event(&self, kind: &notify::EventKind) -> bool {
+        use notify::event::{EventKind, ModifyKind};
+
+        if matches!(kind, EventKind::Modify(ModifyKind::Metadata(_))) && !self.watch_metadata {
+            return false;
+        }
+        EventOp::of(kind).is_none_or(|op| self.events.contains(&op))
+    }
+
+    /// Returns true if the absolute `path` lies under one of the watch paths.
+    pub fn is_watched(&self, path: &Path) -> bool {
+        self.watch.iter().any(|w| {
+            let root = absolute_path(w);
+            if self.is_non_recursive(w) {
+                path == root || path.parent() == Some(&root)
+            } else {
+                path.starts_with(root)
+            }
+        })
+    }
+
+    fn is_non_recursive(&self, entry: &Path) -> bool {
+        self.non_recursive.iter().any(|n| n == entry)
+    }
+
+    /// What to register with the watcher for the `watch` entry `entry` (see
+    /// `watch_registration`), honouring `recursive = false`: such a directory is watched
+    /// without its subdirectories, and only covers the files directly in it.
+    pub fn watch_registration(&self, entry: &Path) -> Option<(PathBuf, notify::RecursiveMode)> {
+        if self.is_non_recursive(entry) && !entry.is_file() {
+            return Some((entry.to_path_buf(), notify::RecursiveMode::NonRecursive));
+        }
+        let parent = absolute_path(entry).parent().map(Path::to_path_buf);
+        let covering: Vec<_> = self
+            .watch
+            .iter()
+            .filter(|d| !self.is_non_recursive(d) || parent.as_ref() == Some(&absolute_path(d)))
+            .cloned()
+            .collect();
+        watch_registration(entry, &covering)
+    }
+
+    /// Registers the `watch` entry `entry` with `watcher`. Ok(false) if another entry's
+    /// watch already covers it.
+    pub fn register_watch<W: notify::Watcher>(
+        &self,
+        watcher: &mut W,
+        entry: &Path,
+    ) -> notify::Result<bool> {
+        let Some((target, mode)) = self.watch_registration(entry) else {
+            return Ok(false);
+        };
+        watcher.watch(&target, mode)?;
+        Ok(true)
+    }
+}
+
+fn absolute_path(p: &Path) -> PathBuf {
+    std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf())
+}
+
+/// Swap, backup and probe files editors write next to the real file: Vim's `*.swp`,
+/// `*.swx` and `4913`, `*~` backups and Emacs' `.#*` locks and `#*#` autosaves.
+pub fn is_editor_temp(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    name == "4913"
+        || name.ends_with(".swp")
+        || name.ends_with(".swx")
+        || name.ends_with('~')
+        || name.starts_with(".#")
+        || (name.len() > 1 && name.starts_with('#') && name.ends_with('#'))
+}
+
+/// A kind of filesystem change, for `events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventOp {
+    Create,
+    Modify,
+    Remove,
+    Rename,
+}
+
+impl EventOp {
+    pub const ALL: [EventOp; 4] = [
+        EventOp::Create,
+        EventOp::Modify,
+        EventOp::Remove,
+        EventOp::Rename,
+    ];
+
+    pub fn parse(s: &str) -> Result<EventOp, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "create" => Ok(EventOp::Create),
+            "modify" => Ok(EventOp::Modify),
+            "remove" => Ok(EventOp::Remove),
+            "rename" => Ok(EventOp::Rename),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown event {:?} in `events` (expected \"create\", \"modify\", \"remove\" or \"rename\")",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventOp::Create => "create",
+            EventOp::Modify => "modify",
+            EventOp::Remove => "remove",
+            EventOp::Rename => "rename",
+        }
+    }
+
+    /// The change behind a watcher event. `None` for kinds that don't say, like the
+    /// `Any` events of some backends; those are never filtered out.
+    pub fn of(kind: &notify::EventKind) -> Option<EventOp> {
+        use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind};
+
+        match kind {
+            EventKind::Create(_) => Some(EventOp::Create),
+            EventKind::Modify(ModifyKind::Name(_)) => Some(EventOp::Rename),
+            EventKind::Modify(_) => Some(EventOp::Modify),
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some(EventOp::Modify),
+            EventKind::Remove(_) => Some(EventOp::Remove),
+            _ => None,
+        }
+    }
+}
+
+/// The paths of `event` worth filtering. For a rename with both ends known only the
+/// target counts, so an atomic save (write temp file, rename over the original)
+/// reacts to the real file rather than the temp one.
+pub fn event_paths(event: &notify::Event) -> &[PathBuf] {
+    use notify::event::{EventKind, ModifyKind, RenameMode};
+
+    match event.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            &event.paths[1..]
+        }
+        _ => &event.paths,
+    }
+}
+
+/// Returns true if a file or directory in `path` below `root` starts with a dot. For an
+/// absolute path outside `root` only the file name is checked, so a project that itself
+/// lives in a hidden directory (`~/.local/src/app`) isn't hidden.
+pub fn is_hidden(path: &Path, root: &Path) -> bool {
+    let rel = if path.is_relative() {
+        path
+    } else {
+        path.strip_prefix(root)
+            .unwrap_or_else(|_| path.file_name().map_or(path, Path::new))
+    };
+    rel.components().any(|c| match c {
+        std::path::Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// Returns true if this path should trigger rebuild/restart. `manifests` are extra
+/// manifest and lock files (see `path_key`), relevant whatever their name.
+/// A leading dot doesn't start an extension: `.env.toml` has extension `toml`, `.env` none.
+pub fn is_relevant_path(
+    path: &Path,
+    manifests: &HashSet<PathBuf>,
+    include_ext: &HashSet<String>,
+    exclude_ext: &HashSet<String>,
+) -> bool {
+    // Always treat Cargo manifest/lock as relevant.
+    if path.ends_with("Cargo.toml") || path.ends_with("Cargo.lock") {
+        return true;
+    }
+    if !manifests.is_empty() && manifests.contains(&path_key(path)) {
+        return true;
+    }
+
+    let ext = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    if ext.is_empty() {
+        return false;
+    }
+    if exclude_ext.contains(&ext) {
+        return false;
+    }
+    include_ext.contains(&ext)
+}
+
+/// Why a changed path didn't trigger a rebuild, for `watch_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterReason {
+    /// Matched an `ignore` glob or `.rairignore`.
+    Ignored,
+    /// A hidden file or directory (without `include_hidden`), or inside `.git`.
+    Hidden,
+    /// Not tracked by git (`git_tracked_only`).
+    Untracked,
+    EditorTemp,
+    /// Not in `include_ext` (or in `exclude_ext`), and no trigger glob matched.
+    Extension,
+    /// Saved without changing its contents (`skip_unchanged`).
+    Unchanged,
+    /// Fired within `per_path_cooldown_ms`.
+    Cooldown,
+    /// Next to a single-file `watch` entry, in the directory watched on its behalf.
+    Unwatched,
+    /// A kind of change not listed in `events`.
+    EventKind,
+}
+
+/// Watcher counters logged every `watch_stats_interval_ms` with `watch_stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchStats {
+    /// Events received from the watcher.
+    pub events: u64,
+    /// Events dropped because they arrived within the debounce window.
+    pub debounced: u64,
+    /// Changed paths filtered out, by reason.
+    pub ignored: u64,
+    pub hidden: u64,
+    pub untracked: u64,
+    pub editor_temp: u64,
+    pub extension: u64,
+    pub unchanged: u64,
+    pub cooldown: u64,
+    pub unwatched: u64,
+    pub event_kind: u64,
+    /// Events that triggered a rebuild.
+    pub rebuilds: u64,
+}
+
+impl WatchStats {
+    pub fn record_filtered(&mut self, reason: FilterReason) {
+        let counter = match reason {
+            FilterReason::Ignored => &mut self.ignored,
+            FilterReason::Hidden => &mut self.hidden,
+            FilterReason::Untracked => &mut self.untracked,
+            FilterReason::EditorTemp => &mut self.editor_temp,
+            FilterReason::Extension => &mut self.extension,
+            FilterReason::Unchanged => &mut self.unchanged,
+            FilterReason::Cooldown => &mut self.cooldown,
+            FilterReason::Unwatched => &mut self.unwatched,
+            FilterReason::EventKind => &mut self.event_kind,
+        };
+        *counter += 1;
+    }
+
+    /// One log line for counters gathered over `elapsed`, e.g.
+    /// "12 events (1.2/s), 1 rebuild; filtered: 8 ignored, 3 extension".
+    pub fn report(&self, elapsed: Duration) -> String {
+        let rate = self.events as f64 / elapsed.as_secs_f64().max(0.001);
+        let mut line = format!(
+            "{} events ({:.1}/s), {} rebuild{}",
+            self.events,
+            rate,
+            self.rebuilds,
+            if self.rebuilds == 1 { "" } else { "s" }
+        );
+        let filtered: Vec<String> = [
+            (self.debounced, "debounced"),
+            (self.ignored, "ignored"),
+            (self.hidden, "hidden"),
+            (self.untracked, "untracked"),
+            (self.editor_temp, "editor temp"),
+            (self.extension, "extension"),
+            (self.unchanged, "unchanged"),
+            (self.cooldown, "cooldown"),
+            (self.unwatched, "unwatched"),
+            (self.event_kind, "event kind"),
+        ]
+        .iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, what)| format!("{} {}", n, what))
+        .collect();
+        if !filtered.is_empty() {
+            line.push_str("; filtered: ");
+            line.push_str(&filtered.join(", "));
+        }
+        line
+    }
+}
+
+/// Debounce bookkeeping for the watch loop. The window is measured from when rair became
+/// idle again after a build/restart cycle, not from the event that started it, and an
+/// event that arrived while a cycle was running is honored once it finishes.
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    window: Duration,
+    /// Start and end of the last build/restart cycle.
+    last_cycle: Option<(Instant, Instant)>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_cycle: None,
+        }
+    }
+
+    /// Whether an event that arrived at `at` may start a cycle. Events within `window` of
+    /// the last cycle's start are part of the burst that started it; events within
+    /// `window` of its end are debounced; events in between changed files mid-build.
+    pub fn accept(&self, at: Instant) -> bool {
+        let Some((start, idle)) = self.last_cycle else {
+            return true;
+        };
+        if at < start + self.window {
+            return false;
+        }
+        at < idle || at >= idle + self.window
+    }
+
+    /// Records a cycle that started at `start` and left rair idle at `idle`.
+    pub fn cycle(&mut self, start: Instant, idle: Instant) {
+        self.last_cycle = Some((start, idle));
+    }
+}
+
+/// Which end of a burst of changes builds (`debounce_mode`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebounceMode {
+    /// Build on the first change; the rest of the burst is dropped.
+    #[default]
+    Leading,
+    /// Build once no change has come for the window.
+    Trailing,
+    /// Build on the first change, and again once the burst settles if more came.
+    Both,
+}
+
+impl DebounceMode {
+    pub fn parse(s: &str) -> Result<DebounceMode, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "leading" => Ok(DebounceMode::Leading),
+            "trailing" => Ok(DebounceMode::Trailing),
+            "both" => Ok(DebounceMode::Both),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown debounce_mode {:?} (expected \"leading\", \"trailing\" or \"both\")",
+                other
+            ))),
+        }
+    }
+}
+
+/// The watch loop's debounce timing for every `debounce_mode`. `leading` leaves it to a
+/// `Debouncer`; `trailing` and `both` see every event and keep track of the burst: changes
+/// less than the window apart. A change that arrived before a build started is covered
+/// by that build.
+#[derive(Debug, Clone)]
+pub struct DebounceState {
+    mode: DebounceMode,
+    window: Duration,
+    debouncer: Debouncer,
+    last_change: Option<Instant>,
+    last_start: Option<Instant>,
+    pending: bool,
+}
+
+impl DebounceState {
+    pub fn new(mode: DebounceMode, window: Duration) -> Self {
+        Self {
+            mode,
+            window,
+            debouncer: Debouncer::new(window),
+            last_change: None,
+            last_start: None,
+            pending: false,
+        }
+    }
+
+    /// Whether an event that arrived at `at` goes on to the filters.
+    pub fn accept(&self, at: Instant) -> bool {
+        self.mode != DebounceMode::Leading || self.debouncer.accept(at)
+    }
+
+    /// A relevant change that arrived at `now`; true if it should build right away.
+    /// Otherwise it builds when `should_fire` says the burst has settled.
+    pub fn on_event(&mut self, now: Instant) -> bool {
+        if self.mode == DebounceMode::Leading {
+            return true;
+        }
+        if self.last_start.is_some_and(|start| now <= start) {
+            return false;
+        }
+        let quiet = self.last_change.is_none_or(|t| now >= t + self.window);
+        self.last_change = Some(now);
+        if self.mode == DebounceMode::Both && quiet {
+            return true;
+        }
+        self.pending = true;
+        false
+    }
+
+    /// Whether a burst with changes still to build has been quiet for the window at
+    /// `now`. True once per burst.
+    pub fn should_fire(&mut self, now: Instant) -> bool {
+        let due = self.pending && self.last_change.is_some_and(|t| now >= t + self.window);
+        if due {
+            self.pending = false;
+        }
+        due
+    }
+
+    /// Records a cycle that started at `start` and left rair idle at `idle`.
+    pub fn cycle(&mut self, start: Instant, idle: Instant) {
+        self.debouncer.cycle(start, idle);
+        self.last_start = Some(start);
+        if self.last_change.is_some_and(|t| t <= start) {
+            self.pending = false;
+        }
+    }
+}
+
+/// `burst_threshold`: a large set of changes arriving together, as from a checkout or a
+/// pull, waits until no change has come for the window and then builds once, whatever the
+/// `debounce_mode`.
+#[derive(Debug, Clone)]
+pub struct ChangeBurst {
+    threshold: usize,
+    window: Duration,
+    /// The latest change of the burst in progress, if any.
+    last_change: Option<Instant>,
+}
+
+impl ChangeBurst {
+    pub fn new(threshold: usize, window: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            last_change: None,
+        }
+    }
+
+    /// A relevant change at `now`, with `count` changes known so far (those waiting to
+    /// be built and those queued behind it). True if it's part of a burst and waits.
+    pub fn on_change(&mut self, now: Instant, count: usize) -> bool {
+        if self.last_change.is_none() && count < self.threshold {
+            return false;
+        }
+        self.last_change = Some(now);
+        true
+    }
+
+    /// Whether a burst has been quiet for the window at `now`. True once per burst.
+    pub fn should_fire(&mut self, now: Instant) -> bool {
+        let due = self.last_change.is_some_and(|t| now >= t + self.window);
+        if due {
+            self.last_change = None;
+        }
+        due
+    }
+}
+
+/// The line logged before building a burst: `187 files changed across 12 dirs -> rebuilding
+/// once`.
+pub fn format_burst_digest(paths: &[PathBuf]) -> String {
+    let dirs: HashSet<&Path> = paths.iter().filter_map(|p| p.parent()).collect();
+    format!(
+        "{} file{} changed across {} dir{} -> rebuilding once",
+        paths.len(),
+        if paths.len() == 1 { "" } else { "s" },
+        dirs.len(),
+        if dirs.len() == 1 { "" } else { "s" },
+    )
+}
+
+/// The relevant changes since the last build, for `{changed}` and the burst digest. An
+/// editor's save often arrives as a create and a modify or two for the same file: they
+/// make one entry (keyed by `path_key`) holding the latest kind of change.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeBatch {
+    changes: Vec<(PathBuf, Option<EventOp>)>,
+    index: HashMap<PathBuf, usize>,
+}
+
+impl ChangeBatch {
+    /// Adds a change to `path`. An `op` of None (a kind the backend didn't say) keeps the
+    /// kind recorded before.
+    pub fn record(&mut self, path: &Path, op: Option<EventOp>) {
+        match self.index.get(&path_key(path)) {
+            Some(&i) => {
+                let change = &mut self.changes[i];
+                change.1 = op.or(change.1);
+            }
+            None => {
+                self.index.insert(path_key(path), self.changes.len());
+                self.changes.push((path.to_path_buf(), op));
+            }
+        }
+    }
+
+    /// Records every path of `event` (see `event_paths`) that `relevant` accepts, not
+    /// just the first. Returns true if any was recorded.
+    pub fn record_event(
+        &mut self,
+        event: &notify::Event,
+        mut relevant: impl FnMut(&Path) -> bool,
+    ) -> bool {
+        let op = EventOp::of(&event.kind);
+        let mut recorded = false;
+        for p in event_paths(event) {
+            if relevant(p) {
+                self.record(p, op);
+                recorded = true;
+            }
+        }
+        recorded
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Each changed path (as first seen) with its latest kind, in the order they changed.
+    pub fn changes(&self) -> &[(PathBuf, Option<EventOp>)] {
+        &self.changes
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.changes.iter().map(|(p, _)| p.clone()).collect()
+    }
+
+    /// `src/main.rs (modify), build.rs (create)`, relative to `root` where possible.
+    pub fn describe(&self, root: &Path) -> String {
+        let describe = |(path, op): &(PathBuf, Option<EventOp>)| {
+            let path = path.strip_prefix(root).unwrap_or(path).display();
+            match op {
+                Some(op) => format!("{} ({})", path, op.as_str()),
+                None => path.to_string(),
+            }
+        };
+        self.changes
+            .iter()
+            .map(describe)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// `min_build_interval_ms`: a floor on how often file changes start a build, however long
+/// they keep coming. A change that comes too soon defers the build instead of dropping it.
+#[derive(Debug, Clone)]
+pub struct BuildRateLimit {
+    interval: Duration,
+    last_start: Option<Instant>,
+    deferred: bool,
+}
+
+impl BuildRateLimit {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_start: None,
+            deferred: false,
+        }
+    }
+
+    /// How long until a build may start at `now`; None if it may start now.
+    pub fn wait(&self, now: Instant) -> Option<Duration> {
+        let next = self.last_start? + self.interval;
+        (now < next).then(|| next - now)
+    }
+
+    /// Records a build started at `at`, which covers any deferred one.
+    pub fn started(&mut self, at: Instant) {
+        self.last_start = Some(at);
+        self.deferred = false;
+    }
+
+    /// Notes a build that had to wait; true the first time until the next build starts.
+    pub fn defer(&mut self) -> bool {
+        !std::mem::replace(&mut self.deferred, true)
+    }
+
+    /// Whether a deferred build may start now.
+    pub fn due(&self, now: Instant) -> bool {
+        self.deferred && self.wait(now).is_none()
+    }
+}
+
+/// Whether watching is paused (`p` with `interactive`), and the paths whose events were
+/// dropped meanwhile, so resuming can tell whether to catch up with a rebuild.
+#[derive(Debug, Clone, Default)]
+pub struct PauseState {
+    paused: bool,
+    missed: HashSet<PathBuf>,
+}
+
+impl PauseState {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses, or resumes and hands back the paths changed while paused.
+    pub fn toggle(&mut self) -> Option<Vec<PathBuf>> {
+        self.paused = !self.paused;
+        if self.paused {
+            return None;
+        }
+        let mut missed: Vec<_> = self.missed.drain().collect();
+        missed.sort();
+        Some(missed)
+    }
+
+    /// Whether to handle an event for `paths` now; while paused they're noted and dropped.
+    pub fn admit(&mut self, paths: impl IntoIterator<Item = PathBuf>) -> bool {
+        if self.paused {
+            self.missed.extend(paths);
+        }
+        !self.paused
+    }
+}
+
+/// Per-path throttle: a path that fired within `cooldown` is ignored.
+/// Keeps at most `capacity` paths, evicting the least recently fired.
+#[derive(Debug, Clone)]
+pub struct PathCooldown {
+    cooldown: Duration,
+    capacity: usize,
+    last: HashMap<PathBuf, Instant>,
+}
+
+impl PathCooldown {
+    pub fn new(cooldown: Duration) -> Self {
+        Self::with_capacity(cooldown, 1024)
+    }
+
+    pub fn with_capacity(cooldown: Duration, capacity: usize) -> Self {
+        PathCooldown {
+            cooldown,
+            capacity: capacity.max(1),
+            last: HashMap::new(),
+        }
+    }
+
+    /// Returns true (and records `now`) if `path` hasn't fired within the cooldown.
+    pub fn allow(&mut self, path: &Path, now: Instant) -> bool {
+        if let Some(prev) = self.last.get(path) {
+            if now.saturating_duration_since(*prev) < self.cooldown {
+                return false;
+            }
+        }
+        self.last.insert(path.to_path_buf(), now);
+        if self.last.len() > self.capacity {
+            if let Some(oldest) = self
+                .last
+                .iter()
+                .min_by_key(|(_, t)| **t)
+                .map(|(p, _)| p.clone())
+            {
+                self.last.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.last.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last.is_empty()
+    }
+}
+
+/// Last seen contents hash per path, so a save that didn't change anything (`skip_unchanged`)
+/// doesn't rebuild.
+#[derive(Debug, Clone, Default)]
+pub struct ContentHashes {
+    hashes: HashMap<PathBuf, u64>,
+}
+
+impl ContentHashes {
+    /// Returns false if `path` has the same contents as when last seen. A file seen for the
+    /// first time, and one that can't be read (deleted, a directory), counts as changed.
+    pub fn changed(&mut self, path: &Path) -> bool {
+        use std::hash::{Hash, Hasher};
+
+        let Ok(bytes) = std::fs::read(path) else {
+            self.hashes.remove(path);
+            return true;
+        };
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        bytes.hash(&mut h);
+        self.hashes.insert(path.to_path_buf(), h.finish()) != Some(h.finish())
+    }
+}
+
+/// The set of files git knows about, used by `git_tracked_only`.
+#[derive(Debug, Clone)]
+pub struct GitTracked {
+    root: PathBuf,
+    include_untracked: bool,
+    files: HashSet<PathBuf>,
+}
+
+impl GitTracked {
+    /// Loads the tracked files of the repository containing `dir`.
+    pub fn load(dir: &Path, include_untracked: bool) -> Result<GitTracked> {
+        let out = Command::new("git")
+            .args(["rev-parse", "--show-toplevel"])
+            .current_dir(dir)
+            .stderr(Stdio::null())
+            .output()
+            .context("git rev-parse")?;
+        anyhow::ensure!(
+            out.status.success(),
+            "{:?} is not inside a git repository",
+            dir
+        );
+        let root = PathBuf::from(String::from_utf8_lossy(&out.stdout).trim());
+
+        let mut tracked = GitTracked {
+            root,
+            include_untracked,
+            files: HashSet::new(),
+        };
+        tracked.refresh()?;
+        Ok(tracked)
+    }
+
+    /// Re-reads `git ls-files`.
+    pub fn refresh(&mut self) -> Result<()> {
+        let mut c = Command::new("git");
+        c.args(["ls-files", "-z", "--full-name", "--cached"]);
+        if self.include_untracked {
+            c.args(["--others", "--exclude-standard"]);
+        }
+        let out = c
+            .current_dir(&self.root)
+            .stderr(Stdio::null())
+            .output()
+            .context("git ls-files")?;
+        anyhow::ensure!(
+            out.status.success(),
+            "git ls-files failed in {:?}",
+            self.root
+        );
+
+        self.files = out
+            .stdout
+            .split(|b| *b == 0)
+            .filter(|p| !p.is_empty())
+            .map(|p| self.root.join(String::from_utf8_lossy(p).as_ref()))
+            .collect();
+        Ok(())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        if path.is_absolute() {
+            return self.files.contains(path);
+        }
+        std::env::current_dir()
+            .map(|cwd| self.files.contains(&cwd.join(path)))
+            .unwrap_or(false)
+    }
+
+    /// Whether a change to `path` may change the tracked set (index or ignore rules).
+    pub fn needs_refresh(&self, path: &Path) -> bool {
+        path.ends_with(".gitignore") || path == self.root.join(".git").join("index")
+    }
+}
+
+/// `ENOSPC`, which inotify returns once `fs.inotify.max_user_watches` is used up.
+const ENOSPC: i32 = 28;
+
+/// Appended to watcher errors caused by running out of watches.
+pub const WATCH_LIMIT_HINT: &str = "the system ran out of file watches; raise the limit with \
+`sudo sysctl fs.inotify.max_user_watches=524288` (add it to /etc/sysctl.conf to keep it), \
+or watch less: narrow `watch` or add `ignore` globs for large directories";
+
+/// Whether a watcher error means the OS has no watches left. notify reports this as
+/// `MaxFilesWatch`, or as a bare ENOSPC ("No space left on device") from some paths.
+pub fn is_watch_limit_error(err: &notify::Error) -> bool {
+    match &err.kind {
+        notify::ErrorKind::MaxFilesWatch => true,
+        notify::ErrorKind::Io(e) => e.raw_os_error() == Some(ENOSPC),
+        notify::ErrorKind::Generic(msg) => {
+            msg.contains("No space left on device") || msg.contains("os error 28")
+        }
+        _ => false,
+    }
+}
+
+/// A watcher error for the log, with [`WATCH_LIMIT_HINT`] when it's the watch limit.
+pub fn describe_watch_error(err: &notify::Error) -> String {
+    if is_watch_limit_error(err) {
+        format!("{:#}: {}", err, WATCH_LIMIT_HINT)
+    } else {
+        format!("{:#}", err)
+    }
+}
+
+/// Decides whether startup goes on after registering the watch paths. `watched` is how
+/// many were registered and `failures` the paths `watch` rejected, with the error.
+/// Without `keep_going` any failure aborts. Nothing watched aborts too, unless
+/// `keep_going` and rair can be triggered another way (`externally_driven`: a control
+/// socket, trigger file or HTTP trigger).
+pub fn check_watch_setup(
+    watched: usize,
+    failures: &[(PathBuf, String)],
+    keep_going: bool,
+    externally_driven: bool,
+) -> Result<(), RairError> {
+    if let Some((path, err)) = failures.first().filter(|_| !keep_going) {
+        return Err(RairError::WatchSetup {
+            path: path.clone(),
+            message: err.clone(),
+        });
+    }
+    if watched == 0 && !(keep_going && externally_driven) {
+        return Err(RairError::NoWatchPaths);
+    }
+    Ok(())
+}
+
+/// Counts watcher recreations after its event channel closed (`watcher_restarts`).
+/// Events arriving again reset the count, so only back-to-back failures add up.
+#[derive(Debug, Clone)]
+pub struct WatcherRestarts {
+    limit: u32,
+    used: u32,
+}
+
+impl WatcherRestarts {
+    pub fn new(limit: u32) -> Self {
+        WatcherRestarts { limit, used: 0 }
+    }
+
+    /// The number of the next recreation attempt, or None once `limit` is used up.
+    pub fn attempt(&mut self) -> Option<u32> {
+        if self.used >= self.limit {
+            return None;
+        }
+        self.used += 1;
+        Some(self.used)
+    }
+
+    /// The watcher delivered an event: it works again.
+    pub fn recovered(&mut self) {
+        self.used = 0;
+    }
+
+    pub fn used(&self) -> u32 {
+        self.used
+    }
+}
+
+/// Watch paths that did not exist at startup; retried until they appear.
+#[derive(Debug, Clone, Default)]
+pub struct MissingWatches {
+    paths: Vec<PathBuf>,
+}
+
+impl MissingWatches {
+    pub fn push(&mut self, path: PathBuf) {
+        if !self.paths.contains(&path) {
+            self.paths.push(path);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Removes and returns the paths that exist now, leaving the rest pending.
+    pub fn take_present(&mut self) -> Vec<PathBuf> {
+        let (present, missing) = self.paths.drain(..).partition(|p: &PathBuf| p.exists());
+        self.paths = missing;
+        present
+    }
+}
+
+/// A resolved `[[transform]]` entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct Transform {
+    pub glob: String,
+    #[serde(skip)]
+    matcher: globset::GlobMatcher,
+    pub run: Vec<String>,
+}
+
+impl Transform {
+    pub fn new(glob: &str, run: Vec<String>) -> Result<Self, RairError> {
+        ensure_config!(!run.is_empty(), "transform {:?} has an empty `run`", glob);
+        let matcher = Glob::new(glob)
+            .map_err(|source| RairError::BadGlob {
+                glob: glob.to_string(),
+                source,
+            })?
+            .compile_matcher();
+        Ok(Self {
+            glob: glob.to_string(),
+            matcher,
+            run,
+        })
+    }
+
+    /// Whether a change to `rel` (relative to the project root) runs this transform.
+    pub fn matches(&self, rel: &Path) -> bool {
+        self.matcher.is_match(rel)
+    }
+}
+
+/// A resolved `[[asset_sync]]` entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetSync {
+    pub glob: String,
+    #[serde(skip)]
+    matcher: globset::GlobMatcher,
+    /// The glob's leading literal directories (`static` for `static/**/*.css`), which
+    /// aren't repeated under `dest`.
+    #[serde(skip)]
+    base: PathBuf,
+    pub dest: PathBuf,
+}
+
+impl AssetSync {
+    pub fn new(glob: &str, dest: &str) -> Result<Self, RairError> {
+        let matcher = Glob::new(glob)
+            .map_err(|source| RairError::BadGlob {
+                glob: glob.to_string(),
+                source,
+            })?
+            .compile_matcher();
+        let dirs: Vec<&str> = glob.split('/').collect();
+        let base = dirs[..dirs.len() - 1]
+            .iter()
+            .take_while(|d| !d.contains(['*', '?', '[', '{']))
+            .collect();
+        Ok(Self {
+            glob: glob.to_string(),
+            matcher,
+            base,
+            dest: PathBuf::from(dest),
+        })
+    }
+
+    /// Where a file at `rel` (relative to the project root) goes, if this rule covers it.
+    /// Files already under `dest` are left alone, so copies never trigger more copies.
+    pub fn target(&self, rel: &Path) -> Option<PathBuf> {
+        if !self.matcher.is_match(rel) || rel.starts_with(&self.dest) {
+            return None;
+        }
+        let below = rel.strip_prefix(&self.base).ok()?;
+        Some(self.dest.join(below))
+    }
+
+    /// Copies `path` to its target under `root`, or removes the target if `path` is gone.
+    /// `None` if the rule doesn't cover `path` or it's a directory.
+    pub fn sync(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>> {
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let Some(target) = self.target(rel) else {
+            return Ok(None);
+        };
+        let (src, dest) = (root.join(rel), root.join(target));
+        if src.is_file() {
+            if let Some(dir) = dest.parent() {
+                std::fs::create_dir_all(dir).with_context(|| format!("create {:?}", dir))?;
+            }
+            std::fs::copy(&src, &dest).with_context(|| format!("copy {:?} to {:?}", src, dest))?;
+        } else if src.exists() {
+            return Ok(None);
+        } else {
+            match std::fs::remove_file(&dest) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("remove {:?}", dest));
+                }
+                _ => {}
+            }
+        }
+        Ok(Some(dest))
+    }
+}
+
+/// What to register with the watcher for the `watch` entry `entry`: a directory
+/// recursively, a single file through its parent directory, non-recursively. FSEvents only
+/// watches directories, and inotify loses a watch on a file that an editor replaces by
+/// renaming over it. `None` when a directory in `all` already covers the file.
+pub fn watch_registration(
+    entry: &Path,
+    all: &[PathBuf],
+) -> Option<(PathBuf, notify::RecursiveMode)> {
+    if !entry.is_file() {
+        return Some((entry.to_path_buf(), notify::RecursiveMode::Recursive));
+    }
+    let file = absolute_path(entry);
+    if all
+        .iter()
+        .any(|d| d.is_dir() && file.starts_with(absolute_path(d)))
+    {
+        return None;
+    }
+    let dir = file
+        .parent()
+        .map_or_else(|| file.clone(), Path::to_path_buf);
+    Some((dir, notify::RecursiveMode::NonRecursive))
+}
+
+/// Symlinked directories among the watch paths: a `watch` entry that is itself a link, and
+/// with `follow_symlinks` the links to directories below the entries. Events can arrive
+/// under a link's target (FSEvents reports resolved paths, and followed targets are watched
+/// directly), so they're mapped back to the linked path that globs and filters expect.
+#[derive(Debug, Clone, Default)]
+pub struct SymlinkMap {
+    /// (canonical target, link path), deepest target first.
+    links: Vec<(PathBuf, PathBuf)>,
+    /// Targets of links below the entries, to watch in addition to them.
+    followed: Vec<PathBuf>,
+}
+
+impl SymlinkMap {
+    /// Resolves the directory entries in `watch`; with `follow`, also walks them (skipping
+    /// what `.gitignore` skips) for symlinked directories, and those for further links.
+    pub fn scan(watch: &[PathBuf], follow: bool) -> Self {
+        let mut map = SymlinkMap::default();
+        let mut seen = HashSet::new();
+        let mut pending: Vec<(PathBuf, bool)> =
+            watch.iter().map(|w| (absolute_path(w), false)).collect();
+        while let Some((dir, nested)) = pending.pop() {
+            let Ok(target) = dir.canonicalize() else {
+                continue;
+            };
+            if !target.is_dir() || !seen.insert(target.clone()) {
+                continue;
+            }
+            if target != dir {
+                map.links.push((target.clone(), dir.clone()));
+            }
+            if nested {
+                map.followed.push(target);
+            }
+            if !follow {
+                continue;
+            }
+            for entry in ignore::WalkBuilder::new(&dir).build().flatten() {
+                if entry.depth() > 0 && entry.path_is_symlink() && entry.path().is_dir() {
+                    pending.push((entry.into_path(), true));
+                }
+            }
+        }
+        map.links
+            .sort_by_key(|(target, _)| std::cmp::Reverse(target.components().count()));
+        map
+    }
+
+    /// Directories to watch recursively besides the `watch` entries.
+    pub fn followed(&self) -> &[PathBuf] {
+        &self.followed
+    }
+
+    /// `path` as seen through the link it came in under, or unchanged.
+    pub fn link_path(&self, path: &Path) -> PathBuf {
+        for (target, link) in &self.links {
+            if let Ok(rest) = path.strip_prefix(target) {
+                return link.join(rest);
+            }
+        }
+        path.to_path_buf()
+    }
+}
+
+/// The `watch` entries, for narrowing events from a directory watched on behalf of a
+/// single file down to that file.
+#[derive(Debug, Clone, Default)]
+pub struct WatchSet {
+    /// Each entry as given (made absolute) and canonicalized, since FSEvents reports
+    /// resolved paths (`/private/var/...` for `/var/...`).
+    roots: Vec<PathBuf>,
+    has_files: bool,
+    extra_watches: bool,
+}
+
+impl WatchSet {
+    pub fn new(entries: &[PathBuf]) -> Self {
+        let mut set = Self::default();
+        for entry in entries {
+            set.has_files |= entry.is_file();
+            let abs = absolute_path(entry);
+            if let Ok(canonical) = abs.canonicalize() {
+                if canonical != abs {
+                    set.roots.push(canonical);
+                }
+            }
+            set.roots.push(abs);
+        }
+        set
+    }
+
+    /// Something besides the entries is watched too (the config file's directory), so
+    /// events can't be assumed to come from the entries.
+    pub fn with_extra_watches(mut self, extra: bool) -> Self {
+        self.extra_watches = extra;
+        self
+    }
+
+    /// Whether an event for `path` belongs to one of the entries: inside a watched
+    /// directory, or exactly a watched file. Directories are watched recursively and only
+    /// report their own contents, so with no file entries everything matches.
+    pub fn contains(&self, path: &Path) -> bool {
+        if !self.has_files && !self.extra_watches {
+            return true;
+        }
+        let path = absolute_path(path);
+        self.roots.iter().any(|r| path.starts_with(r))
+    }
+}
+
+pub fn cmd_from_argv(argv: &[String]) -> Result<Command> {
+    anyhow::ensure!(!argv.is_empty(), "command argv cannot be empty");
+    let mut c = Command::new(&argv[0]);
+    if argv.len() > 1 {
+        c.args(&argv[1..]);
+    }
+    Ok(c)
+}
+
+/// The final run argv: the explicit `run`, or `default_run` (the built binary),
+/// prefixed with the configured `runner` and followed by `run_args`.
+pub fn resolve_run_argv<F>(eff: &EffectiveConfig, default_run: F) -> Result<Vec<String>>
+where
+    F: FnOnce(&EffectiveConfig) -> Result<Vec<String>>,
+{
+    let argv = match &eff.run {
+        Some(v) => v.clone(),
+        None => default_run(eff)?,
+    };
+    let mut full = eff.runner.clone();
+    full.extend(argv);
+    full.extend(eff.run_args.iter().cloned());
+    Ok(full)
+}
+
+/// The binary a cycle runs, for `{exe}` in `post_build` hooks: the program of an explicit
+/// `run`, else the built binary (`default_run`). The `runner` isn't part of it.
+pub fn resolve_exe<F>(eff: &EffectiveConfig, default_run: F) -> Result<String>
+where
+    F: FnOnce(&EffectiveConfig) -> Result<Vec<String>>,
+{
+    let argv = match &eff.run {
+        Some(v) => v.clone(),
+        None => default_run(eff)?,
+    };
+    argv.into_iter().next().context("empty run argv")
+}
+
+/// Builds the build command (without stdio setup) from its argv.
+pub fn build_command(argv: &[String], eff: &EffectiveConfig) -> Result<Command> {
+    let mut c = cmd_from_argv(argv)?;
+    if let Some(dir) = &eff.build_cwd {
+        c.current_dir(dir);
+    }
+    Ok(c)
+}
+
+/// Builds the run command (without stdio setup) from the resolved argv.
+pub fn run_command(argv: &[String], eff: &EffectiveConfig) -> Result<Command> {
+    let mut c = cmd_from_argv(argv)?;
+
+    // read on every start, so edits apply with the next restart; what rair sets wins
+    if let Some(path) = &eff.env_file {
+        let vars = dotenvy::from_path_iter(path).with_context(|| format!("env_file {:?}", path))?;
+        for var in vars {
+            let (key, value) = var.with_context(|| format!("env_file {:?}", path))?;
+            c.env(key, value);
+        }
+    }
+
+    // Set environment variable to prevent recursive watching
+    c.env("RAIR_ACTIVE", "1");
+
+    if let Some(b) = &eff.backtrace {
+        c.env("RUST_BACKTRACE", b);
+    }
+    if let Some(dir) = &eff.run_cwd {
+        c.current_dir(dir);
+    }
+    Ok(c)
+}
+
+/// What `cargo metadata` says about where the built binary lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CargoTarget {
+    pub target_dir: PathBuf,
+    /// Name of the root package, if the manifest has one (virtual workspaces don't).
+    pub root_package: Option<String>,
+    /// Names of each workspace package's bin targets, for `resolve_bin_name`.
+    pub package_bins: HashMap<String, Vec<String>>,
+}
+
+pub fn cargo_target(manifest_path: Option<&Path>) -> Result<CargoTarget, RairError> {
+    let mut cmd = MetadataCommand::new();
+    if let Some(mp) = manifest_path {
+        cmd.manifest_path(mp);
+    }
+    let md = cmd.exec().map_err(RairError::Metadata)?;
+    let package_bins = md
+        .workspace_packages()
+        .into_iter()
+        .map(|pkg| {
+            let bins = pkg.targets.iter().filter(|t| t.is_bin());
+            (pkg.name.clone(), bins.map(|t| t.name.clone()).collect())
+        })
+        .collect();
+    Ok(CargoTarget {
+        target_dir: md.target_directory.clone().into_std_path_buf(),
+        root_package: md.root_package().map(|p| p.name.clone()),
+        package_bins,
+    })
+}
+
+/// `src` and `Cargo.toml` of every workspace member, from `cargo metadata`.
+pub fn workspace_watch_paths(manifest_path: Option<&Path>) -> Result<Vec<PathBuf>, RairError> {
+    let mut cmd = MetadataCommand::new();
+    if let Some(mp) = manifest_path {
+        cmd.manifest_path(mp);
+    }
+    cmd.no_deps();
+    let md = cmd.exec().map_err(RairError::Metadata)?;
+
+    let mut paths = Vec::new();
+    for pkg in md.workspace_packages() {
+        let manifest = pkg.manifest_path.clone().into_std_path_buf();
+        if let Some(dir) = manifest.parent() {
+            paths.push(dir.join("src"));
+        }
+        paths.push(manifest);
+    }
+    Ok(paths)
+}
+
+/// One workspace target, as listed by `--list-targets`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Target {
+    pub package: String,
+    pub name: String,
+    /// "bin", "lib", "example", "test", "bench", ...
+    pub kind: String,
+}
+
+/// Every target of the workspace's packages, one entry per kind, sorted by package,
+/// name and kind.
+pub fn list_targets(manifest_path: Option<&Path>) -> Result<Vec<Target>, RairError> {
+    let mut cmd = MetadataCommand::new();
+    if let Some(mp) = manifest_path {
+        cmd.manifest_path(mp);
+    }
+    cmd.no_deps();
+    let md = cmd.exec().map_err(RairError::Metadata)?;
+
+    let mut targets: Vec<Target> = md
+        .workspace_packages()
+        .into_iter()
+        .flat_map(|pkg| {
+            pkg.targets.iter().flat_map(move |t| {
+                t.kind.iter().map(move |kind| Target {
+                    package: pkg.name.clone(),
+                    name: t.name.clone(),
+                    kind: kind.clone(),
+                })
+            })
+        })
+        .collect();
+    targets.sort();
+    Ok(targets)
+}
+
+/// Names of the workspace's targets of `kind` ("bin", "example", ...), sorted, for shell
+/// completion of `--bin` values.
+pub fn target_names(manifest_path: Option<&Path>, kind: &str) -> Result<Vec<String>, RairError> {
+    let mut names: Vec<String> = list_targets(manifest_path)?
+        .into_iter()
+        .filter(|t| t.kind == kind)
+        .map(|t| t.name)
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Whether a `bin` value is a glob over the binary targets rather than one name.
+fn is_target_glob(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+/// The workspace's binary targets whose names match the glob `pattern`, sorted.
+pub fn expand_bin_glob(
+    manifest_path: Option<&Path>,
+    pattern: &str,
+) -> Result<Vec<String>, RairError> {
+    let glob = Glob::new(pattern)
+        .map_err(|e| RairError::BadGlob {
+            glob: pattern.to_string(),
+            source: e,
+        })?
+        .compile_matcher();
+    Ok(target_names(manifest_path, "bin")?
+        .into_iter()
+        .filter(|name| glob.is_match(name))
+        .collect())
+}
+
+/// Crate name rustc derives for a standalone file: its stem with `-` replaced.
+pub fn file_crate_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().replace('-', "_"))
+        .unwrap_or_default()
+}
+
+/// Files that an incremental files-mode build compiles, in order. The first file is the
+/// binary's crate root and is compiled last; each of the others is a library crate that
+/// can use the ones listed before it. A stale library recompiles every later one, and the
+/// root relinks whenever anything was recompiled.
+pub fn files_to_recompile(files: &[PathBuf], is_stale: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let Some((root, libs)) = files.split_first() else {
+        return Vec::new();
+    };
+    let mut stale: Vec<PathBuf> = match libs.iter().position(|f| is_stale(f)) {
+        Some(first) => libs[first..].to_vec(),
+        None => Vec::new(),
+    };
+    if !stale.is_empty() || is_stale(root) {
+        stale.push(root.clone());
+    }
+    stale
+}
+
+/// Files mode with `--incremental` (`rair --incremental main.rs util.rs`): every file
+/// after the first is compiled to an rlib in `cache_dir`, next to a hash of its inputs
+/// (the file and the `mod` files rustc's dep-info lists for it), and only recompiled
+/// when one of those changes (see [`files_to_recompile`]). Each file is a crate of its
+/// own, used with `use name::...`; a file another one pulls in with `mod` isn't listed.
+#[derive(Debug, Clone)]
+pub struct IncrementalFiles {
+    pub files: Vec<PathBuf>,
+    /// The binary built from the first file.
+    pub out: PathBuf,
+    pub cache_dir: PathBuf,
+}
+
+impl IncrementalFiles {
+    pub fn new(files: Vec<PathBuf>, out: PathBuf) -> Self {
+        let cache_dir = out.with_extension("d");
+        IncrementalFiles {
+            files,
+            out,
+            cache_dir,
+        }
+    }
+
+    fn is_root(&self, file: &Path) -> bool {
+        self.files.first().is_some_and(|f| f == file)
+    }
+
+    fn output(&self, file: &Path) -> PathBuf {
+        if self.is_root(file) {
+            return self.out.clone();
+        }
+        self.cache_dir
+            .join(format!("lib{}.rlib", file_crate_name(file)))
+    }
+
+    fn hash_path(&self, file: &Path) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}.hash", file_crate_name(file)))
+    }
+
+    fn dep_info_path(&self, file: &Path) -> PathBuf {
+        self.cache_dir.join(format!("{}.d", file_crate_name(file)))
+    }
+
+    fn hash(file: &Path) -> std::io::Result<String> {
+        use std::hash::{Hash, Hasher};
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        std::fs::read(file)?.hash(&mut h);
+        Ok(format!("{:016x}", h.finish()))
+    }
+
+    /// Whether `file` or one of its `mod` files changed since it was last compiled, or
+    /// its output is gone.
+    pub fn is_stale(&self, file: &Path) -> bool {
+        if !self.output(file).is_file() {
+            return true;
+        }
+        let Ok(stored) = std::fs::read_to_string(self.hash_path(file)) else {
+            return true;
+        };
+        let mut inputs = stored.lines().map(|l| l.split_once(' ')).peekable();
+        inputs.peek().is_none()
+            || inputs.any(|input| match input {
+                Some((hash, path)) => Self::hash(Path::new(path)).ok().as_deref() != Some(hash),
+                None => true,
+            })
+    }
+
+    /// Remembers the contents of `file` and the inputs rustc reported for it, after it
+    /// compiled.
+    pub fn record(&self, file: &Path) -> std::io::Result<()> {
+        let mut inputs = vec![file.to_path_buf()];
+        if let Ok(dep_info) = std::fs::read_to_string(self.dep_info_path(file)) {
+            inputs.extend(
+                dep_info_inputs(&dep_info)
+                    .into_iter()
+                    .filter(|p| !same_path(p, file)),
+            );
+        }
+        let mut stored = String::new();
+        for input in inputs {
+            stored.push_str(&format!("{} {}\n", Self::hash(&input)?, input.display()));
+        }
+        std::fs::write(self.hash_path(file), stored)
+    }
+
+    /// Compiles the stale files in order with `run`, which runs one rustc argv and says
+    /// whether it succeeded. Stops at the first failure; Ok(true) if all compiled.
+    pub fn compile(&self, mut run: impl FnMut(&[String]) -> Result<bool>) -> Result<bool> {
+        std::fs::create_dir_all(&self.cache_dir)
+            .with_context(|| format!("create {:?}", self.cache_dir))?;
+        for file in files_to_recompile(&self.files, |f| self.is_stale(f)) {
+            if !run(&self.rustc_argv(&file))? {
+                return Ok(false);
+            }
+            self.record(&file)
+                .with_context(|| format!("record {:?}", file))?;
+        }
+        Ok(true)
+    }
+
+    /// The rustc argv for `file`, with `--extern` for every library it may use.
+    pub fn rustc_argv(&self, file: &Path) -> Vec<String> {
+        let libs = &self.files[1..];
+        let deps = if self.is_root(file) {
+            libs
+        } else {
+            &libs[..libs.iter().position(|f| f == file).unwrap_or(libs.len())]
+        };
+
+        let mut argv = vec!["rustc".to_string(), file.to_string_lossy().into_owned()];
+        if !self.is_root(file) {
+            argv.push("--crate-type=rlib".into());
+            argv.push(format!("--crate-name={}", file_crate_name(file)));
+        }
+        argv.push("-L".into());
+        argv.push(self.cache_dir.to_string_lossy().into_owned());
+        for dep in deps {
+            argv.push("--extern".into());
+            argv.push(format!(
+                "{}={}",
+                file_crate_name(dep),
+                self.output(dep).to_string_lossy()
+            ));
+        }
+        argv.push(format!(
+            "--emit=link,dep-info={}",
+            self.dep_info_path(file).display()
+        ));
+        argv.push("-o".into());
+        argv.push(self.output(file).to_string_lossy().into_owned());
+        argv
+    }
+}
+
+/// The source files a rustc dep-info file (`--emit=dep-info`) lists for its output.
+pub fn dep_info_inputs(dep_info: &str) -> Vec<PathBuf> {
+    let Some(deps) = dep_info
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .and_then(|l| l.split_once(": "))
+        .map(|(_, deps)| deps)
+    else {
+        return Vec::new();
+    };
+    // paths are separated by spaces; a space within one is escaped
+    let mut inputs = Vec::new();
+    let mut current = String::new();
+    let mut chars = deps.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => current.extend(chars.next()),
+            ' ' if !current.is_empty() => inputs.push(PathBuf::from(std::mem::take(&mut current))),
+            ' ' => {}
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        inputs.push(PathBuf::from(current));
+    }
+    inputs
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Appends `extra` to `watch`, skipping paths already present (compared canonically).
+pub fn add_watch_paths(watch: &mut Vec<PathBuf>, extra: Vec<PathBuf>) {
+    for p in extra {
+        if !watch.iter().any(|w| same_path(w, &p)) {
+            watch.push(p);
+        }
+    }
+}
+
+/// The manifest and lock file of an `extra_manifests` entry: the entry itself, or
+/// `Cargo.toml` inside it when it's a directory, and the `Cargo.lock` next to that.
+fn extra_manifest_files(entry: &Path) -> Result<Vec<PathBuf>, RairError> {
+    let manifest = if entry.is_dir() {
+        entry.join("Cargo.toml")
+    } else {
+        entry.to_path_buf()
+    };
+    ensure_config!(
+        manifest.is_file(),
+        "extra_manifests: {:?} is not a manifest or a directory with a Cargo.toml",
+        entry
+    );
+    let lock = manifest.with_file_name("Cargo.lock");
+    Ok(vec![manifest, lock])
+}
+
+/// How a path is compared against event paths however either was written: the canonical
+/// parent directory joined with the file name, so a file that doesn't exist (a lock file
+/// not created yet, a file just removed) still matches.
+pub fn path_key(path: &Path) -> PathBuf {
+    let file = absolute_path(path);
+    match (file.parent().map(Path::canonicalize), file.file_name()) {
+        (Some(Ok(dir)), Some(name)) => dir.join(name),
+        _ => file,
+    }
+}
+
+/// True for `Cargo.toml`; a change means cached cargo metadata is stale.
+pub fn is_manifest_path(path: &Path) -> bool {
+    path.ends_with("Cargo.toml")
+}
+
+/// The binary to run: `bin` (or the one binary its glob matched), else the sole bin
+/// target of `package` (or the root package), whatever it's called, else that package's
+/// name, else the cwd name.
+pub fn resolve_bin_name(eff: &EffectiveConfig, target: &CargoTarget) -> Result<String> {
+    if let [b] = eff.bins.as_slice() {
+        return Ok(b.clone());
+    }
+    if let Some(b) = &eff.bin {
+        return Ok(b.clone());
+    }
+    if let Some(p) = eff.package.as_ref().or(target.root_package.as_ref()) {
+        if let Some([b]) = target.package_bins.get(p).map(Vec::as_slice) {
+            return Ok(b.clone());
+        }
+        return Ok(p.clone());
+    }
+    let cwd = std::env::current_dir().context("cwd")?;
+    let name = cwd
+        .file_name()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow::anyhow!("cannot infer bin name; specify --bin or config bin"))?;
+    Ok(name.to_string())
+}
+
+/// Run argv for the built binary when no explicit `run` is configured.
+pub fn default_run_argv(eff: &EffectiveConfig, target: &CargoTarget) -> Result<Vec<String>> {
+    let bin = resolve_bin_name(eff, target)?;
+    let target_dir = eff.target_dir.as_deref().unwrap_or(&target.target_dir);
+    let exe = exe_path(target_dir, eff.release, &bin);
+    Ok(vec![exe.to_string_lossy().to_string()])
+}
+
+pub fn exe_name(bin: &str) -> String {
+    #[cfg(windows)]
+    {
+        format!("{}.exe", bin)
+    }
+
+    #[cfg(not(windows))]
+    {
+        bin.to_string()
+    }
+}
+
+pub fn exe_path(target_dir: &Path, release: bool, bin: &str) -> PathBuf {
+    let profile = if release { "release" } else { "debug" };
+    target_dir.join(profile).join(exe_name(bin))
+}
+
+/// Runs a list of hooks in order; `parallel` groups run their commands concurrently.
+/// Returns Ok(true) if all commands succeed, Ok(false) if any fails.
+pub fn run_hook_list(name: &str, hooks: &[Hook]) -> Result<bool> {
+    run_hook_list_with_env(name, hooks, &[])
+}
+
+/// Environment passed to `on_build_fail` hooks describing the failed build.
+/// `error_count` is known only when the diagnostics were parsed (`parse_diagnostics`).
+pub fn build_fail_env(
+    build: &[String],
+    exit_code: Option<i32>,
+    error_count: Option<usize>,
+) -> Vec<(String, String)> {
+    vec![
+        ("RAIR_BUILD_CMD".into(), build.join(" ")),
+        (
+            "RAIR_EXIT_CODE".into(),
+            exit_code.map(|c| c.to_string()).unwrap_or_default(),
+        ),
+        (
+            "RAIR_ERROR_COUNT".into(),
+            error_count.map(|n| n.to_string()).unwrap_or_default(),
+        ),
+    ]
+}
+
+/// Like `run_hook_list`, but sets extra environment variables on every hook.
+pub fn run_hook_list_with_env(
+    name: &str,
+    hooks: &[Hook],
+    env: &[(String, String)],
+) -> Result<bool> {
+    run_hooks(name, hooks, env, &mut Timeline::default())
+}
+
+/// Like `run_hook_list`, but records how long the hooks took in `timeline`: under
+/// `name` for a single hook, `name[i]` for each of several.
+pub fn run_hook_list_timed(name: &str, hooks: &[Hook], timeline: &mut Timeline) -> Result<bool> {
+    run_hooks(name, hooks, &[], timeline)
+}
+
+fn run_hooks(
+    name: &str,
+    hooks: &[Hook],
+    env: &[(String, String)],
+    timeline: &mut Timeline,
+) -> Result<bool> {
+    for (i, hook) in hooks.iter().enumerate() {
+        let started = Instant::now();
+        let ok = match hook {
+            Hook::Command(argv) => {
+                let label = format!("{}[{}]", name, i);
+                hook_command(&label, argv, env)?
+                    .status()
+                    .with_context(|| format!("hook {}: {:?}", label, argv))?
+                    .success()
+            }
+            Hook::Parallel { parallel } => run_parallel_hooks(name, i, parallel, env)?,
+        };
+        match hooks.len() {
+            1 => timeline.record(name, started.elapsed()),
+            _ => timeline.record(format!("{}[{}]", name, i), started.elapsed()),
+        }
+        if !ok {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn hook_command(label: &str, argv: &[String], env: &[(String, String)]) -> Result<Command> {
+    anyhow::ensure!(!argv.is_empty(), "hook {} argv is empty", label);
+    let mut c = Command::new(&argv[0]);
+    c.args(&argv[1..])
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    Ok(c)
+}
+
+/// Spawns every command of a parallel group, then waits for all of them.
+fn run_parallel_hooks(
+    name: &str,
+    index: usize,
+    group: &[Vec<String>],
+    env: &[(String, String)],
+) -> Result<bool> {
+    let mut children = Vec::with_capacity(group.len());
+    let mut spawn_err = None;
+    for (j, argv) in group.iter().enumerate() {
+        let label = format!("{}[{}][{}]", name, index, j);
+        let spawned = hook_command(&label, argv, env).and_then(|mut c| {
+            c.spawn()
+                .with_context(|| format!("hook {}: {:?}", label, argv))
+        });
+        match spawned {
+            Ok(child) => children.push(child),
+            Err(e) => {
+                spawn_err = Some(e);
+                break;
+            }
+        }
+    }
+    if spawn_err.is_some() {
+        for child in &mut children {
+            let _ = child.kill();
+        }
+    }
+    // Always reap what was started, even when a sibling failed
+    let mut ok = true;
+    for mut child in children {
+        ok &= child.wait().map(|s| s.success()).unwrap_or(false);
+    }
+    match spawn_err {
+        Some(e) => Err(e),
+        None => Ok(ok),
+    }
+}
+
+/// Calls `probe` until it returns true, at most `retries` times, sleeping `interval` between tries.
+pub fn wait_until_ready(mut probe: impl FnMut() -> bool, interval: Duration, retries: u32) -> bool {
+    for attempt in 0..retries.max(1) {
+        if attempt > 0 {
+            std::thread::sleep(interval);
+        }
+        if probe() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Runs a health check command quietly; exit 0 means ready.
+pub fn run_health_check(argv: &[String]) -> bool {
+    let Ok(mut c) = cmd_from_argv(argv) else {
+        return false;
+    };
+    c.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Tries a TCP connection to `host:port`, giving each resolved address up to `timeout`;
+/// true once one accepts. No subprocess, unlike `run_health_check`.
+pub fn probe_tcp_port(host: &str, port: u16, timeout: Duration) -> bool {
+    use std::net::{TcpStream, ToSocketAddrs};
+
+    let Ok(addrs) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok())
+}
+
+/// Waits for the run process to become ready, then runs the `post_ready` hooks.
+/// `ready` is the readiness already known, e.g. from an `overlap_restart` swap; only
+/// None probes. Without a `health_check` or `ready_port` the process counts as ready as
+/// soon as it was spawned. `current` says whether this is still the latest cycle: once
+/// it isn't, the probe gives up and the hooks don't run. Returns Ok(false) if readiness
+/// never came, a newer cycle took over or a hook failed.
+pub fn run_post_ready(
+    eff: &EffectiveConfig,
+    ready: Option<bool>,
+    current: impl Fn() -> bool,
+) -> Result<bool> {
+    let ready =
+        ready.unwrap_or_else(|| !eff.has_readiness_probe() || eff.wait_until_ready_while(&current));
+    if !ready || !current() {
+        return Ok(false);
+    }
+    run_hook_list("post_ready", &eff.post_ready)
+}
+
+/// Numbers the cycles' `post_ready` runs, which go on in the background: a probe from an
+/// earlier cycle must not take the next process becoming ready for its own.
+#[derive(Debug, Clone, Default)]
+pub struct PostReadyGeneration(Arc<std::sync::atomic::AtomicU64>);
+
+impl PostReadyGeneration {
+    /// Starts a new cycle's run, making every earlier one stale.
+    pub fn next(&self) -> u64 {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1
+    }
+
+    pub fn is_current(&self, generation: u64) -> bool {
+        self.0.load(std::sync::atomic::Ordering::SeqCst) == generation
+    }
+}
+
+/// One step of swapping the running process for a freshly built one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartStep {
+    StopOld,
+    StartNew,
+    /// Wait for the new process's health check; abandon the swap if it never passes.
+    AwaitReady,
+}
+
+/// The order of restart steps: stop-then-start normally, or start, wait for
+/// readiness, then stop when `overlap` is set (zero-downtime restarts).
+pub fn restart_steps(overlap: bool, has_previous: bool) -> Vec<RestartStep> {
+    match (has_previous, overlap) {
+        (false, _) => vec![RestartStep::StartNew],
+        (true, false) => vec![RestartStep::StopOld, RestartStep::StartNew],
+        (true, true) => vec![
+            RestartStep::StartNew,
+            RestartStep::AwaitReady,
+            RestartStep::StopOld,
+        ],
+    }
+}
+
+/// Where a rebuild cycle is when deciding whether to clear the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildOutcome {
+    /// About to build; the result isn't known yet.
+    Pending,
+    /// Build succeeded; the new run process is about to start.
+    Succeeded,
+    /// Build failed.
+    Failed,
+}
+
+/// Whether to clear the screen at this point of the cycle.
+/// `clear` clears right before a new run; `clear_on_fail` also clears before
+/// each build. A failed build's output is never cleared.
+fn should_clear(clear: bool, clear_on_fail: bool, outcome: BuildOutcome) -> bool {
+    match outcome {
+        BuildOutcome::Pending => clear_on_fail,
+        BuildOutcome::Succeeded => clear,
+        BuildOutcome::Failed => false,
+    }
+}
+
+/// When `clear` clears the screen during a cycle (`clear_before`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClearBefore {
+    Build,
+    #[default]
+    Run,
+    Never,
+}
+
+impl ClearBefore {
+    pub fn parse(s: &str) -> Result<ClearBefore, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "build" => Ok(ClearBefore::Build),
+            "run" => Ok(ClearBefore::Run),
+            "never" => Ok(ClearBefore::Never),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown clear_before {:?} (expected \"build\", \"run\" or \"never\")",
+                other
+            ))),
+        }
+    }
+}
+
+/// When to ring the terminal bell after a build (`bell`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bell {
+    #[default]
+    Off,
+    OnFail,
+    OnSuccess,
+    Always,
+}
+
+impl Bell {
+    pub fn parse(s: &str) -> Result<Bell, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(Bell::Off),
+            "on_fail" => Ok(Bell::OnFail),
+            "on_success" => Ok(Bell::OnSuccess),
+            "always" => Ok(Bell::Always),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown bell {:?} (expected \"off\", \"on_fail\", \"on_success\" or \"always\")",
+                other
+            ))),
+        }
+    }
+
+    /// What to write to the terminal after a build: one BEL for a result this setting
+    /// rings for, two when a build succeeds after a failed one (unless the bell is off).
+    pub fn cue(self, succeeded: bool, after_failure: bool) -> &'static str {
+        match (self, succeeded) {
+            (Bell::Off, _) => "",
+            (_, true) if after_failure => "\x07\x07",
+            (Bell::OnFail | Bell::Always, false) | (Bell::OnSuccess | Bell::Always, true) => "\x07",
+            _ => "",
+        }
+    }
+}
+
+/// Identifies a build artifact by modification time and size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArtifactStamp {
+    pub modified: std::time::SystemTime,
+    pub len: u64,
+}
+
+impl ArtifactStamp {
+    /// The stamp of the file at `path`, if it exists.
+    pub fn of(path: &Path) -> Option<ArtifactStamp> {
+        let meta = std::fs::metadata(path).ok().filter(|m| m.is_file())?;
+        Some(ArtifactStamp {
+            modified: meta.modified().ok()?,
+            len: meta.len(),
+        })
+    }
+}
+
+/// What a rebuild cycle does with the run process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunDecision {
+    /// Start the newly built binary in place of the running one.
+    Restart,
+    /// The build produced the binary that is already running.
+    KeepUnchanged,
+    /// The build failed.
+    KeepFailed,
+    /// The build failed; stop the running process (`on_fail_action = "stop"`).
+    StopFailed,
+    /// The build failed; start the last good build again (`"restart_last_good"`).
+    RestartLastGood,
+}
+
+impl RunDecision {
+    pub fn describe(self) -> &'static str {
+        match self {
+            RunDecision::Restart => "restarting",
+            RunDecision::KeepUnchanged => "binary unchanged; keeping existing process",
+            RunDecision::KeepFailed => "build failed; keeping existing process",
+            RunDecision::StopFailed => "build failed; stopping the running process",
+            RunDecision::RestartLastGood => "build failed; restarting the last good build",
+        }
+    }
+}
+
+/// Where the run process's stdin comes from (`run_stdin`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStdin {
+    /// rair's own stdin, for interactive programs.
+    #[default]
+    Inherit,
+    /// Empty: reads see end of file.
+    Null,
+    /// A pipe rair holds open without writing, for daemons that exit on end of file.
+    Pipe,
+}
+
+impl RunStdin {
+    pub fn parse(s: &str) -> Result<RunStdin, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "inherit" => Ok(RunStdin::Inherit),
+            "null" => Ok(RunStdin::Null),
+            "pipe" => Ok(RunStdin::Pipe),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown run_stdin {:?} (expected \"inherit\", \"null\" or \"pipe\")",
+                other
+            ))),
+        }
+    }
+
+    pub fn stdio(self) -> Stdio {
+        match self {
+            RunStdin::Inherit => Stdio::inherit(),
+            RunStdin::Null => Stdio::null(),
+            RunStdin::Pipe => Stdio::piped(),
+        }
+    }
+}
+
+/// What a failed build does to the run process (`on_fail_action`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnFailAction {
+    #[default]
+    Keep,
+    Stop,
+    RestartLastGood,
+}
+
+impl OnFailAction {
+    pub fn parse(s: &str) -> Result<OnFailAction, RairError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(OnFailAction::Keep),
+            "stop" => Ok(OnFailAction::Stop),
+            "restart_last_good" => Ok(OnFailAction::RestartLastGood),
+            other => Err(RairError::InvalidConfig(format!(
+                "unknown on_fail_action {:?} (expected \"keep\", \"stop\" or \"restart_last_good\")",
+                other
+            ))),
+        }
+    }
+}
+
+/// What a failed build does with the run process. `running` is whether one is up,
+/// `last_good` whether a successful build has been started before. Without anything to
+/// stop or restart it comes down to `KeepFailed`.
+pub fn on_fail_decision(action: OnFailAction, running: bool, last_good: bool) -> RunDecision {
+    match action {
+        OnFailAction::Stop if running => RunDecision::StopFailed,
+        OnFailAction::RestartLastGood if last_good => RunDecision::RestartLastGood,
+        _ => RunDecision::KeepFailed,
+    }
+}
+
+/// Whether to restart after a build, separate from whether to rebuild (`ContentHashes`):
+///
+/// | build     | built vs running binary         | decision        |
+/// |-----------|---------------------------------|-----------------|
+/// | failed    | -                               | `KeepFailed`    |
+/// | succeeded | different                       | `Restart`       |
+/// | succeeded | same                            | `KeepUnchanged` |
+/// | succeeded | unknown, or nothing running     | `Restart`       |
+///
+/// `running` is the stamp of the binary the live run process was started from, `built`
+/// that of the binary the build just produced; either is None when not known (e.g. an
+/// explicit `run` that isn't a file path).
+pub fn restart_decision(
+    build_succeeded: bool,
+    running: Option<ArtifactStamp>,
+    built: Option<ArtifactStamp>,
+) -> RunDecision {
+    if !build_succeeded {
+        return RunDecision::KeepFailed;
+    }
+    match (running, built) {
+        (Some(running), Some(built)) if running == built => RunDecision::KeepUnchanged,
+        _ => RunDecision::Restart,
+    }
+}
+
+/// Whether post_build, post_run and post_ready fire for a cycle that ended in `decision`.
+/// A build that left the running binary as it was is a no-op, and skips them unless
+/// `hooks_on_noop` is set.
+pub fn runs_post_hooks(decision: RunDecision, hooks_on_noop: bool) -> bool {
+    match decision {
+        RunDecision::Restart => true,
+        RunDecision::KeepUnchanged => hooks_on_noop,
+        RunDecision::KeepFailed | RunDecision::StopFailed | RunDecision::RestartLastGood => false,
+    }
+}
+
+/// When to restart a run process that exited on its own (`restart_on_exit`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitRestart {
+    Now,
+    /// Still within `crash_cooldown_ms` of the exit; this much of it is left.
+    Wait(Duration),
+}
+
+/// A relevant file change restarts right away, since the code has likely been fixed.
+/// Without one the process is restarted once `cooldown` has passed since it exited, so a
+/// binary that crashes on startup isn't respawned in a tight loop.
+pub fn exit_restart(
+    exited_at: Instant,
+    now: Instant,
+    cooldown: Duration,
+    file_changed: bool,
+) -> ExitRestart {
+    let waited = now.saturating_duration_since(exited_at);
+    if file_changed || waited >= cooldown {
+        ExitRestart::Now
+    } else {
+        ExitRestart::Wait(cooldown - waited)
+    }
+}
+
+/// Runs the first build cycle, retrying up to `retries` times while it fails (e.g. on
+/// transient lock contention). `before_retry` gets the retry number (from 1) and is the
+/// place to wait. Returns the last outcome.
+pub fn retry_initial_build(
+    retries: u32,
+    mut attempt: impl FnMut() -> Result<BuildOutcome>,
+    mut before_retry: impl FnMut(u32),
+) -> Result<BuildOutcome> {
+    let mut outcome = attempt()?;
+    for n in 1..=retries {
+        if outcome != BuildOutcome::Failed {
+            break;
+        }
+        before_retry(n);
+        outcome = attempt()?;
+    }
+    Ok(outcome)
+}
+
+/// One line of captured child output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLine {
+    pub text: String,
+    /// Written to stdout (true) or stderr (false).
+    pub stdout: bool,
+}
+
+/// Build output captured in `buffer_output` mode.
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer {
+    lines: Vec<OutputLine>,
+}
+
+impl OutputBuffer {
+    pub fn push(&mut self, text: &str, stdout: bool) {
+        self.lines.push(OutputLine {
+            text: text.to_string(),
+            stdout,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Lines to replay: all of them after a successful build, none after a failed one.
+    pub fn release(self, succeeded: bool) -> Vec<OutputLine> {
+        if succeeded {
+            self.lines
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn into_lines(self) -> Vec<OutputLine> {
+        self.lines
+    }
+
+    /// The last `n` lines (`fail_tail_lines`), and how many came before them.
+    pub fn into_tail(mut self, n: usize) -> (usize, Vec<OutputLine>) {
+        let omitted = self.lines.len().saturating_sub(n);
+        let tail = self.lines.split_off(omitted);
+        (omitted, tail)
+    }
+
+    /// Hash of the output, skipping cargo progress lines (`Compiling ...`) that vary between runs.
+    pub fn fingerprint(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        const PROGRESS: [&str; 4] = ["Compiling ", "Checking ", "Blocking ", "Building "];
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        for line in &self.lines {
+            let text = line.text.trim();
+            if !PROGRESS.iter().any(|p| text.starts_with(p)) {
+                text.hash(&mut h);
+            }
+        }
+        h.finish()
+    }
+}
+
+/// True for cargo's notice that another cargo holds the package cache or build directory
+/// lock (`Blocking waiting for file lock on build directory`). Cargo carries on once the lock
+/// frees, so this is a wait, not a failure.
+pub fn is_cargo_lock_wait(line: &str) -> bool {
+    line.trim_start()
+        .starts_with("Blocking waiting for file lock")
+}
+
+/// Logged instead of the full output when a build fails exactly like the previous one.
+pub const REPEATED_FAILURE_MSG: &str = "build failed (same errors as before)";
+
+/// Remembers the last failed build so identical consecutive failures can be collapsed.
+#[derive(Debug, Clone, Default)]
+pub struct FailureCache {
+    last: Option<u64>,
+}
+
+impl FailureCache {
+    /// Records a failed build; returns true if its output matches the previous failure.
+    pub fn is_repeat(&mut self, output: &OutputBuffer) -> bool {
+        let fp = output.fingerprint();
+        self.last.replace(fp) == Some(fp)
+    }
+
+    /// Forgets the last failure (after a successful build).
+    pub fn clear(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Files build scripts declared with `cargo:rerun-if-changed`, as found in cargo's JSON
+/// messages (see `take_cargo_messages`).
+#[derive(Debug, Clone, Default)]
+pub struct BuildScriptInputs {
+    paths: Vec<PathBuf>,
+    new: Vec<PathBuf>,
+}
+
+impl BuildScriptInputs {
+    /// Records `paths`; the ones not seen before are returned by the next `take_new`.
+    pub fn extend(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for p in paths {
+            if !self.paths.contains(&p) {
+                self.paths.push(p.clone());
+                self.new.push(p);
+            }
+        }
+    }
+
+    /// Returns true if `path` is an input or lies under an input directory.
+    pub fn contains(&self, path: &Path) -> bool {
+        let path = absolute_path(path);
+        self.paths.iter().any(|p| path.starts_with(p))
+    }
+
+    /// Inputs recorded since the last call, which still need to be watched.
+    pub fn take_new(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.new)
+    }
+
+    /// Marks every input as new again, for a recreated watcher.
+    pub fn rewatch_all(&mut self) {
+        self.new = self.paths.clone();
+    }
+}
+
+/// What rair reads from cargo's JSON messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CargoMessages {
+    /// `rerun-if-changed` paths of the build scripts that ran.
+    pub build_script_inputs: Vec<PathBuf>,
+    /// Files the build produced for the project's own packages: each binary, or a
+    /// library's files (without `.rmeta`). Dependencies and build scripts aren't listed.
+    pub artifacts: Vec<PathBuf>,
+    /// Compiler warnings, with `--message-format=json` (`parse_diagnostics`).
+    pub warnings: usize,
+    /// Compiler errors, counted like `warnings`. rustc's closing "aborting due to"
+    /// summary isn't an error of its own.
+    pub errors: usize,
+}
+
+/// The message format `parse_diagnostics` builds with: diagnostics as JSON, rendered by rair.
+pub const JSON_DIAGNOSTICS_FORMAT: &str = "--message-format=json";
+
+/// Removes cargo's JSON messages (stdout of `--message-format=json-render-diagnostics`)
+/// from `output` and returns what rair uses from them. Other lines are kept. With plain
+/// `--message-format=json`, compiler messages are counted and replaced by their rendered
+/// text, as stderr lines.
+pub fn take_cargo_messages(output: &mut OutputBuffer) -> CargoMessages {
+    use cargo_metadata::{diagnostic::DiagnosticLevel, Message};
+
+    let mut messages = CargoMessages::default();
+    let mut kept = Vec::with_capacity(output.lines.len());
+    for line in std::mem::take(&mut output.lines) {
+        if !line.stdout || !line.text.starts_with('{') {
+            kept.push(line);
+            continue;
+        }
+        match Message::parse_stream(line.text.as_bytes()).next() {
+            Some(Ok(Message::BuildScriptExecuted(script))) => {
+                messages
+                    .build_script_inputs
+                    .extend(rerun_if_changed(&script));
+            }
+            Some(Ok(Message::CompilerArtifact(artifact))) => {
+                messages.artifacts.extend(artifact_files(&artifact));
+            }
+            Some(Ok(Message::CompilerMessage(msg))) => {
+                match msg.message.level {
+                    DiagnosticLevel::Warning => messages.warnings += 1,
+                    DiagnosticLevel::Error | DiagnosticLevel::Ice
+                        if !msg.message.message.starts_with("aborting due to") =>
+                    {
+                        messages.errors += 1
+                    }
+                    _ => {}
+                }
+                let rendered = msg.message.rendered.as_deref().unwrap_or_default();
+                kept.extend(rendered.lines().map(|text| OutputLine {
+                    text: text.to_string(),
+                    stdout: false,
+                }));
+            }
+            Some(Ok(Message::TextLine(_))) | Some(Err(_)) | None => kept.push(line),
+            Some(Ok(_)) => {}
+        }
+    }
+    output.lines = kept;
+    messages
+}
+
+/// The line `parse_diagnostics` logs after a successful build.
+pub fn format_build_ok(warnings: usize) -> String {
+    match warnings {
+        1 => "build ok (1 warning)".to_string(),
+        n => format!("build ok ({} warnings)", n),
+    }
+}
+
+fn artifact_files(artifact: &cargo_metadata::Artifact) -> Vec<PathBuf> {
+    let build_script = artifact.target.kind.iter().any(|k| k == "custom-build");
+    if build_script || local_package_root(&artifact.package_id.repr).is_none() {
+        return Vec::new();
+    }
+    if let Some(exe) = &artifact.executable {
+        return vec![exe.clone().into()];
+    }
+    artifact
+        .filenames
+        .iter()
+        .filter(|f| f.extension() != Some("rmeta"))
+        .map(|f| f.clone().into())
+        .collect()
+}
+
+/// Reads the `rerun-if-changed` lines from the build script's saved output (next to its
+/// `OUT_DIR`). Only local packages are considered; registry sources don't change.
+fn rerun_if_changed(script: &cargo_metadata::BuildScript) -> Vec<PathBuf> {
+    let Some(root) = local_package_root(&script.package_id.repr) else {
+        return Vec::new();
+    };
+    let Some(output) = script.out_dir.parent().map(|d| d.join("output")) else {
+        return Vec::new();
+    };
+    let Ok(text) = std::fs::read_to_string(&output) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|l| {
+            l.strip_prefix("cargo:rerun-if-changed=")
+                .or_else(|| l.strip_prefix("cargo::rerun-if-changed="))
+        })
+        .map(|p| root.join(p))
+        .collect()
+}
+
+/// The directory of a `path+file://` package id, in both the old
+/// (`name 0.1.0 (path+file:///dir)`) and the current (`path+file:///dir#0.1.0`) formats.
+fn local_package_root(repr: &str) -> Option<PathBuf> {
+    let rest = &repr[repr.find("path+file://")? + "path+file://".len()..];
+    let end = rest.find(['#', ')']).unwrap_or(rest.len());
+    Some(PathBuf::from(percent_decode(&rest[..end])))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Collects a child's piped stdout and stderr into an `OutputBuffer` from reader threads.
+pub struct OutputCapture {
+    buffer: Arc<Mutex<OutputBuffer>>,
+    readers: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl OutputCapture {
+    /// Takes the child's stdout/stderr (which must be piped) and starts reading them.
+    pub fn start(child: &mut std::process::Child) -> Self {
+        Self::start_with(child, |_, _| {})
+    }
+
+    /// Like `start`, also handing each line (and whether it came from stdout) to `on_line`
+    /// as it arrives.
+    pub fn start_with<F>(child: &mut std::process::Child, on_line: F) -> Self
+    where
+        F: Fn(&str, bool) + Send + Sync + 'static,
+    {
+        let buffer = Arc::new(Mutex::new(OutputBuffer::default()));
+        let on_line = Arc::new(on_line);
+        let capture = |r: Box<dyn std::io::Read + Send>, stdout: bool| {
+            let buffer = buffer.clone();
+            let on_line = on_line.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(r).split(b'\n').map_while(Result::ok) {
+                    let text = String::from_utf8_lossy(&line);
+                    let text = text.trim_end_matches('\r');
+                    on_line(text, stdout);
+                    buffer.lock().unwrap().push(text, stdout);
+                }
+            })
+        };
+        let readers = [
+            child.stdout.take().map(|o| capture(Box::new(o), true)),
+            child.stderr.take().map(|e| capture(Box::new(e), false)),
+        ];
+        OutputCapture {
+            buffer,
+            readers: readers.into_iter().flatten().collect(),
+        }
+    }
+
+    /// Waits for the child's output to close and returns everything read.
+    pub fn finish(self) -> OutputBuffer {
+        for r in self.readers {
+            let _ = r.join();
+        }
+        let mut buffer = self.buffer.lock().unwrap();
+        std::mem::take(&mut *buffer)
+    }
+}
+
+/// Runs `cmd` to completion, capturing stdout and stderr line by line in arrival order.
+pub fn run_captured(mut cmd: Command) -> Result<(ExitStatus, OutputBuffer), RairError> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let spawn_err = |source| RairError::BuildSpawn {
+        program: program.clone(),
+        source,
+    };
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_err)?;
+    let capture = OutputCapture::start(&mut child);
+    let status = child.wait().map_err(spawn_err)?;
+    Ok((status, capture.finish()))
+}
+
+/// How waiting on a process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitOutcome {
+    Exited(ExitStatus),
+    TimedOut,
+    /// Stopped waiting because rair is shutting down (e.g. Ctrl-C).
+    Interrupted,
+}
+
+impl WaitOutcome {
+    pub fn success(self) -> bool {
+        matches!(self, WaitOutcome::Exited(s) if s.success())
+    }
+
+    /// The exit code, if the process exited normally.
+    pub fn code(self) -> Option<i32> {
+        match self {
+            WaitOutcome::Exited(s) => s.code(),
+            _ => None,
+        }
+    }
+}
+
+/// Exit code for a run killed by `run_timeout_ms`, as used by coreutils `timeout`.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Polls `try_wait` until the process exits, `timeout` elapses or `interrupted` returns true.
+/// The caller kills the process on `TimedOut`/`Interrupted`.
+pub fn wait_timeout(
+    mut try_wait: impl FnMut() -> std::io::Result<Option<ExitStatus>>,
+    timeout: Option<Duration>,
+    interrupted: impl Fn() -> bool,
+) -> std::io::Result<WaitOutcome> {
+    const POLL: Duration = Duration::from_millis(20);
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        if let Some(status) = try_wait()? {
+            return Ok(WaitOutcome::Exited(status));
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Ok(WaitOutcome::TimedOut);
+        }
+        if interrupted() {
+            return Ok(WaitOutcome::Interrupted);
+        }
+        std::thread::sleep(POLL);
+    }
+}
+
+/// State shown by the status line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Building,
+    /// The build is blocked on another cargo's file lock.
+    WaitingForLock,
+    /// Build succeeded and there's nothing to run (e.g. bench mode).
+    Built,
+    BuildFailed,
+    /// A hook list (pre_build, post_build, pre_run) failed.
+    StepFailed(String),
+    Running {
+        pid: u32,
+    },
+    /// Watching is paused (`interactive`).
+    Paused,
+}
+
+/// The single-line status text for `status`, stamped with `ts`.
+pub fn format_status(status: &Status, ts: &str) -> String {
+    let what = match status {
+        Status::Building => "building...".to_string(),
+        Status::WaitingForLock => "building (waiting for another cargo's lock)...".to_string(),
+        Status::Built => "build ok".to_string(),
+        Status::BuildFailed => "build failed; keeping existing process".to_string(),
+        Status::StepFailed(step) => format!("{} failed; keeping existing process", step),
+        Status::Running { pid } => format!("running (pid {})", pid),
+        Status::Paused => "paused; press p to resume".to_string(),
+    };
+    format!("[{}] rair: {}", ts, what)
+}
+
+/// Counters reported when rair shuts down.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub builds: u32,
+    pub build_failures: u32,
+    pub last_build_failed: bool,
+}
+
+impl Stats {
+    /// Records one cycle; `Pending` means no build ran (e.g. a pre_build hook failed).
+    pub fn record(&mut self, outcome: BuildOutcome) {
+        match outcome {
+            BuildOutcome::Pending => {}
+            BuildOutcome::Succeeded => {
+                self.builds += 1;
+                self.last_build_failed = false;
+            }
+            BuildOutcome::Failed => {
+                self.builds += 1;
+                self.build_failures += 1;
+                self.last_build_failed = true;
+            }
+        }
+    }
+
+    /// Process exit code: non-zero only with `strict` and a failed last build.
+    pub fn exit_code(&self, strict: bool) -> i32 {
+        if strict && self.last_build_failed {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// Compact duration like `1h 02m 03s`, `4m 05s`, `12s` or (under a second) `300ms`.
+pub fn format_duration(d: Duration) -> String {
+    if d > Duration::ZERO && d < Duration::from_secs(1) {
+        return format!("{}ms", d.as_millis());
+    }
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}h {:02}m {:02}s", h, m, s)
+    } else if m > 0 {
+        format!("{}m {:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+/// How long each phase of a cycle took (`timings`), in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timeline {
+    phases: Vec<(String, Duration)>,
+}
+
+impl Timeline {
+    pub fn record(&mut self, phase: impl Into<String>, took: Duration) {
+        self.phases.push((phase.into(), took));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phases.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.phases.clear();
+    }
+
+    /// One line for the log: `pre_build 0.3s | build 4.1s | run spawned in 0.02s`.
+    /// Phases under a tenth of a second get a second decimal.
+    pub fn format(&self) -> String {
+        self.phases
+            .iter()
+            .map(|(phase, took)| {
+                let secs = took.as_secs_f64();
+                let took = if secs < 0.1 {
+                    format!("{:.2}s", secs)
+                } else {
+                    format!("{:.1}s", secs)
+                };
+                match phase.as_str() {
+                    "run" => format!("run spawned in {}", took),
+                    _ => format!("{} {}", phase, took),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+/// The `concise` line for a finished cycle: what happened (`rebuilt`, `restarted`
+/// or both), the new process's pid when one started, and how long it took.
+pub fn format_cycle_summary(rebuilt: bool, pid: Option<u32>, elapsed: Duration) -> String {
+    let what = match (rebuilt, pid) {
+        (true, Some(pid)) => format!("rebuilt & restarted (pid {})", pid),
+        (false, Some(pid)) => format!("restarted (pid {})", pid),
+        (true, None) => "rebuilt".to_string(),
+        (false, None) => "nothing to do".to_string(),
+    };
+    format!("{} in {:.1}s", what, elapsed.as_secs_f64())
+}
+
+/// One-line shutdown summary.
+pub fn format_summary(stats: &Stats, uptime: Duration) -> String {
+    let mut line = format!(
+        "{} build{} ({} failed), up {}",
+        stats.builds,
+        if stats.builds == 1 { "" } else { "s" },
+        stats.build_failures,
+        format_duration(uptime)
+    );
+    if stats.last_build_failed {
+        line.push_str("; last build failed");
+    }
+    line
+}
+
+/// Commands accepted on the control socket, one per line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Rebuild and restart now, as if a relevant file had changed.
+    Restart,
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> Option<ControlCommand> {
+        match line.trim() {
+            "restart" | "trigger" => Some(ControlCommand::Restart),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ControlCommand::Restart => "restart",
+        }
+    }
+}
+
+/// Binds `path` and calls `on_command` from a background thread for every command received.
+/// A stale socket file left by a crashed rair is replaced; a live one is an error.
+#[cfg(unix)]
+pub fn listen_control_socket(
+    path: &Path,
+    on_command: impl Fn(ControlCommand) + Send + 'static,
+) -> Result<()> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        anyhow::ensure!(
+            UnixStream::connect(path).is_err(),
+            "control socket {:?} is in use by another rair",
+            path
+        );
+        std::fs::remove_file(path)
+            .with_context(|| format!("remove stale control socket {:?}", path))?;
+    }
+    let listener =
+        UnixListener::bind(path).with_context(|| format!("bind control socket {:?}", path))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if let Some(cmd) = ControlCommand::parse(&line) {
+                    on_command(cmd);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Serves `POST /trigger` on `addr` and calls `on_command` with [`ControlCommand::Restart`]
+/// from a background thread for each one. With `token`, a request without a matching
+/// `Authorization: Bearer` header gets 401. Returns the bound address (port 0 picks one).
+#[cfg(feature = "http-trigger")]
+pub fn listen_http_trigger(
+    addr: &str,
+    token: Option<String>,
+    on_command: impl Fn(ControlCommand) + Send + 'static,
+) -> Result<std::net::SocketAddr> {
+    use tiny_http::{Method, Response, Server};
+
+    let server =
+        Server::http(addr).map_err(|e| anyhow::anyhow!("bind http trigger on {}: {}", addr, e))?;
+    let bound = server
+        .server_addr()
+        .to_ip()
+        .context("http trigger isn't bound to an IP address")?;
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let path = request.url().split('?').next().unwrap_or_default();
+            let authorized = token.as_deref().is_none_or(|token| {
+                request.headers().iter().any(|h| {
+                    h.field.equiv("Authorization")
+                        && h.value.as_str().strip_prefix("Bearer ") == Some(token)
+                })
+            });
+            let status = match (request.method(), path) {
+                (_, p) if p != "/trigger" => 404,
+                (Method::Post, _) if !authorized => 401,
+                (Method::Post, _) => {
+                    on_command(ControlCommand::Restart);
+                    202
+                }
+                _ => 405,
+            };
+            let body = match status {
+                202 => "triggered\n",
+                401 => "bad or missing token\n",
+                404 => "not found\n",
+                _ => "use POST\n",
+            };
+            let _ = request.respond(Response::from_string(body).with_status_code(status));
+        }
+    });
+    Ok(bound)
+}
+
+/// Sends one command to a rair listening on `path`.
+#[cfg(unix)]
+pub fn send_control_command(path: &Path, cmd: ControlCommand) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("connect to control socket {:?} (is rair running?)", path))?;
+    writeln!(stream, "{}", cmd.as_str()).context("write control command")?;
+    Ok(())
+}
+
+/// CPU and memory usage of a process at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessSample {
+    /// Percent of one core since the previous sample (0 on the first).
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+}
+
+impl ProcessSample {
+    pub fn describe(&self) -> String {
+        format!(
+            "cpu {:.1}%, rss {:.1} MiB",
+            self.cpu_percent,
+            self.rss_bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// Samples process usage; keeps state between calls so CPU usage is a delta.
+#[cfg(feature = "monitor")]
+#[derive(Default)]
+pub struct ProcessMonitor {
+    sys: sysinfo::System,
+}
+
+#[cfg(feature = "monitor")]
+impl ProcessMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns None if the process doesn't exist (e.g. it exited since the last sample).
+    pub fn sample(&mut self, pid: u32) -> Option<ProcessSample> {
+        let pid = sysinfo::Pid::from_u32(pid);
+        if !self.sys.refresh_process(pid) {
+            return None;
+        }
+        self.sys.process(pid).map(|p| ProcessSample {
+            cpu_percent: p.cpu_usage(),
+            rss_bytes: p.memory(),
+        })
+    }
+}
+
+/// A pseudo-terminal for the run process's output (`pty`). The child writes to the
+/// terminal side, sees a TTY and keeps its colours; rair reads the other side line by
+/// line. stdout and stderr share the one terminal.
+#[cfg(all(feature = "pty", unix))]
+pub struct Pty {
+    master: Box<dyn portable_pty::MasterPty + Send>,
+    // held until the child has its own descriptors, so the pty isn't seen as hung up
+    _slave: Box<dyn portable_pty::SlavePty + Send>,
+    tty: PathBuf,
+}
+
+#[cfg(all(feature = "pty", unix))]
+impl Pty {
+    /// Opens a pty as large as rair's own terminal (80x24 without one).
+    pub fn open() -> Result<Pty> {
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+        let size = portable_pty::PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        let pair = portable_pty::native_pty_system()
+            .openpty(size)
+            .context("open pty")?;
+        let tty = pair
+            .master
+            .tty_name()
+            .context("pty has no terminal device")?;
+        Ok(Pty {
+            master: pair.master,
+            _slave: pair.slave,
+            tty,
+        })
+    }
+
+    /// The child's stdout and stderr: the terminal side, opened once for each.
+    pub fn stdio(&self) -> Result<(Stdio, Stdio)> {
+        let open = || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&self.tty)
+                .with_context(|| format!("open {:?}", self.tty))
+        };
+        Ok((Stdio::from(open()?), Stdio::from(open()?)))
+    }
+
+    /// What the child writes; ends once every copy of the terminal side is closed.
+    pub fn reader(&self) -> Result<Box<dyn std::io::Read + Send>> {
+        self.master.try_clone_reader().context("read pty")
+    }
+}
+
+/// Splits `major.minor.patch[-pre][+build]` into its numbers and pre-release part.
+fn parse_version(v: &str) -> Option<(u64, u64, u64, Option<&str>)> {
+    let v = v.trim().trim_start_matches('v');
+    let v = v.split_once('+').map_or(v, |(v, _)| v);
+    let (core, pre) = match v.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (v, None),
+    };
+    let mut parts = core.split('.').map(|n| n.parse::<u64>().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??, pre);
+    parts.next().is_none().then_some(version)
+}
+
+/// Whether `latest` is a newer semver version than `current`. A pre-release sorts before
+/// its release (`1.0.0-rc.1` < `1.0.0`); anything unparseable is never newer.
+pub fn is_newer_version(latest: &str, current: &str) -> bool {
+    let (Some(latest), Some(current)) = (parse_version(latest), parse_version(current)) else {
+        return false;
+    };
+    let core = |v: (u64, u64, u64, Option<&str>)| (v.0, v.1, v.2);
+    match core(latest).cmp(&core(current)) {
+        std::cmp::Ordering::Equal => match (latest.3, current.3) {
+            (None, Some(_)) => true,
+            (Some(l), Some(c)) => compare_prerelease(l, c) == std::cmp::Ordering::Greater,
+            _ => false,
+        },
+        order => order == std::cmp::Ordering::Greater,
+    }
+}
+
+/// Orders two pre-release tags as semver does: identifier by identifier, numeric ones by
+/// value and before alphanumeric ones; a longer tag wins when one is a prefix of the other.
+fn compare_prerelease(latest: &str, current: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let numeric = |id: &str| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit());
+    let mut l = latest.split('.');
+    let mut c = current.split('.');
+    loop {
+        let order = match (l.next(), c.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(c)) => match (numeric(l), numeric(c)) {
+                // without leading zeros, a longer number is a bigger one
+                (true, true) => {
+                    let (l, c) = (l.trim_start_matches('0'), c.trim_start_matches('0'));
+                    l.len().cmp(&c.len()).then_with(|| l.cmp(c))
+                }
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (false, false) => l.cmp(c),
+            },
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// How long a crates.io lookup for `check_updates` is reused.
+pub const UPDATE_CHECK_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The latest version stored in the `check_updates` cache file, if it was written less
+/// than `ttl` before `now`. The file holds `<unix seconds> <version>`.
+pub fn cached_latest_version(
+    cache: &Path,
+    now: std::time::SystemTime,
+    ttl: Duration,
+) -> Option<String> {
+    let text = std::fs::read_to_string(cache).ok()?;
+    let (stamp, version) = text.trim().split_once(' ')?;
+    let written = std::time::UNIX_EPOCH + Duration::from_secs(stamp.parse().ok()?);
+    let age = now.duration_since(written).ok()?;
+    (age < ttl).then(|| version.to_string())
+}
+
+/// Records the latest version for [`cached_latest_version`].
+pub fn write_cached_latest_version(
+    cache: &Path,
+    now: std::time::SystemTime,
+    version: &str,
+) -> std::io::Result<()> {
+    let secs = now
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    std::fs::write(cache, format!("{} {}\n", secs, version))
+}
+
+/// The latest `rair` release on crates.io, from the cache if it's fresh enough.
+#[cfg(feature = "update-check")]
+pub fn latest_version(cache: &Path) -> Result<String> {
+    let now = std::time::SystemTime::now();
+    if let Some(version) = cached_latest_version(cache, now, UPDATE_CHECK_TTL) {
+        return Ok(version);
+    }
+    // crates.io asks API clients to identify themselves
+    let agent = format!("rair/{} (update check)", env!("CARGO_PKG_VERSION"));
+    let body = ureq::get("https://crates.io/api/v1/crates/rair")
+        .set("User-Agent", &agent)
+        .timeout(Duration::from_secs(10))
+        .call()
+        .context("query crates.io")?
+        .into_string()
+        .context("read crates.io response")?;
+    let body: serde_json::Value =
+        serde_json::from_str(&body).context("parse crates.io response")?;
+    let version = body["crate"]["max_stable_version"]
+        .as_str()
+        .or_else(|| body["crate"]["max_version"].as_str())
+        .context("no version in the crates.io response")?
+        .to_string();
+    let _ = write_cached_latest_version(cache, now, &version);
+    Ok(version)
 }
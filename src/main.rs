@@ -1,31 +1,71 @@
 use anyhow::{Context, Result};
-use cargo_metadata::MetadataCommand;
-use chrono::Local;
 use command_group::{CommandGroup, GroupChild};
 use crossterm::{
-    cursor::MoveTo,
+    cursor::{MoveTo, MoveToColumn},
     execute,
     terminal::{Clear, ClearType},
 };
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use std::{
-    io::{self, Write},
-    path::PathBuf,
-    process::{Command, Stdio},
-    sync::{mpsc, Arc, Mutex},
-    time::Instant,
+    borrow::Cow,
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
-use clap::Parser;
-use rair::{Config, EffectiveConfig};
+use clap::{Parser, Subcommand};
+use rair::{
+    ArtifactStamp, BuildOutcome, BuildRateLimit, BuildScriptInputs, CargoTarget, ChangeBatch,
+    ChangeBurst, ClearBefore, Config, ConfigSource, ContentHashes, ControlCommand, DebounceState,
+    EffectiveConfig, ExitRestart, FailureCache, FilterReason, GitTracked, Hook, IgnoreFiles,
+    MissingWatches, Mode, OutputBuffer, OutputCapture, PathCooldown, PauseState,
+    PostReadyGeneration, RestartStep, RunDecision, Service, Stats, Status, SymlinkMap, Timeline,
+    TimestampFormat, WaitOutcome, WatchEntry, WatchSet, WatchStats, WatcherRestarts,
+    EXE_PLACEHOLDER,
+};
+
+/// How often the loop wakes without events (retry missing watch paths, check for shutdown).
+const LOOP_TICK: Duration = Duration::from_millis(250);
+
+/// `trigger_file` events arriving this soon after a triggered build are part of the same touch.
+const TRIGGER_SETTLE: Duration = Duration::from_millis(100);
+
+/// Pause before retrying a failed first build (see `initial_build_retries`).
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Pause before recreating a watcher that stopped (see `watcher_restarts`).
+const WATCHER_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Set by the Ctrl-C handler; the watch loop and process waits stop when it is.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+fn shutting_down() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Log timestamp format, set once the config is resolved; the default until then.
+static TIMESTAMP: OnceLock<TimestampFormat> = OnceLock::new();
+
+/// Current status-line text; `None` unless status-line mode is active.
+static STATUS_LINE: Mutex<Option<String>> = Mutex::new(None);
 
 #[derive(Parser, Debug, Clone)]
 #[command(name = "rair", about = "Air-like hot reload for Rust (cross-platform)")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Cmd>,
+
     /// Rust files to watch and compile (e.g., rair main.rs, rair *.rs)
     files: Vec<PathBuf>,
 
-    /// Config file path (default: .rair.toml if present)
+    /// Config file path (.toml, .json, .yaml/.yml; default: .rair.toml if present)
     #[arg(long)]
     config: Option<PathBuf>,
 
@@ -37,39 +77,299 @@ struct Cli {
     #[arg(long)]
     ignore: Vec<String>,
 
-    /// Include file extensions (repeatable). Default: rs,toml
+    /// glob (default) or ignore: also apply .gitignore/.ignore files like ripgrep
+    #[arg(long, value_name = "ENGINE")]
+    ignore_engine: Option<String>,
+
+    /// Include file extensions, added to the config/default list (repeatable). Default: rs,toml
     #[arg(long)]
     include_ext: Vec<String>,
 
-    /// Exclude file extensions (repeatable)
+    /// Exclude file extensions, added to the config list (repeatable)
     #[arg(long)]
     exclude_ext: Vec<String>,
 
+    /// Watch only these extensions, replacing the config/default list (repeatable)
+    #[arg(long)]
+    only_ext: Vec<String>,
+
+    /// Only rebuild on these kinds of change: create, modify, remove, rename (repeatable)
+    #[arg(long)]
+    watch_events: Vec<String>,
+
+    /// Also rebuild on metadata-only changes, like a chmod
+    #[arg(long)]
+    watch_metadata: bool,
+
     /// Debounce in ms
     #[arg(long)]
     debounce_ms: Option<u64>,
 
+    /// Which end of a burst of changes builds: leading (default), trailing or both
+    #[arg(long, value_name = "MODE")]
+    debounce_mode: Option<String>,
+
+    /// Build this many changes arriving together once, after they settle (default: 50)
+    #[arg(long, value_name = "N")]
+    burst_threshold: Option<usize>,
+
+    /// Log filter decisions and other debugging detail
+    #[arg(short = 'v', long)]
+    verbose: bool,
+
+    /// Log how long each phase of a cycle took (on with --verbose)
+    #[arg(long)]
+    timings: bool,
+
+    /// chrono format for log timestamps (default: %Y-%m-%d %H:%M:%S)
+    #[arg(long)]
+    timestamp_format: Option<String>,
+
+    /// Log timestamps in UTC instead of local time
+    #[arg(long)]
+    timestamp_utc: bool,
+
+    /// Ignore a path that already triggered a rebuild within this many ms
+    #[arg(long)]
+    per_path_cooldown_ms: Option<u64>,
+
+    /// Start builds for file changes at most once per this many ms
+    #[arg(long)]
+    min_build_interval_ms: Option<u64>,
+
+    /// Only react to files tracked by git
+    #[arg(long)]
+    git_tracked_only: bool,
+
+    /// With --git-tracked-only, also react to untracked, non-ignored files
+    #[arg(long)]
+    git_include_untracked: bool,
+
     /// Clear screen before run
     #[arg(long)]
     clear: Option<bool>,
 
+    /// Also clear screen before each build, so a failing build starts on a clean screen
+    #[arg(long)]
+    clear_on_fail: Option<bool>,
+
+    /// Ring the terminal bell after builds: off (default), on_fail, on_success or always
+    #[arg(long, value_name = "WHEN")]
+    bell: Option<String>,
+
+    /// When --clear clears: run (default), build or never
+    #[arg(long, value_name = "WHEN")]
+    clear_before: Option<String>,
+
+    /// Skip editor temp files like *.swp, 4913 and *~ (default: true)
+    #[arg(long)]
+    ignore_editor_temp: Option<bool>,
+
+    /// Also react to hidden files and directories (.git is always ignored)
+    #[arg(long)]
+    include_hidden: bool,
+
+    /// Also watch the targets of symlinked directories under the watch paths
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Show a single in-place status line instead of per-step logs (TTY only)
+    #[arg(long)]
+    status_line: bool,
+
+    /// One summary line per rebuild cycle instead of the build/run/stop steps
+    #[arg(long)]
+    concise: bool,
+
+    /// Exit non-zero on shutdown if the last build failed
+    #[arg(long)]
+    strict_exit: bool,
+
+    /// Skip watch paths that fail to register instead of aborting
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Recreate the watcher up to N times in a row if it stops (default: 3)
+    #[arg(long, value_name = "N")]
+    watcher_restarts: Option<u32>,
+
+    /// Accept commands such as `rair trigger` on this unix socket
+    #[arg(long)]
+    control_socket: Option<String>,
+
+    /// Rebuild on POST /trigger to this port (localhost; see http_trigger_token)
+    #[arg(long, value_name = "PORT")]
+    http_trigger_port: Option<u16>,
+
+    /// Read key commands from stdin: p + Enter pauses/resumes watching
+    #[arg(long)]
+    interactive: bool,
+
+    /// Touching this file rebuilds immediately, bypassing debounce and filters
+    #[arg(long)]
+    trigger_file: Option<String>,
+
+    /// Re-read the config file when it changes instead of rebuilding (default: true)
+    #[arg(long)]
+    reload_config_on_change: Option<bool>,
+
+    /// Show build output only once the build succeeds (keeps the previous output on failure)
+    #[arg(long)]
+    buffer_output: bool,
+
+    /// Log a build failure identical to the previous one as a single line
+    #[arg(long)]
+    collapse_repeated_errors: bool,
+
+    /// Show only the last N lines of a failed build's output
+    #[arg(long, value_name = "N")]
+    fail_tail_lines: Option<usize>,
+
+    /// Also watch files that build scripts list with `cargo:rerun-if-changed`
+    #[arg(long)]
+    watch_build_script_inputs: bool,
+
+    /// Log the binaries and libraries each successful build produced
+    #[arg(long)]
+    list_artifacts: bool,
+
+    /// Log `build ok (N warnings)` after each successful cargo build
+    #[arg(long)]
+    parse_diagnostics: bool,
+
+    /// Run the process on a pseudo-terminal so it keeps its colours
+    #[arg(long)]
+    pty: bool,
+
+    /// Periodically log the run process's CPU and memory usage
+    #[arg(long)]
+    monitor: bool,
+
+    /// Interval between usage samples in ms (default: 10000)
+    #[arg(long)]
+    monitor_interval_ms: Option<u64>,
+
+    /// Periodically log watcher event rates and why events were filtered out
+    #[arg(long = "stats")]
+    watch_stats: bool,
+
+    /// Interval between --stats reports in ms (default: 10000)
+    #[arg(long = "stats-interval-ms")]
+    watch_stats_interval_ms: Option<u64>,
+
+    /// Log a notice at startup if crates.io has a newer rair (cached for a day)
+    #[arg(long)]
+    check_updates: bool,
+
     /// Explicit build command argv (single command)
     #[arg(long, num_args = 1.., allow_hyphen_values = true)]
     build: Vec<String>,
 
+    /// Working directory for the build command
+    #[arg(long)]
+    build_cwd: Option<String>,
+
     /// Explicit run command argv (single command)
     #[arg(long, num_args = 1.., allow_hyphen_values = true)]
     run: Vec<String>,
 
+    /// Arguments for the run process, after `--` (e.g. rair -- --port 8080)
+    #[arg(last = true)]
+    run_args: Vec<String>,
+
+    /// Working directory for the run command
+    #[arg(long)]
+    run_cwd: Option<String>,
+
+    /// RUST_BACKTRACE for the run process: 0, 1 or full
+    #[arg(long, value_name = "WHEN")]
+    backtrace: Option<String>,
+
+    /// The run process's stdin: inherit, null or pipe (default: inherit; null with --interactive)
+    #[arg(long, value_name = "MODE")]
+    run_stdin: Option<String>,
+
+    /// Load the run process's variables from this file (default: .env if present)
+    #[arg(long, value_name = "FILE")]
+    env_file: Option<String>,
+
+    /// Build and run once, then exit with the run's exit code (no watching)
+    #[arg(long)]
+    once: bool,
+
+    /// With --once: run pre_build, build and post_build, then exit with the build's status
+    #[arg(long)]
+    build_only: bool,
+
+    /// Build on startup, but start the run process only after the first change
+    #[arg(long)]
+    defer_run: bool,
+
+    /// Build once on startup, without hooks or the run, before watching starts
+    #[arg(long)]
+    warm: bool,
+
+    /// Build on every change but never start the run process
+    #[arg(long)]
+    no_run: bool,
+
+    /// Kill the run (in --once) or the tests (in test mode) after this many ms
+    #[arg(long)]
+    run_timeout_ms: Option<u64>,
+
+    /// Retry a failed first build this many times before waiting for changes (default: 0)
+    #[arg(long)]
+    initial_build_retries: Option<u32>,
+
+    /// Wrapper command for the run step, e.g. --runner valgrind (repeatable)
+    #[arg(long, allow_hyphen_values = true)]
+    runner: Vec<String>,
+
+    /// Start the new process before stopping the old one (zero-downtime restarts)
+    #[arg(long)]
+    overlap_restart: bool,
+
+    /// Restart the run process when it exits on its own
+    #[arg(long)]
+    restart_on_exit: bool,
+
+    /// What a failed build does to the run process: keep (default), stop or restart_last_good
+    #[arg(long, value_name = "ACTION")]
+    on_fail_action: Option<String>,
+
+    /// With --restart-on-exit, wait this long after an exit before restarting (default: 1000)
+    #[arg(long)]
+    crash_cooldown_ms: Option<u64>,
+
+    /// Ignore saves that don't change a file, and don't restart when the binary is unchanged
+    #[arg(long)]
+    skip_unchanged: bool,
+
+    /// With --skip-unchanged, still run the post hooks when the binary is unchanged
+    #[arg(long)]
+    hooks_on_noop: bool,
+
+    /// Not a cargo project: build and run must be explicit, the default watch is `.`
+    #[arg(long)]
+    no_cargo: bool,
+
     /// Cargo.toml path
     #[arg(long)]
     manifest_path: Option<String>,
 
+    /// Manifest (or crate directory) of another crate to watch (repeatable)
+    #[arg(long = "extra-manifest", value_name = "PATH")]
+    extra_manifests: Vec<String>,
+
+    /// Cargo target directory (passed to cargo; the binary is looked up there)
+    #[arg(long)]
+    target_dir: Option<String>,
+
     /// Package name (workspace)
     #[arg(short = 'p', long)]
     package: Option<String>,
 
-    /// Binary name to run
+    /// Binary name to run; a glob like 'worker-*' builds every matching binary
     #[arg(long)]
     bin: Option<String>,
 
@@ -83,19 +383,246 @@ struct Cli {
     #[arg(long)]
     no_default_features: bool,
 
+    /// Extra cargo flag for the derived build, e.g. --cargo-arg=--offline (repeatable)
+    #[arg(
+        long = "cargo-arg",
+        visible_alias = "build-arg",
+        allow_hyphen_values = true
+    )]
+    cargo_extra_args: Vec<String>,
+
     #[arg(long)]
     workspace: bool,
 
     #[arg(long)]
     release: bool,
+
+    /// Pass --locked to cargo (Cargo.lock must be up to date)
+    #[arg(long)]
+    locked: bool,
+
+    /// Pass --frozen to cargo (--locked and --offline)
+    #[arg(long)]
+    frozen: bool,
+
+    /// Pass --offline to cargo
+    #[arg(long)]
+    offline: bool,
+
+    /// Watch every workspace member's src and Cargo.toml
+    #[arg(long)]
+    watch_workspace_members: bool,
+
+    /// Mode: run (default), bench, test or doc
+    #[arg(long)]
+    mode: Option<String>,
+
+    /// Test mode: cargo-test (default) or nextest (cargo nextest run)
+    #[arg(long, value_name = "RUNNER")]
+    test_runner: Option<String>,
+
+    /// Bench target name (bench mode)
+    #[arg(long)]
+    bench: Option<String>,
+
+    /// Doc mode: document dependencies too instead of passing --no-deps
+    #[arg(long)]
+    doc_deps: bool,
+
+    /// Doc mode: extra cargo doc flag, e.g. --doc-arg=--document-private-items (repeatable)
+    #[arg(long = "doc-arg", allow_hyphen_values = true)]
+    doc_args: Vec<String>,
+
+    /// Doc mode: open the docs in the browser after the first successful build
+    #[arg(long)]
+    open_docs: bool,
+
+    /// Run cargo fmt --check before each build; unformatted code skips the build
+    #[arg(long)]
+    fmt_check: bool,
+
+    /// Run cargo clippy before each build; any warning skips the build
+    #[arg(long)]
+    clippy: bool,
+
+    /// Print the resolved configuration and exit
+    #[arg(long)]
+    print_config: bool,
+
+    /// List the workspace's targets (package, name, kind) and exit
+    #[arg(long)]
+    list_targets: bool,
+
+    /// Output format for --print-config and --list-targets: text (default) or json
+    #[arg(long, value_parser = ["text", "json"], default_value = "text")]
+    format: String,
+
+    /// Files mode: compile each file after the first as its own crate, and only the changed ones.
+    /// The first file uses the others with `use name::...`, not `mod name;`
+    #[arg(long)]
+    incremental: bool,
+
+    /// Print the names of the bin (or example) targets, one per line, for shell completion
+    #[arg(long, hide = true, num_args = 0..=1, default_missing_value = "bin")]
+    complete_targets: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Cmd {
+    /// Ask a running rair to rebuild and restart now
+    #[command(visible_alias = "restart")]
+    Trigger {
+        /// Control socket of the running rair (default: control_socket from the config)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+    /// Load and resolve the config, list its problems and exit (non-zero if there are any)
+    CheckConfig {
+        /// Config file to check (default: --config, or .rair.toml if present)
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+}
+
+/// Everything the watch loop reacts to: file system events, control socket commands and
+/// `interactive` keys.
+enum LoopEvent {
+    /// A watcher event and when it arrived.
+    Fs(Instant, notify::Result<notify::Event>),
+    /// A control command and where it came from (`control socket`, `http`).
+    Control(ControlCommand, &'static str),
+    /// `p` with `interactive`.
+    TogglePause,
 }
 
 fn ts() -> String {
-    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+    match TIMESTAMP.get() {
+        Some(format) => format.now(),
+        None => TimestampFormat::default().now(),
+    }
 }
 
 fn log_info(msg: &str) {
-    eprintln!("[{}] {}", ts(), msg);
+    emit_line(&format!("[{}] {}", ts(), msg), false);
+}
+
+/// Routine per-cycle messages; the status line replaces them when active.
+fn log_step(msg: &str) {
+    if !status_line_active() {
+        log_info(msg);
+    }
+}
+
+/// Per-step detail (`build:`, `run:`, `stopping previous process`); `concise` keeps it
+/// to `--verbose`.
+fn log_detail(eff: &EffectiveConfig, msg: &str) {
+    if !eff.concise || eff.verbose {
+        log_step(msg);
+    }
+}
+
+fn status_line_active() -> bool {
+    STATUS_LINE.lock().unwrap().is_some()
+}
+
+/// Prints a line, keeping the status line (if active) below it.
+fn emit_line(line: &str, to_stdout: bool) {
+    let status = STATUS_LINE.lock().unwrap();
+    let mut err = io::stderr();
+    if status.is_some() {
+        let _ = execute!(err, MoveToColumn(0), Clear(ClearType::CurrentLine));
+    }
+    if to_stdout {
+        let mut out = io::stdout();
+        let _ = writeln!(out, "{}", line);
+        let _ = out.flush();
+    } else {
+        let _ = writeln!(err, "{}", line);
+    }
+    if let Some(text) = status.as_ref() {
+        let _ = write!(err, "{}", text);
+        let _ = err.flush();
+    }
+}
+
+/// Leaves status-line mode, keeping the last status on screen.
+fn set_status_off() {
+    if STATUS_LINE.lock().unwrap().take().is_some() {
+        eprintln!();
+    }
+}
+
+fn set_status(status: Status) {
+    let mut guard = STATUS_LINE.lock().unwrap();
+    if guard.is_none() {
+        return;
+    }
+    let text = rair::format_status(&status, &ts());
+    let mut err = io::stderr();
+    let _ = execute!(err, MoveToColumn(0), Clear(ClearType::CurrentLine));
+    let _ = write!(err, "{}", text);
+    let _ = err.flush();
+    *guard = Some(text);
+}
+
+/// Forwards piped child output line by line so it prints above the status line.
+fn forward_output<R: io::Read + Send + 'static>(
+    r: R,
+    to_stdout: bool,
+) -> std::thread::JoinHandle<()> {
+    forward_output_with(r, to_stdout, |_| {})
+}
+
+/// `forward_output`, handing each line to `on_line` before it's printed.
+fn forward_output_with<R, F>(r: R, to_stdout: bool, on_line: F) -> std::thread::JoinHandle<()>
+where
+    R: io::Read + Send + 'static,
+    F: Fn(&str) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        for line in io::BufReader::new(r).split(b'\n') {
+            let Ok(line) = line else {
+                break;
+            };
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches('\r');
+            on_line(text);
+            emit_line(text, to_stdout);
+        }
+    })
+}
+
+/// Watches build stderr for cargo's file-lock notice: logs once that rair is waiting,
+/// shows it on the status line, and flags `waited` so a timeout can allow for it.
+fn lock_wait_watcher(waited: Arc<AtomicBool>) -> impl Fn(&str) + Send + Sync + 'static {
+    let waiting = AtomicBool::new(false);
+    move |line| {
+        if rair::is_cargo_lock_wait(line) {
+            if !waiting.swap(true, Ordering::SeqCst) {
+                log_info("build: waiting for another cargo to release its file lock");
+                set_status(Status::WaitingForLock);
+            }
+            waited.store(true, Ordering::SeqCst);
+        } else if waiting.swap(false, Ordering::SeqCst) {
+            // cargo got the lock and moved on
+            set_status(Status::Building);
+        }
+    }
+}
+
+/// Child stdio: inherited normally, piped through `forward_output` in status-line mode.
+fn child_stdio() -> Stdio {
+    if status_line_active() {
+        Stdio::piped()
+    } else {
+        Stdio::inherit()
+    }
+}
+
+fn log_verbose(eff: &EffectiveConfig, msg: &str) {
+    if eff.verbose {
+        log_info(msg);
+    }
 }
 
 fn clear_screen() -> Result<()> {
@@ -103,94 +630,350 @@ fn clear_screen() -> Result<()> {
     Ok(())
 }
 
-fn cmd_from_argv(argv: &[String]) -> Result<Command> {
-    anyhow::ensure!(!argv.is_empty(), "command argv cannot be empty");
-    let mut c = Command::new(&argv[0]);
-    if argv.len() > 1 {
-        c.args(&argv[1..]);
+/// Runs `cargo doc --open` for `open_docs`; the docs are already built, so this only
+/// opens the browser. A failure is logged and otherwise ignored.
+fn open_docs(argv: &[String], eff: &EffectiveConfig) {
+    log_detail(eff, &format!("open_docs: {}", argv.join(" ")));
+    let status = rair::run_command(argv, eff).and_then(|mut c| Ok(c.status()?));
+    match status {
+        Ok(s) if s.success() => {}
+        Ok(s) => log_info(&format!("open_docs: cargo doc --open exited with {}", s)),
+        Err(e) => log_info(&format!("open_docs: {:#}", e)),
+    }
+}
+
+/// Writes a `bell` cue, unless stdout is piped somewhere.
+fn ring_bell(cue: &str) {
+    let mut out = io::stdout();
+    if !cue.is_empty() && out.is_terminal() {
+        let _ = out.write_all(cue.as_bytes());
+        let _ = out.flush();
     }
-    Ok(c)
 }
 
-fn run_build(build: &[String]) -> Result<bool> {
-    log_info(&format!("build: {:?}", build));
-    let mut c = cmd_from_argv(build)?;
-    let status = c
+/// Runs the build in its own process group, so a timeout or Ctrl-C also stops whatever
+/// it spawned (e.g. test binaries). With `capture`, its output is returned instead of shown.
+/// `capture_stdout` captures only stdout (cargo's JSON messages) and still streams stderr.
+/// `build` is `eff.build` with its placeholders expanded.
+fn run_build(
+    eff: &EffectiveConfig,
+    build: &[String],
+    capture: bool,
+    capture_stdout: bool,
+    timeout: Option<Duration>,
+) -> Result<(WaitOutcome, Option<OutputBuffer>)> {
+    log_detail(eff, &format!("build: {:?}", build));
+    set_status(Status::Building);
+    let stdout = if capture || capture_stdout {
+        Stdio::piped()
+    } else {
+        child_stdio()
+    };
+    let mut c = rair::build_command(build, eff)?;
+    // stderr is always read, to spot cargo blocking on another cargo's lock; keep cargo's
+    // colours where it would otherwise have written straight to the terminal
+    if !capture && io::stderr().is_terminal() && std::env::var_os("CARGO_TERM_COLOR").is_none() {
+        c.env("CARGO_TERM_COLOR", "always");
+    }
+    let mut child = c
         .stdin(Stdio::null())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .group_spawn()
+        .with_context(|| format!("build: {:?}", build))?;
+    let lock_waited = Arc::new(AtomicBool::new(false));
+    let watcher = lock_wait_watcher(lock_waited.clone());
+    let mut forwarders = Vec::new();
+    let captured = if capture {
+        Some(OutputCapture::start_with(
+            child.inner(),
+            move |line, stdout| {
+                if !stdout {
+                    watcher(line);
+                }
+            },
+        ))
+    } else {
+        if let Some(e) = child.inner().stderr.take() {
+            forwarders.push(forward_output_with(e, false, watcher));
+        }
+        capture_stdout.then(|| OutputCapture::start(child.inner()))
+    };
+    if let Some(o) = child.inner().stdout.take() {
+        forwarders.push(forward_output(o, true));
+    }
+    let mut outcome = rair::wait_timeout(|| child.try_wait(), timeout, shutting_down)
         .with_context(|| format!("build: {:?}", build))?;
-    Ok(status.success())
+    // time spent blocked on another cargo's lock isn't the build's fault: allow it once more
+    while outcome == WaitOutcome::TimedOut && lock_waited.swap(false, Ordering::SeqCst) {
+        log_info("build: timed out after waiting on cargo's file lock; allowing it longer");
+        outcome = rair::wait_timeout(|| child.try_wait(), timeout, shutting_down)
+            .with_context(|| format!("build: {:?}", build))?;
+    }
+    if !matches!(outcome, WaitOutcome::Exited(_)) {
+        kill_group(&mut child);
+    }
+    for f in forwarders {
+        let _ = f.join();
+    }
+    Ok((outcome, captured.map(OutputCapture::finish)))
 }
 
-fn spawn_run_group(run: &[String]) -> Result<GroupChild> {
-    log_info(&format!("run: {:?}", run));
-    let mut c = cmd_from_argv(run)?;
-
-    // Set environment variable to prevent recursive watching
-    c.env("RAIR_ACTIVE", "1");
+fn spawn_run_group(run: &[String], eff: &EffectiveConfig) -> Result<GroupChild> {
+    log_detail(eff, &format!("run: {:?}", run));
+    let mut c = rair::run_command(run, eff)?;
 
-    let child = c
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+    #[cfg(all(feature = "pty", unix))]
+    if eff.pty {
+        let pty = rair::Pty::open()?;
+        let (stdout, stderr) = pty.stdio()?;
+        let child = c
+            .stdin(eff.run_stdin.stdio())
+            .stdout(stdout)
+            .stderr(stderr)
+            .group_spawn()
+            .with_context(|| format!("run: {:?}", run))?;
+        forward_output(pty.reader()?, true);
+        set_status(Status::Running { pid: child.id() });
+        return Ok(child);
+    }
+    let mut child = c
+        .stdin(eff.run_stdin.stdio())
+        .stdout(child_stdio())
+        .stderr(child_stdio())
         .group_spawn()
         .with_context(|| format!("run: {:?}", run))?;
+    if let Some(out) = child.inner().stdout.take() {
+        forward_output(out, true);
+    }
+    if let Some(err) = child.inner().stderr.take() {
+        forward_output(err, false);
+    }
+    set_status(Status::Running { pid: child.id() });
     Ok(child)
 }
 
+/// `warm`: the build argv once, output shown but no checks, hooks or run process. A
+/// failure is only logged; the first cycle reports it properly.
+fn warm_build(eff: &EffectiveConfig) -> Result<()> {
+    log_info("warm: building once before watching");
+    let started = Instant::now();
+    let build = rair::expand_build_argv(&eff.build, &[], &eff.project_root());
+    let (outcome, _) = run_build(eff, &build, false, eff.reads_cargo_messages(), None)?;
+    match outcome {
+        WaitOutcome::Exited(_) if outcome.success() => log_info(&format!(
+            "warm: done in {}",
+            rair::format_duration(started.elapsed())
+        )),
+        WaitOutcome::Interrupted => {}
+        _ => log_info("warm: build failed; watching anyway"),
+    }
+    Ok(())
+}
+
 fn kill_group(child: &mut GroupChild) {
     let _ = child.kill();
     let _ = child.wait();
 }
 
-fn load_cfg_file(path: Option<PathBuf>) -> Option<Config> {
-    let p = match path {
-        Some(p) => p,
-        None => {
-            let d = PathBuf::from(".rair.toml");
-            if d.exists() {
-                d
-            } else {
-                return None;
-            }
-        }
-    };
+/// Spawns a `[[services]]` command in its own process group, output forwarded like the run's.
+fn spawn_service_cmd(svc: &Service, argv: &[String]) -> Result<GroupChild> {
+    let mut c = rair::cmd_from_argv(argv)?;
+    c.env("RAIR_ACTIVE", "1");
+    if let Some(dir) = &svc.cwd {
+        c.current_dir(dir);
+    }
+    let mut child = c
+        .stdin(Stdio::null())
+        .stdout(child_stdio())
+        .stderr(child_stdio())
+        .group_spawn()
+        .with_context(|| format!("[{}] {:?}", svc.name, argv))?;
+    if let Some(out) = child.inner().stdout.take() {
+        forward_output(out, true);
+    }
+    if let Some(err) = child.inner().stderr.take() {
+        forward_output(err, false);
+    }
+    Ok(child)
+}
 
-    match rair::load_config(&p) {
-        Ok(cfg) => Some(cfg),
-        Err(e) => {
-            eprintln!("[{}] rair: failed to load {:?}: {:#}", ts(), p, e);
-            None
+/// Builds and (re)starts a service; a failed build keeps the running process.
+fn restart_service(svc: &Service, child: &mut Option<GroupChild>) -> Result<()> {
+    let log = |msg: String| log_info(&format!("[{}] {}", svc.name, msg));
+    if let Some(build) = &svc.build {
+        log(format!("build: {:?}", build));
+        let mut b = spawn_service_cmd(svc, build)?;
+        let outcome = rair::wait_timeout(|| b.try_wait(), None, shutting_down)?;
+        if !outcome.success() {
+            kill_group(&mut b);
+            log("build failed; keeping existing process".into());
+            return Ok(());
+        }
+    }
+    if let Some(run) = &svc.run {
+        if let Some(mut ch) = child.take() {
+            log("stopping previous process".into());
+            kill_group(&mut ch);
         }
+        log(format!("run: {:?}", run));
+        *child = Some(spawn_service_cmd(svc, run)?);
     }
+    Ok(())
 }
 
-fn files_mode_config(files: Vec<PathBuf>) -> Result<Config> {
-    anyhow::ensure!(!files.is_empty(), "no files provided");
+/// Runs one `[[services]]` pipeline until shutdown, independently of the main one: its own
+/// watcher, debounce, build and run process.
+fn run_service(svc: Service, eff: EffectiveConfig) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(
+        move |res| {
+            let _ = tx.send(res);
+        },
+        notify::Config::default(),
+    )
+    .context("create watcher")?;
+    for p in &svc.watch {
+        if let Some((target, mode)) = rair::watch_registration(p, &svc.watch) {
+            watcher.watch(&target, mode).map_err(|e| {
+                anyhow::anyhow!("watch {:?}: {}", p, rair::describe_watch_error(&e))
+            })?;
+        }
+    }
+    let watch_set = WatchSet::new(&svc.watch);
 
-    // Verify all files exist and are .rs files
-    for f in &files {
-        anyhow::ensure!(f.exists(), "file does not exist: {:?}", f);
-        anyhow::ensure!(
-            f.extension().and_then(|s| s.to_str()) == Some("rs"),
-            "not a .rs file: {:?}",
-            f
-        );
+    let mut child = None;
+    restart_service(&svc, &mut child)?;
+    let mut last = Instant::now();
+    while !shutting_down() {
+        let event: notify::Event = match rx.recv_timeout(LOOP_TICK) {
+            Ok(Ok(event)) => event,
+            Ok(Err(e)) => {
+                log_info(&format!(
+                    "[{}] watch error: {}",
+                    svc.name,
+                    rair::describe_watch_error(&e)
+                ));
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(e) => return Err(e).context("watch recv"),
+        };
+        let now = Instant::now();
+        if now.duration_since(last) < svc.debounce {
+            continue;
+        }
+        if !eff.allows_event(&event.kind)
+            || !rair::event_paths(&event)
+                .iter()
+                .any(|p| watch_set.contains(p) && svc.is_relevant(p, &eff))
+        {
+            continue;
+        }
+        last = now;
+        restart_service(&svc, &mut child)?;
     }
+    if let Some(mut ch) = child.take() {
+        kill_group(&mut ch);
+    }
+    Ok(())
+}
 
-    // Build command: compile all files into /tmp/rair-out
-    let mut build_cmd = vec!["rustc".to_string()];
+/// `--once`: the exit code after a single cycle. The run process, if any, is waited on
+/// (up to `run_timeout`) and its exit code is passed through.
+fn once_exit_code(
+    eff: &EffectiveConfig,
+    child: &Mutex<Option<GroupChild>>,
+    outcome: BuildOutcome,
+) -> Result<i32> {
+    if outcome != BuildOutcome::Succeeded {
+        return Ok(1);
+    }
+    if !eff.has_run_step() {
+        return Ok(0);
+    }
+    // No process means a hook or the readiness check failed
+    let Some(mut ch) = child.lock().unwrap().take() else {
+        return Ok(1);
+    };
+    let waited = rair::wait_timeout(|| ch.try_wait(), eff.run_timeout, shutting_down)
+        .context("wait for run process")?;
+    match waited {
+        WaitOutcome::Exited(status) => {
+            log_step(&format!("run exited: {}", status));
+            Ok(status.code().unwrap_or(1))
+        }
+        WaitOutcome::TimedOut => {
+            kill_group(&mut ch);
+            log_info(&format!(
+                "run timed out after {}; killed",
+                rair::format_duration(eff.run_timeout.unwrap_or_default())
+            ));
+            Ok(rair::TIMEOUT_EXIT_CODE)
+        }
+        WaitOutcome::Interrupted => {
+            kill_group(&mut ch);
+            Ok(130)
+        }
+    }
+}
+
+/// The config file in use: `--config`, else `.rair.toml` if present.
+fn config_file_path(path: Option<PathBuf>) -> Option<PathBuf> {
+    path.or_else(|| {
+        let d = PathBuf::from(".rair.toml");
+        d.exists().then_some(d)
+    })
+}
+
+fn load_cfg_file(path: Option<PathBuf>) -> Option<Config> {
+    let p = config_file_path(path)?;
+
+    match rair::load_config(&p) {
+        Ok(cfg) => Some(cfg),
+        Err(e) => {
+            eprintln!("[{}] rair: failed to load {:?}: {:#}", ts(), p, e);
+            None
+        }
+    }
+}
+
+fn files_mode_config(files: Vec<PathBuf>, incremental: bool) -> Result<Config> {
+    anyhow::ensure!(!files.is_empty(), "no files provided");
+
+    // Verify all files exist and are .rs files
     for f in &files {
-        build_cmd.push(f.to_string_lossy().to_string());
+        anyhow::ensure!(f.exists(), "file does not exist: {:?}", f);
+        anyhow::ensure!(
+            f.extension().and_then(|s| s.to_str()) == Some("rs"),
+            "not a .rs file: {:?}",
+            f
+        );
     }
-    build_cmd.push("-o".to_string());
-    build_cmd.push("/tmp/rair-out".to_string());
+
+    // Build command: compile all files into /tmp/rair-out
+    let build_cmd = if incremental {
+        let mut names = std::collections::HashSet::new();
+        for f in &files {
+            let name = rair::file_crate_name(f);
+            anyhow::ensure!(names.insert(name.clone()), "two files named {:?}", name);
+        }
+        // rair compiles the changed libraries first (see build_incremental); this links
+        rair::IncrementalFiles::new(files.clone(), PathBuf::from("/tmp/rair-out"))
+            .rustc_argv(&files[0])
+    } else {
+        let mut cmd = vec!["rustc".to_string()];
+        for f in &files {
+            cmd.push(f.to_string_lossy().to_string());
+        }
+        cmd.push("-o".to_string());
+        cmd.push("/tmp/rair-out".to_string());
+        cmd
+    };
 
     Ok(Config {
-        watch: Some(vec![".".to_string()]), // Always watch current directory
-        include_ext: Some(vec!["rs".to_string()]),
+        watch: Some(vec![".".into()]), // Always watch current directory
+        only_ext: Some(vec!["rs".to_string()]),
         ignore: Some(vec!["**/target/**".to_string(), "**/.git/**".to_string()]),
         build: Some(build_cmd),
         run: Some(vec!["/tmp/rair-out".to_string()]),
@@ -202,7 +985,11 @@ fn files_mode_config(files: Vec<PathBuf>) -> Result<Config> {
 fn cli_to_config(cli: Cli) -> Result<Config> {
     // If files are provided, use files mode
     if !cli.files.is_empty() {
-        return files_mode_config(cli.files);
+        let cfg = files_mode_config(cli.files, cli.incremental)?;
+        return Ok(Config {
+            run_args: (!cli.run_args.is_empty()).then_some(cli.run_args),
+            ..cfg
+        });
     }
 
     // Otherwise use flag-based mode
@@ -210,13 +997,14 @@ fn cli_to_config(cli: Cli) -> Result<Config> {
         watch: if cli.watch.is_empty() {
             None
         } else {
-            Some(cli.watch)
+            Some(cli.watch.into_iter().map(WatchEntry::from).collect())
         },
         ignore: if cli.ignore.is_empty() {
             None
         } else {
             Some(cli.ignore)
         },
+        ignore_engine: cli.ignore_engine,
         include_ext: if cli.include_ext.is_empty() {
             None
         } else {
@@ -227,20 +1015,100 @@ fn cli_to_config(cli: Cli) -> Result<Config> {
         } else {
             Some(cli.exclude_ext)
         },
+        only_ext: if cli.only_ext.is_empty() {
+            None
+        } else {
+            Some(cli.only_ext)
+        },
+        events: if cli.watch_events.is_empty() {
+            None
+        } else {
+            Some(cli.watch_events)
+        },
+        watch_metadata: cli.watch_metadata.then_some(true),
         debounce_ms: cli.debounce_ms,
+        debounce_mode: cli.debounce_mode,
+        burst_threshold: cli.burst_threshold,
+        verbose: cli.verbose.then_some(true),
+        timings: cli.timings.then_some(true),
+        timestamp_format: cli.timestamp_format,
+        timestamp_utc: cli.timestamp_utc.then_some(true),
+        per_path_cooldown_ms: cli.per_path_cooldown_ms,
+        min_build_interval_ms: cli.min_build_interval_ms,
+        git_tracked_only: cli.git_tracked_only.then_some(true),
+        git_include_untracked: cli.git_include_untracked.then_some(true),
         clear: cli.clear,
+        clear_on_fail: cli.clear_on_fail,
+        bell: cli.bell,
+        clear_before: cli.clear_before,
+        ignore_editor_temp: cli.ignore_editor_temp,
+        include_hidden: cli.include_hidden.then_some(true),
+        follow_symlinks: cli.follow_symlinks.then_some(true),
+        status_line: cli.status_line.then_some(true),
+        concise: cli.concise.then_some(true),
+        strict_exit: cli.strict_exit.then_some(true),
+        keep_going: cli.keep_going.then_some(true),
+        watcher_restarts: cli.watcher_restarts,
+        control_socket: cli.control_socket,
+        http_trigger_port: cli.http_trigger_port,
+        interactive: cli.interactive.then_some(true),
+        trigger_file: cli.trigger_file,
+        reload_config_on_change: cli.reload_config_on_change,
+        buffer_output: cli.buffer_output.then_some(true),
+        collapse_repeated_errors: cli.collapse_repeated_errors.then_some(true),
+        fail_tail_lines: cli.fail_tail_lines,
+        watch_build_script_inputs: cli.watch_build_script_inputs.then_some(true),
+        list_artifacts: cli.list_artifacts.then_some(true),
+        parse_diagnostics: cli.parse_diagnostics.then_some(true),
+        pty: cli.pty.then_some(true),
+        monitor: cli.monitor.then_some(true),
+        monitor_interval_ms: cli.monitor_interval_ms,
+        watch_stats: cli.watch_stats.then_some(true),
+        watch_stats_interval_ms: cli.watch_stats_interval_ms,
+        check_updates: cli.check_updates.then_some(true),
         build: if cli.build.is_empty() {
             None
         } else {
             Some(cli.build)
         },
+        build_cwd: cli.build_cwd,
+        run_args: if cli.run_args.is_empty() {
+            None
+        } else {
+            Some(cli.run_args)
+        },
         run: if cli.run.is_empty() {
             None
         } else {
             Some(cli.run)
         },
+        run_cwd: cli.run_cwd,
+        backtrace: cli.backtrace,
+        run_stdin: cli.run_stdin,
+        env_file: cli.env_file,
+        once: cli.once.then_some(true),
+        build_only: cli.build_only.then_some(true),
+        defer_run: cli.defer_run.then_some(true),
+        warm: cli.warm.then_some(true),
+        no_run: cli.no_run.then_some(true),
+        run_timeout_ms: cli.run_timeout_ms,
+        initial_build_retries: cli.initial_build_retries,
+        runner: if cli.runner.is_empty() {
+            None
+        } else {
+            Some(cli.runner)
+        },
+        overlap_restart: cli.overlap_restart.then_some(true),
+        restart_on_exit: cli.restart_on_exit.then_some(true),
+        on_fail_action: cli.on_fail_action,
+        crash_cooldown_ms: cli.crash_cooldown_ms,
+        skip_unchanged: cli.skip_unchanged.then_some(true),
+        hooks_on_noop: cli.hooks_on_noop.then_some(true),
 
+        cargo: cli.no_cargo.then_some(false),
         manifest_path: cli.manifest_path,
+        extra_manifests: (!cli.extra_manifests.is_empty()).then_some(cli.extra_manifests),
+        target_dir: cli.target_dir,
         package: cli.package,
         bin: cli.bin,
         features: if cli.features.is_empty() {
@@ -248,59 +1116,280 @@ fn cli_to_config(cli: Cli) -> Result<Config> {
         } else {
             Some(cli.features)
         },
-        all_features: Some(cli.all_features),
-        no_default_features: Some(cli.no_default_features),
-        workspace: Some(cli.workspace),
-        release: Some(cli.release),
-
-        pre_build: None,
-        post_build: None,
-        pre_run: None,
-        post_run: None,
-        on_build_fail: None,
+        // Flags only override lower layers when actually passed
+        all_features: cli.all_features.then_some(true),
+        no_default_features: cli.no_default_features.then_some(true),
+        cargo_extra_args: if cli.cargo_extra_args.is_empty() {
+            None
+        } else {
+            Some(cli.cargo_extra_args)
+        },
+        workspace: cli.workspace.then_some(true),
+        release: cli.release.then_some(true),
+        locked: cli.locked.then_some(true),
+        frozen: cli.frozen.then_some(true),
+        offline: cli.offline.then_some(true),
+        watch_workspace: cli.watch_workspace_members.then_some(true),
+        mode: cli.mode,
+        test_runner: cli.test_runner,
+        bench: cli.bench,
+        doc_deps: cli.doc_deps.then_some(true),
+        doc_args: if cli.doc_args.is_empty() {
+            None
+        } else {
+            Some(cli.doc_args)
+        },
+        open_docs: cli.open_docs.then_some(true),
+        fmt_check: cli.fmt_check.then_some(true),
+        clippy: cli.clippy.then_some(true),
+
+        // Hooks and readiness are config-file only
+        ..Default::default()
     })
 }
 
-fn cargo_metadata_target_dir(manifest_path: Option<&PathBuf>) -> Result<PathBuf> {
-    let mut cmd = MetadataCommand::new();
-    if let Some(mp) = manifest_path {
-        cmd.manifest_path(mp);
+fn run_post_run_hooks(eff: &EffectiveConfig) {
+    match rair::run_hook_list("post_run", &eff.post_run) {
+        Ok(true) => {}
+        Ok(false) => log_info("post_run hook failed (ignored)"),
+        Err(e) => log_info(&format!("post_run hook error (ignored): {:#}", e)),
+    }
+}
+
+/// Waits for readiness (unless `ready` already says) and runs the `post_ready` hooks on
+/// a thread of their own, so the watch loop keeps going meanwhile. The thread gives up
+/// quietly once a later cycle moves `generations` past `generation`.
+fn run_post_ready_hooks(
+    eff: &EffectiveConfig,
+    ready: Option<bool>,
+    generations: &PostReadyGeneration,
+    generation: u64,
+) -> Option<std::thread::JoinHandle<()>> {
+    if !eff.has_readiness_probe() && eff.post_ready.is_empty() {
+        return None;
+    }
+    let (eff, generations) = (eff.clone(), generations.clone());
+    Some(std::thread::spawn(move || {
+        let current = || generations.is_current(generation);
+        match rair::run_post_ready(&eff, ready, current) {
+            _ if !current() => {}
+            Ok(true) => log_info("run process ready"),
+            Ok(false) => log_info("run process not ready or post_ready hook failed (ignored)"),
+            Err(e) => log_info(&format!("post_ready hook error (ignored): {:#}", e)),
+        }
+    }))
+}
+
+/// `rair trigger`: sends a restart to the instance listening on the control socket.
+fn send_trigger(config: Option<PathBuf>, socket: Option<PathBuf>) -> Result<()> {
+    let socket =
+        match socket {
+            Some(s) => s,
+            None => {
+                let file_cfg = load_cfg_file(config).unwrap_or_default();
+                let cfg = rair::merge_config(file_cfg, Config::from_env()?);
+                PathBuf::from(cfg.control_socket.context(
+                    "no control socket: pass --socket or set control_socket in the config",
+                )?)
+            }
+        };
+    #[cfg(unix)]
+    {
+        rair::send_control_command(&socket, ControlCommand::Restart)
+    }
+    #[cfg(not(unix))]
+    {
+        anyhow::bail!("control sockets are only supported on unix ({:?})", socket)
     }
-    let md = cmd.exec().context("cargo metadata")?;
-    Ok(md.target_directory.into_std_path_buf())
 }
 
-fn resolve_bin_name(eff: &EffectiveConfig) -> Result<String> {
-    if let Some(b) = &eff.bin {
-        return Ok(b.clone());
+/// `rair check-config`: the config file merged with RAIR_* variables, as the watch loop
+/// would see it, linted by `rair::validate`.
+fn check_config(config: Option<PathBuf>) -> Result<()> {
+    let path = config_file_path(config);
+    let file_cfg = match &path {
+        Some(p) => rair::load_config(p)?,
+        None => Config::default(),
+    };
+    let cfg = rair::merge_config(file_cfg, Config::from_env()?);
+    let name = path.map_or_else(|| "defaults".to_string(), |p| p.display().to_string());
+    let problems = rair::validate(&cfg);
+    if problems.is_empty() {
+        println!("{}: ok", name);
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}: {}", name, problem);
     }
-    if let Some(p) = &eff.package {
-        return Ok(p.clone());
+    match problems.len() {
+        1 => anyhow::bail!("1 problem in {}", name),
+        n => anyhow::bail!("{} problems in {}", n, name),
     }
-    let cwd = std::env::current_dir().context("cwd")?;
-    let name = cwd
-        .file_name()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| anyhow::anyhow!("cannot infer bin name; specify --bin or config bin"))?;
-    Ok(name.to_string())
 }
 
-fn build_default_run_argv(eff: &EffectiveConfig) -> Result<Vec<String>> {
-    let target_dir = cargo_metadata_target_dir(eff.manifest_path.as_ref())?;
-    let bin = resolve_bin_name(eff)?;
-    let exe = rair::exe_path(&target_dir, eff.release, &bin);
-    Ok(vec![exe.to_string_lossy().to_string()])
+/// The build step of `--incremental` files mode: recompiles the files whose inputs
+/// changed since their last successful compile, then relinks the binary. Each rustc runs
+/// like any build; the outcome is the first failure's, or success if nothing failed.
+fn build_incremental(
+    eff: &EffectiveConfig,
+    files: &rair::IncrementalFiles,
+    capture: bool,
+    timeout: Option<Duration>,
+) -> Result<(WaitOutcome, Option<OutputBuffer>)> {
+    let mut outcome = WaitOutcome::Exited(ExitStatus::default());
+    let mut output = capture.then(OutputBuffer::default);
+    files.compile(|argv| {
+        let (step, captured) = run_build(eff, argv, capture, false, timeout)?;
+        if let (Some(output), Some(captured)) = (output.as_mut(), captured) {
+            for line in captured.into_lines() {
+                output.push(&line.text, line.stdout);
+            }
+        }
+        outcome = step;
+        Ok(step.success())
+    })?;
+    Ok((outcome, output))
 }
 
-fn run_post_run_hooks(eff: &EffectiveConfig) {
-    match rair::run_hook_list("post_run", &eff.post_run) {
-        Ok(true) => {}
-        Ok(false) => log_info("post_run hook failed (ignored)"),
-        Err(e) => log_info(&format!("post_run hook error (ignored): {:#}", e)),
+/// Moves the watches from the `old` entries to the `new` ones after a config reload;
+/// entries that can't be watched (yet) are retried like at startup.
+fn rewatch(
+    watcher: &mut RecommendedWatcher,
+    old: &EffectiveConfig,
+    new: &EffectiveConfig,
+    missing: &mut MissingWatches,
+) {
+    for p in &old.watch {
+        if let Some((target, _)) = old.watch_registration(p) {
+            let _ = watcher.unwatch(&target);
+        }
+    }
+    *missing = MissingWatches::default();
+    for p in &new.watch {
+        if !p.exists() {
+            log_info(&format!("watch path missing (will retry): {:?}", p));
+            missing.push(p.clone());
+            continue;
+        }
+        if let Err(e) = new.register_watch(watcher, p) {
+            log_info(&format!(
+                "watch {:?} failed (will retry): {}",
+                p,
+                rair::describe_watch_error(&e)
+            ));
+            missing.push(p.clone());
+        }
+    }
+}
+
+/// A watcher that forwards its events to the main loop through `tx`.
+fn new_watcher(tx: mpsc::Sender<LoopEvent>) -> Result<RecommendedWatcher> {
+    RecommendedWatcher::new(
+        move |res| {
+            let _ = tx.send(LoopEvent::Fs(Instant::now(), res));
+        },
+        notify::Config::default(),
+    )
+    .context("create watcher")
+}
+
+/// `follow_symlinks`: swaps the watches on previously followed symlink targets for
+/// `symlinks`' targets.
+fn watch_followed(watcher: &mut RecommendedWatcher, old: &[PathBuf], symlinks: &SymlinkMap) {
+    for dir in old {
+        let _ = watcher.unwatch(dir);
+    }
+    for dir in symlinks.followed() {
+        if let Err(e) = watcher.watch(dir, RecursiveMode::Recursive) {
+            log_info(&format!(
+                "watch symlink target {:?} failed: {}",
+                dir,
+                rair::describe_watch_error(&e)
+            ));
+        }
+    }
+}
+
+/// `check_updates`: looks up the latest release in the background and logs a notice if
+/// it's newer. Never holds up startup; a failed lookup is silently skipped.
+#[cfg(feature = "update-check")]
+fn check_for_update() {
+    std::thread::spawn(|| {
+        let cache = std::env::temp_dir().join("rair-update-check");
+        let Ok(latest) = rair::latest_version(&cache) else {
+            return;
+        };
+        let current = env!("CARGO_PKG_VERSION");
+        if rair::is_newer_version(&latest, current) {
+            log_info(&format!(
+                "rair {} is available (this is {}); run `cargo install rair` to update",
+                latest, current
+            ));
+        }
+    });
+}
+
+#[cfg(not(feature = "update-check"))]
+fn check_for_update() {
+    log_info("check_updates needs rair built with the `update-check` feature; ignoring");
+}
+
+/// The built binary's argv, from cargo metadata that's looked up once and kept until a
+/// manifest changes.
+fn default_run_argv(
+    eff: &EffectiveConfig,
+    cargo_target: &mut Option<CargoTarget>,
+) -> Result<Vec<String>> {
+    if cargo_target.is_none() {
+        *cargo_target = Some(rair::cargo_target(eff.manifest_path.as_deref())?);
+    }
+    let target = cargo_target.as_ref().expect("cargo target resolved above");
+    rair::default_run_argv(eff, target)
+}
+
+/// Pretty-printed JSON for `--format json`.
+fn print_json<T: serde::Serialize>(value: &T) -> Result<()> {
+    #[cfg(feature = "json")]
+    {
+        println!("{}", serde_json::to_string_pretty(value)?);
+        Ok(())
+    }
+    #[cfg(not(feature = "json"))]
+    {
+        let _ = value;
+        anyhow::bail!("--format json needs rair built with the `json` feature")
     }
 }
 
 fn main() -> Result<()> {
+    let mut cli = Cli::parse();
+    match cli.command.take() {
+        Some(Cmd::Trigger { socket }) => return send_trigger(cli.config, socket),
+        Some(Cmd::CheckConfig { config }) => return check_config(config.or(cli.config)),
+        None => {}
+    }
+    if let Some(kind) = &cli.complete_targets {
+        let manifest = cli.manifest_path.as_deref().map(Path::new);
+        for name in rair::target_names(manifest, kind)? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    if cli.list_targets {
+        let manifest = cli.manifest_path.as_deref().map(Path::new);
+        let targets = rair::list_targets(manifest)?;
+        if cli.format == "json" {
+            return print_json(&targets);
+        }
+        for t in targets {
+            println!("{}\t{}\t{}", t.package, t.name, t.kind);
+        }
+        return Ok(());
+    }
+    let (print_config, format) = (cli.print_config, cli.format.clone());
+    let incremental = (cli.incremental && !cli.files.is_empty())
+        .then(|| rair::IncrementalFiles::new(cli.files.clone(), PathBuf::from("/tmp/rair-out")));
+
     // Prevent recursive watching - if we're already being watched by rair, don't watch again
     if std::env::var("RAIR_ACTIVE").is_ok() {
         eprintln!("Error: rair is already watching this process");
@@ -309,132 +1398,1126 @@ fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    let cli = Cli::parse();
-
     // Determine config source priority:
     // 1. If files provided as args → use files mode (ignore config file)
-    // 2. Otherwise → merge config file + CLI flags
-    let (cli_cfg, file_cfg) = if !cli.files.is_empty() {
-        (cli_to_config(cli)?, None)
-    } else {
+    // 2. Otherwise → merge config file < RAIR_* env vars < CLI flags
+    let (cli_cfg, file_cfg, mut config_source) = if !cli.files.is_empty() {
+        let cli_cfg = cli_to_config(cli)?;
         (
-            cli_to_config(cli.clone())?,
-            load_cfg_file(cli.config.clone()),
+            cli_cfg.clone(),
+            None,
+            ConfigSource::new(cli_cfg, None, Config::default()),
         )
+    } else {
+        let path = config_file_path(cli.config.clone());
+        let file_cfg = load_cfg_file(path.clone()).unwrap_or_default();
+        let env_cfg = Config::from_env()?;
+        let cli_cfg = cli_to_config(cli)?;
+        let source = ConfigSource::new(cli_cfg.clone(), path.as_deref(), file_cfg.clone());
+        (cli_cfg, Some(rair::merge_config(file_cfg, env_cfg)), source)
     };
 
-    let eff: EffectiveConfig = rair::effective_config(cli_cfg, file_cfg)?;
+    let mut eff: EffectiveConfig = rair::effective_config(cli_cfg, file_cfg)?;
+    let _ = TIMESTAMP.set(eff.timestamp.clone());
 
-    let child: Arc<Mutex<Option<GroupChild>>> = Arc::new(Mutex::new(None));
+    if eff.watch_workspace {
+        let members = rair::workspace_watch_paths(eff.manifest_path.as_deref())?;
+        rair::add_watch_paths(&mut eff.watch, members);
+    }
+    if print_config {
+        if format == "json" {
+            return print_json(&eff);
+        }
+        print!("{}", toml::to_string_pretty(&eff).context("print config")?);
+        return Ok(());
+    }
 
-    // watcher channel
-    let (tx, rx) = mpsc::channel();
-    let mut watcher: RecommendedWatcher =
-        RecommendedWatcher::new(tx, notify::Config::default()).context("create watcher")?;
+    if eff.status_line && io::stderr().is_terminal() {
+        *STATUS_LINE.lock().unwrap() = Some(String::new());
+    }
 
-    let mut watched_any = false;
-    for p in &eff.watch {
-        if !p.exists() {
-            log_info(&format!("watch path missing (skipped): {:?}", p));
-            continue;
-        }
-        watcher
-            .watch(p, RecursiveMode::Recursive)
-            .with_context(|| format!("watch {:?}", p))?;
-        watched_any = true;
+    if eff.check_updates {
+        check_for_update();
     }
-    anyhow::ensure!(watched_any, "no watch paths exist");
+
+    let started = Instant::now();
+    let mut stats = Stats::default();
+    ctrlc::set_handler(|| SHUTDOWN.store(true, Ordering::SeqCst))
+        .context("install Ctrl-C handler")?;
+
+    let child: Arc<Mutex<Option<GroupChild>>> = Arc::new(Mutex::new(None));
 
     // Start / restart helper
-    let start_app = |eff: &EffectiveConfig, child: &Arc<Mutex<Option<GroupChild>>>| -> Result<()> {
+    // cargo metadata (target dir, root package) for the run argv; reset when a manifest changes
+    let mut cargo_target: Option<CargoTarget> = None;
+
+    // last failed build's output, for collapse_repeated_errors
+    let mut failures = FailureCache::default();
+    // rerun-if-changed paths reported by build scripts, for watch_build_script_inputs
+    let build_inputs = RefCell::new(BuildScriptInputs::default());
+    // how long this cycle's phases took, for timings
+    let timeline = RefCell::new(Timeline::default());
+    // binary the run process was started from, for skip_unchanged
+    let mut running_artifact: Option<ArtifactStamp> = None;
+    // whether the last build failed, for the bell's recovery cue
+    let mut last_build_failed = false;
+    // argv of the last run process started after a good build, for restart_last_good
+    let last_good_run: RefCell<Option<Vec<String>>> = RefCell::new(None);
+    // relevant paths changed since the last build, for `{changed}` in the build argv
+    let pending_changes: RefCell<ChangeBatch> = RefCell::new(ChangeBatch::default());
+    // the last cycle's post_ready hooks, which once-mode lets finish
+    let post_ready_hooks: RefCell<Option<std::thread::JoinHandle<()>>> = RefCell::new(None);
+    // which cycle's post_ready may still run; an older probe stops once a restart begins
+    let post_ready_generation = PostReadyGeneration::default();
+
+    // Everything up to post_build. Some(outcome) ends the cycle there; None goes on to run
+    let mut build_app = |eff: &EffectiveConfig| -> Result<Option<BuildOutcome>> {
+        // buffer_output keeps the previous output on screen until a build succeeds
+        if !eff.buffer_output && eff.clears_at(BuildOutcome::Pending) {
+            clear_screen()?;
+        }
+
         // pre_build
-        if !rair::run_hook_list("pre_build", &eff.pre_build)? {
-            log_info("pre_build failed; skipping build");
-            return Ok(());
+        if !rair::run_hook_list_timed("pre_build", &eff.pre_build, &mut timeline.borrow_mut())? {
+            set_status(Status::StepFailed("pre_build".into()));
+            log_step("pre_build failed; skipping build");
+            return Ok(Some(BuildOutcome::Pending));
+        }
+
+        // fmt_check / clippy
+        for check in eff.checks() {
+            let argv = check.argv(eff);
+            log_detail(eff, &format!("{}: {:?}", check.name(), argv));
+            let check_started = Instant::now();
+            let (status, output) = rair::run_captured(rair::cmd_from_argv(&argv)?)
+                .with_context(|| format!("{}: {:?}", check.name(), argv))?;
+            timeline
+                .borrow_mut()
+                .record(check.name(), check_started.elapsed());
+            if let Some(why) = check.failure(status.success(), &output) {
+                // the fmt diff or clippy's diagnostics
+                for line in output.into_lines() {
+                    emit_line(&line.text, line.stdout);
+                }
+                set_status(Status::StepFailed(check.name().into()));
+                log_step(&why);
+                return Ok(Some(BuildOutcome::Pending));
+            }
         }
 
         // build
-        let ok = run_build(&eff.build)?;
-        if !ok {
-            let _ = rair::run_hook_list("on_build_fail", &eff.on_build_fail);
-            log_info("build failed; keeping existing process");
-            return Ok(());
+        let capture =
+            eff.buffer_output || eff.collapse_repeated_errors || eff.fail_tail_lines.is_some();
+        // in test mode the build step is the test run
+        let timeout = eff.run_timeout.filter(|_| eff.mode == Mode::Test);
+        let changes = std::mem::take(&mut *pending_changes.borrow_mut());
+        if !changes.is_empty() {
+            log_verbose(eff, &format!("changed: {}", changes.describe(&eff.root)));
+        }
+        let build = rair::expand_build_argv(&eff.build, &changes.paths(), &eff.project_root());
+        let build_started = Instant::now();
+        let (outcome, mut captured) = match &incremental {
+            Some(files) => build_incremental(eff, files, capture, timeout)?,
+            None => run_build(eff, &build, capture, eff.reads_cargo_messages(), timeout)?,
+        };
+        timeline
+            .borrow_mut()
+            .record("build", build_started.elapsed());
+        let mut artifacts = Vec::new();
+        let mut warnings = 0;
+        let mut errors = None;
+        if eff.reads_cargo_messages() {
+            if let Some(output) = captured.as_mut() {
+                let messages = rair::take_cargo_messages(output);
+                if eff.watch_build_script_inputs {
+                    build_inputs
+                        .borrow_mut()
+                        .extend(messages.build_script_inputs);
+                }
+                artifacts = messages.artifacts;
+                warnings = messages.warnings;
+                errors = eff.counts_warnings().then_some(messages.errors);
+            }
+            // only stdout was held for its JSON messages; show whatever else it printed
+            if !capture {
+                for line in captured
+                    .take()
+                    .map(OutputBuffer::into_lines)
+                    .unwrap_or_default()
+                {
+                    emit_line(&line.text, line.stdout);
+                }
+            }
+        }
+        match outcome {
+            WaitOutcome::Interrupted => return Ok(Some(BuildOutcome::Pending)),
+            WaitOutcome::TimedOut => log_info(&format!(
+                "tests timed out after {}; killed",
+                rair::format_duration(timeout.unwrap_or_default())
+            )),
+            WaitOutcome::Exited(_) => {}
+        }
+        let mut repeated = false;
+        if let Some(output) = captured {
+            if outcome.success() {
+                failures.clear();
+                if eff.buffer_output && eff.clear && eff.clear_before != ClearBefore::Never {
+                    clear_screen()?;
+                }
+                for line in output.release(true) {
+                    emit_line(&line.text, line.stdout);
+                }
+            } else {
+                // If the screen was cleared before this build, the earlier errors are gone
+                let cleared = eff.clears_at(BuildOutcome::Pending) && !eff.buffer_output;
+                repeated = eff.collapse_repeated_errors && failures.is_repeat(&output) && !cleared;
+                // a repeat is reported as one line below; fail_tail_lines beats buffer_output
+                let shown = match eff.fail_tail_lines {
+                    _ if repeated => None,
+                    Some(n) => Some(output.into_tail(n)),
+                    None if eff.buffer_output => {
+                        log_step(&format!("build output withheld ({} lines)", output.len()));
+                        None
+                    }
+                    None => Some((0, output.into_lines())),
+                };
+                if let Some((omitted, lines)) = shown {
+                    if omitted > 0 {
+                        log_info(&format!(
+                            "... {} earlier lines of build output omitted",
+                            omitted
+                        ));
+                    }
+                    for line in lines {
+                        emit_line(&line.text, line.stdout);
+                    }
+                }
+            }
+        }
+        ring_bell(eff.bell.cue(outcome.success(), last_build_failed));
+        last_build_failed = !outcome.success();
+        if !outcome.success() {
+            let env = rair::build_fail_env(&build, outcome.code(), errors);
+            let _ = rair::run_hook_list_with_env("on_build_fail", &eff.on_build_fail, &env);
+            // BuildOutcome::Failed never clears: keep the errors on screen
+            set_status(Status::BuildFailed);
+            let decision = rair::on_fail_decision(
+                eff.on_fail_action,
+                child.lock().unwrap().is_some(),
+                last_good_run.borrow().is_some(),
+            );
+            if repeated {
+                log_step(rair::REPEATED_FAILURE_MSG);
+            } else {
+                log_step(decision.describe());
+            }
+            if decision != RunDecision::KeepFailed {
+                let mut guard = child.lock().unwrap();
+                if let Some(mut ch) = guard.take() {
+                    kill_group(&mut ch);
+                }
+                if let Some(argv) = last_good_run.borrow().as_ref() {
+                    if decision == RunDecision::RestartLastGood {
+                        *guard = Some(spawn_run_group(argv, eff)?);
+                        set_status(Status::BuildFailed);
+                    }
+                }
+            }
+            return Ok(Some(BuildOutcome::Failed));
+        }
+        if eff.list_artifacts {
+            for path in &artifacts {
+                log_info(&format!("artifact: {}", path.display()));
+            }
+        }
+        if eff.counts_warnings() {
+            log_info(&rair::format_build_ok(warnings));
         }
 
-        // post_build
-        if !rair::run_hook_list("post_build", &eff.post_build)? {
-            log_info("post_build failed; keeping existing process");
-            return Ok(());
+        Ok(None)
+    };
+
+    // post_build, with `{exe}` standing for the built binary. false ends the cycle there
+    let post_build_step =
+        |eff: &EffectiveConfig, cargo_target: &mut Option<CargoTarget>| -> Result<bool> {
+            let mut post_build = Cow::Borrowed(&eff.post_build);
+            if post_build.iter().any(|h| h.uses(EXE_PLACEHOLDER)) {
+                match rair::resolve_exe(eff, |eff| default_run_argv(eff, cargo_target)) {
+                    Ok(exe) => {
+                        let expand = |h: &Hook| h.expand(EXE_PLACEHOLDER, &exe);
+                        post_build = Cow::Owned(eff.post_build.iter().map(expand).collect());
+                    }
+                    Err(e) => {
+                        set_status(Status::StepFailed("post_build".into()));
+                        log_step(&format!("post_build: can't resolve {{exe}}: {:#}", e));
+                        return Ok(false);
+                    }
+                }
+            }
+            if !rair::run_hook_list_timed("post_build", &post_build, &mut timeline.borrow_mut())? {
+                set_status(Status::StepFailed("post_build".into()));
+                log_step("post_build failed; keeping existing process");
+                return Ok(false);
+            }
+            Ok(true)
+        };
+
+    // open_docs only opens the browser once
+    let mut docs_opened = false;
+
+    // Build (unless only assets changed) and restart the run process (unless !run)
+    let mut run_cycle = |eff: &EffectiveConfig,
+                         child: &Arc<Mutex<Option<GroupChild>>>,
+                         cargo_target: &mut Option<CargoTarget>,
+                         rebuild: bool,
+                         run: bool|
+     -> Result<BuildOutcome> {
+        let started = Instant::now();
+        let summarize = |pid| {
+            if eff.concise {
+                log_step(&rair::format_cycle_summary(rebuild, pid, started.elapsed()));
+            }
+        };
+        if rebuild {
+            if let Some(outcome) = build_app(eff)? {
+                return Ok(outcome);
+            }
         }
 
-        // pre_run
-        if !rair::run_hook_list("pre_run", &eff.pre_run)? {
-            log_info("pre_run failed; keeping existing process");
-            return Ok(());
+        if !eff.has_run_step() {
+            if rebuild && !post_build_step(eff, cargo_target)? {
+                return Ok(BuildOutcome::Succeeded);
+            }
+            set_status(Status::Built);
+            summarize(None);
+            if !docs_opened {
+                if let Some(argv) = eff.open_docs_argv() {
+                    docs_opened = true;
+                    open_docs(&argv, eff);
+                }
+            }
+            return Ok(BuildOutcome::Succeeded);
+        }
+        if !run {
+            if rebuild && !post_build_step(eff, cargo_target)? {
+                return Ok(BuildOutcome::Succeeded);
+            }
+            set_status(Status::Built);
+            summarize(None);
+            log_step("defer_run: the run process starts with the first change");
+            return Ok(BuildOutcome::Succeeded);
         }
 
         // determine run argv
-        let run_argv = match &eff.run {
-            Some(v) => v.clone(),
-            None => build_default_run_argv(eff)?,
-        };
+        let run_argv = rair::resolve_run_argv(eff, |eff| default_run_argv(eff, cargo_target))?;
+
+        // restart, unless the running process already runs this binary
+        let built = run_argv
+            .first()
+            .and_then(|exe| ArtifactStamp::of(Path::new(exe)));
+        if eff.skip_unchanged && rebuild {
+            let mut guard = child.lock().unwrap();
+            let live_pid = guard
+                .as_mut()
+                .and_then(|c| matches!(c.try_wait(), Ok(None)).then(|| c.id()));
+            let running = live_pid.and(running_artifact);
+            let decision = rair::restart_decision(true, running, built);
+            log_verbose(eff, &format!("run decision: {}", decision.describe()));
+            if decision == RunDecision::KeepUnchanged {
+                drop(guard);
+                log_step(decision.describe());
+                // a no-op build: the post hooks only fire with hooks_on_noop
+                if rair::runs_post_hooks(decision, eff.hooks_on_noop) {
+                    if !post_build_step(eff, cargo_target)? {
+                        return Ok(BuildOutcome::Succeeded);
+                    }
+                    run_post_run_hooks(eff);
+                    // the same process keeps running: its readiness was settled already
+                    *post_ready_hooks.borrow_mut() = run_post_ready_hooks(
+                        eff,
+                        Some(true),
+                        &post_ready_generation,
+                        post_ready_generation.next(),
+                    );
+                }
+                if let Some(pid) = live_pid {
+                    set_status(Status::Running { pid });
+                }
+                return Ok(BuildOutcome::Succeeded);
+            }
+        }
+        if rebuild && !post_build_step(eff, cargo_target)? {
+            return Ok(BuildOutcome::Succeeded);
+        }
+
+        // pre_run
+        if !rair::run_hook_list_timed("pre_run", &eff.pre_run, &mut timeline.borrow_mut())? {
+            set_status(Status::StepFailed("pre_run".into()));
+            log_step("pre_run failed; keeping existing process");
+            return Ok(BuildOutcome::Succeeded);
+        }
 
         // restart
+        let mut ready = None;
+        // from here on the previous cycle's post_ready is stale
+        let generation = post_ready_generation.next();
         {
             let mut guard = child.lock().unwrap();
-            if let Some(ch) = guard.as_mut() {
-                log_info("stopping previous process");
-                kill_group(ch);
+            let mut new_child = None;
+            for step in rair::restart_steps(eff.overlap_restart, guard.is_some()) {
+                match step {
+                    RestartStep::StopOld => {
+                        if let Some(mut ch) = guard.take() {
+                            log_detail(eff, "stopping previous process");
+                            kill_group(&mut ch);
+                        }
+                    }
+                    RestartStep::StartNew => {
+                        // with buffer_output the screen was cleared before replaying the build
+                        if !eff.buffer_output && eff.clears_at(BuildOutcome::Succeeded) {
+                            clear_screen()?;
+                        }
+                        let spawn_started = Instant::now();
+                        new_child = Some(spawn_run_group(&run_argv, eff)?);
+                        *last_good_run.borrow_mut() = Some(run_argv.clone());
+                        timeline.borrow_mut().record("run", spawn_started.elapsed());
+                    }
+                    RestartStep::AwaitReady => {
+                        if !eff.has_readiness_probe() {
+                            continue;
+                        }
+                        if !eff.wait_until_ready() {
+                            log_info("new process never became ready; keeping previous process");
+                            if let Some(mut ch) = new_child.take() {
+                                kill_group(&mut ch);
+                            }
+                            return Ok(BuildOutcome::Succeeded);
+                        }
+                        ready = Some(true);
+                    }
+                }
             }
-            if eff.clear {
-                clear_screen()?;
+            if let Some(ch) = &new_child {
+                running_artifact = built;
+                summarize(Some(ch.id()));
             }
-            *guard = Some(spawn_run_group(&run_argv)?);
+            *guard = new_child;
         }
 
         run_post_run_hooks(eff);
-        Ok(())
+        *post_ready_hooks.borrow_mut() =
+            run_post_ready_hooks(eff, ready, &post_ready_generation, generation);
+        Ok(BuildOutcome::Succeeded)
+    };
+
+    // A cycle, followed by its timeline with timings (or verbose)
+    let mut start_app = |eff: &EffectiveConfig,
+                         child: &Arc<Mutex<Option<GroupChild>>>,
+                         cargo_target: &mut Option<CargoTarget>,
+                         rebuild: bool,
+                         run: bool|
+     -> Result<BuildOutcome> {
+        timeline.borrow_mut().clear();
+        let outcome = run_cycle(eff, child, cargo_target, rebuild, run)?;
+        let timeline = timeline.borrow();
+        if (eff.timings || eff.verbose) && !timeline.is_empty() {
+            log_step(&format!("timings: {}", timeline.format()));
+        }
+        Ok(outcome)
+    };
+
+    let mut first_build = |stats: &mut Stats| {
+        rair::retry_initial_build(
+            eff.initial_build_retries,
+            || {
+                let run = eff.runs_after_build(true);
+                let outcome = start_app(&eff, &child, &mut cargo_target, true, run)?;
+                stats.record(outcome);
+                Ok(outcome)
+            },
+            |n| {
+                log_info(&format!(
+                    "first build failed; retrying ({}/{})",
+                    n, eff.initial_build_retries
+                ));
+                std::thread::sleep(INITIAL_RETRY_DELAY);
+            },
+        )
     };
 
+    if eff.once {
+        let outcome = first_build(&mut stats)?;
+        if let Some(hooks) = post_ready_hooks.take() {
+            let _ = hooks.join();
+        }
+        std::process::exit(once_exit_code(&eff, &child, outcome)?);
+    }
+    if eff.warms_before_watching() {
+        warm_build(&eff)?;
+    }
+
+    // watcher channel
+    let (tx, mut rx) = mpsc::channel();
+    let mut watcher = new_watcher(tx.clone())?;
+
+    if eff.interactive {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                match line.trim() {
+                    "p" => {
+                        let _ = tx.send(LoopEvent::TogglePause);
+                    }
+                    "" => {}
+                    other => log_info(&format!("unknown key {:?} (p: pause/resume)", other)),
+                }
+            }
+        });
+        log_info("interactive: press p and Enter to pause/resume watching");
+    }
+
+    if let Some(path) = &eff.control_socket {
+        #[cfg(unix)]
+        {
+            let tx = Mutex::new(tx.clone());
+            rair::listen_control_socket(path, move |cmd| {
+                let _ = tx
+                    .lock()
+                    .unwrap()
+                    .send(LoopEvent::Control(cmd, "control socket"));
+            })?;
+            log_verbose(&eff, &format!("listening for commands on {:?}", path));
+        }
+        #[cfg(not(unix))]
+        log_info(&format!(
+            "control_socket is only supported on unix; ignoring {:?}",
+            path
+        ));
+    }
+    if let Some(port) = eff.http_trigger_port {
+        #[cfg(feature = "http-trigger")]
+        {
+            let tx = Mutex::new(tx.clone());
+            let addr = format!("{}:{}", eff.http_trigger_host, port);
+            let bound =
+                rair::listen_http_trigger(&addr, eff.http_trigger_token.clone(), move |cmd| {
+                    let _ = tx.lock().unwrap().send(LoopEvent::Control(cmd, "http"));
+                })?;
+            log_verbose(
+                &eff,
+                &format!("listening for POST /trigger on http://{}", bound),
+            );
+        }
+        #[cfg(not(feature = "http-trigger"))]
+        log_info(&format!(
+            "http_trigger_port needs rair built with the `http-trigger` feature; ignoring {}",
+            port
+        ));
+    }
+    // the watcher and the input threads hold the only senders: the channel closes if the
+    // watcher stops
+    drop(tx);
+
+    let mut watched = 0;
+    let mut failures = Vec::new();
+    let mut missing = MissingWatches::default();
+    for p in &eff.watch {
+        if !p.exists() {
+            log_info(&format!("watch path missing (will retry): {:?}", p));
+            missing.push(p.clone());
+            continue;
+        }
+        // Ok(false): a file inside a watched directory
+        match eff.register_watch(&mut watcher, p) {
+            Ok(_) => watched += 1,
+            Err(e) => failures.push((p.clone(), rair::describe_watch_error(&e))),
+        }
+    }
+    let externally_driven = eff.control_socket.is_some()
+        || eff.trigger_file.is_some()
+        || eff.http_trigger_port.is_some();
+    rair::check_watch_setup(watched, &failures, eff.keep_going, externally_driven)?;
+    // with keep_going: report the skipped paths and keep retrying them
+    for (p, e) in failures {
+        log_info(&format!("watch {:?} failed (will retry): {}", p, e));
+        missing.push(p);
+    }
+    if watched == 0 {
+        log_info("nothing watched yet; waiting for triggers");
+    }
+    if let Some(dir) = eff.trigger_watch_dir() {
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("watch trigger_file directory {:?}", dir))?;
+    }
+    // the config file's directory, for reload_config_on_change
+    let config_watch = config_source
+        .path()
+        .filter(|_| eff.reload_config_on_change)
+        .and_then(|p| rair::watch_registration(p, &eff.watch));
+    if let Some((dir, mode)) = &config_watch {
+        watcher
+            .watch(dir, *mode)
+            .with_context(|| format!("watch config file directory {:?}", dir))?;
+    }
+    let mut extra_watches = eff.trigger_watch_dir().is_some() || config_watch.is_some();
+    let mut watch_set = WatchSet::new(&eff.watch).with_extra_watches(extra_watches);
+    let mut symlinks = SymlinkMap::scan(&eff.watch, eff.follow_symlinks);
+    watch_followed(&mut watcher, &[], &symlinks);
+
+    let services: Vec<_> = eff
+        .services
+        .iter()
+        .map(|svc| {
+            let (svc, eff) = (svc.clone(), eff.clone());
+            std::thread::spawn(move || {
+                let name = svc.name.clone();
+                if let Err(e) = run_service(svc, eff) {
+                    log_info(&format!("[{}] service stopped: {:#}", name, e));
+                }
+            })
+        })
+        .collect();
+
     // initial start
-    start_app(&eff, &child)?;
+    first_build(&mut stats)?;
 
     // debounce loop
-    let mut last = Instant::now() - eff.debounce;
-    loop {
-        let evt = rx.recv().context("watch recv")?;
+    let mut debouncer = DebounceState::new(eff.debounce_mode, eff.debounce);
+    let new_burst = |eff: &EffectiveConfig| {
+        eff.burst_threshold
+            .map(|n| ChangeBurst::new(n, eff.debounce))
+    };
+    let mut burst = new_burst(&eff);
+    // events taken off the channel early to size up a burst, handled before newer ones
+    let mut backlog: VecDeque<LoopEvent> = VecDeque::new();
+    let mut watcher_restarts = WatcherRestarts::new(eff.watcher_restarts);
+    let mut pause = PauseState::default();
+    let mut cooldown = eff.per_path_cooldown.map(PathCooldown::new);
+    let mut rate_limit = eff.min_build_interval.map(BuildRateLimit::new);
+    let mut content = eff.skip_unchanged.then(ContentHashes::default);
+    let mut git_tracked = if eff.git_tracked_only {
+        let cwd = std::env::current_dir().context("cwd")?;
+        Some(GitTracked::load(&cwd, eff.git_include_untracked)?)
+    } else {
+        None
+    };
+    let mut trigger_done: Option<Instant> = None;
+    #[cfg(feature = "monitor")]
+    let mut monitor = eff
+        .monitor
+        .then(|| (rair::ProcessMonitor::new(), Instant::now()));
+    #[cfg(not(feature = "monitor"))]
+    if eff.monitor {
+        log_info("monitor needs rair built with the `monitor` feature; ignoring");
+    }
+    #[cfg(not(all(feature = "pty", unix)))]
+    if eff.pty {
+        log_info("pty needs rair built with the `pty` feature, on unix; ignoring");
+    }
+    let mut watch_stats = WatchStats::default();
+    let mut watch_stats_since = Instant::now();
+    let mut exited_at: Option<Instant> = None;
+    while !shutting_down() {
+        if eff.watch_stats && watch_stats_since.elapsed() >= eff.watch_stats_interval {
+            let counted = std::mem::take(&mut watch_stats);
+            log_info(&format!(
+                "stats: {}",
+                counted.report(watch_stats_since.elapsed())
+            ));
+            watch_stats_since = Instant::now();
+        }
+
+        #[cfg(feature = "monitor")]
+        if let Some((m, last_sample)) = monitor.as_mut() {
+            if last_sample.elapsed() >= eff.monitor_interval {
+                *last_sample = Instant::now();
+                let pid = child.lock().unwrap().as_ref().map(|c| c.id());
+                if let Some(sample) = pid.and_then(|pid| m.sample(pid)) {
+                    log_info(&format!("monitor: {}", sample.describe()));
+                }
+            }
+        }
+
+        // restart_on_exit: respawn a process that exited on its own once the cooldown is over
+        if eff.restart_on_exit {
+            let exited = {
+                let mut guard = child.lock().unwrap();
+                match guard.as_mut().map(|c| c.try_wait()) {
+                    Some(Ok(Some(status))) => {
+                        guard.take();
+                        Some(status)
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(status) = exited {
+                log_info(&format!(
+                    "run exited: {}; restarting in {} unless a file changes",
+                    status,
+                    rair::format_duration(eff.crash_cooldown)
+                ));
+                exited_at = Some(Instant::now());
+            }
+            if let Some(at) = exited_at {
+                let decision = rair::exit_restart(at, Instant::now(), eff.crash_cooldown, false);
+                if decision == ExitRestart::Now {
+                    exited_at = None;
+                    log_info("restarting after exit");
+                    stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+                }
+            }
+        }
+
+        // burst_threshold: one build for a checkout's worth of changes, once they settled
+        if burst
+            .as_mut()
+            .is_some_and(|b| b.should_fire(Instant::now()))
+        {
+            let now = Instant::now();
+            log_step(&rair::format_burst_digest(
+                &pending_changes.borrow().paths(),
+            ));
+            exited_at = None;
+            watch_stats.rebuilds += 1;
+            if let Some(limit) = rate_limit.as_mut() {
+                limit.started(now);
+            }
+            stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+            debouncer.cycle(now, Instant::now());
+        }
+
+        // debounce_mode trailing/both: the build for a burst of changes that has settled
+        if debouncer.should_fire(Instant::now()) {
+            let now = Instant::now();
+            match rate_limit.as_mut() {
+                Some(limit) if limit.wait(now).is_some() => {
+                    limit.defer();
+                }
+                limit => {
+                    if let Some(limit) = limit {
+                        limit.started(now);
+                    }
+                    log_verbose(&eff, "changes settled; building");
+                    exited_at = None;
+                    watch_stats.rebuilds += 1;
+                    stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+                    debouncer.cycle(now, Instant::now());
+                }
+            }
+        }
+
+        // min_build_interval_ms: the build deferred by changes that came too soon
+        if let Some(limit) = rate_limit.as_mut().filter(|l| l.due(Instant::now())) {
+            let now = Instant::now();
+            limit.started(now);
+            log_step("building deferred changes");
+            watch_stats.rebuilds += 1;
+            stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+            debouncer.cycle(now, Instant::now());
+        }
+
+        // pick up watch paths that appeared since startup (e.g. Cargo.lock after the first build)
+        if !missing.is_empty() {
+            for p in missing.take_present() {
+                match eff.register_watch(&mut watcher, &p) {
+                    Ok(false) => {}
+                    Ok(true) => log_info(&format!("watch path appeared, now watching: {:?}", p)),
+                    Err(e) => {
+                        log_info(&format!(
+                            "watch {:?} failed (will retry): {}",
+                            p,
+                            rair::describe_watch_error(&e)
+                        ));
+                        missing.push(p);
+                    }
+                }
+            }
+            watch_set = WatchSet::new(&eff.watch).with_extra_watches(extra_watches);
+        }
+
+        // files read by build scripts, as reported by the last build
+        for p in build_inputs.borrow_mut().take_new() {
+            if eff.is_watched(&p) {
+                continue;
+            }
+            let (dir, mode) = if p.is_dir() {
+                (p.as_path(), RecursiveMode::Recursive)
+            } else {
+                // the directory, so editors replacing the file don't drop the watch
+                (p.parent().unwrap_or(&p), RecursiveMode::NonRecursive)
+            };
+            match watcher.watch(dir, mode) {
+                Ok(()) => log_info(&format!("watching build script input: {:?}", p)),
+                Err(e) => {
+                    log_info(&format!(
+                        "watch {:?} failed (will retry): {}",
+                        p,
+                        rair::describe_watch_error(&e)
+                    ));
+                    missing.push(p);
+                }
+            }
+        }
+
+        let next = match backlog.pop_front() {
+            Some(ev) => Ok(ev),
+            None => rx.recv_timeout(LOOP_TICK),
+        };
+        let (arrived, evt) = match next {
+            Ok(LoopEvent::Fs(at, evt)) => {
+                watcher_restarts.recovered();
+                (at, evt)
+            }
+            Ok(LoopEvent::Control(cmd, source)) => {
+                // Manual triggers skip debounce and the path filters
+                log_info(&format!("{} requested via {}", cmd.as_str(), source));
+                exited_at = None;
+                stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+                continue;
+            }
+            Ok(LoopEvent::TogglePause) => {
+                let Some(missed) = pause.toggle() else {
+                    set_status(Status::Paused);
+                    log_info("paused; file changes are ignored until you press p again");
+                    continue;
+                };
+                let catch_up = missed
+                    .iter()
+                    .filter(|p| !eff.is_ignored(p) && eff.is_relevant(p))
+                    .count();
+                if catch_up == 0 {
+                    let pid = child.lock().unwrap().as_ref().map(|c| c.id());
+                    set_status(pid.map_or(Status::Built, |pid| Status::Running { pid }));
+                    log_info("resumed");
+                    continue;
+                }
+                log_info(&format!(
+                    "resumed; rebuilding for {} file(s) changed while paused",
+                    catch_up
+                ));
+                exited_at = None;
+                stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+                debouncer.cycle(Instant::now(), Instant::now());
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                log_info("watcher stopped: its event channel closed");
+                let Some(attempt) = watcher_restarts.attempt() else {
+                    if let Some(mut ch) = child.lock().unwrap().take() {
+                        kill_group(&mut ch);
+                    }
+                    anyhow::bail!(
+                        "watcher stopped after {} restart(s); giving up",
+                        watcher_restarts.used()
+                    );
+                };
+                log_info(&format!(
+                    "recreating the watcher ({}/{})",
+                    attempt, eff.watcher_restarts
+                ));
+                std::thread::sleep(WATCHER_RESTART_DELAY);
+                // on failure the old, closed channel stays and the next pass tries again
+                let (tx, new_rx) = mpsc::channel();
+                match new_watcher(tx) {
+                    Ok(w) => {
+                        watcher = w;
+                        rx = new_rx;
+                    }
+                    Err(e) => {
+                        log_info(&format!("{:#}", e));
+                        continue;
+                    }
+                }
+                rewatch(&mut watcher, &eff, &eff, &mut missing);
+                if let Some(dir) = eff.trigger_watch_dir() {
+                    let _ = watcher.watch(&dir, RecursiveMode::NonRecursive);
+                }
+                if let Some((dir, mode)) = &config_watch {
+                    let _ = watcher.watch(dir, *mode);
+                }
+                watch_followed(&mut watcher, &[], &symlinks);
+                build_inputs.borrow_mut().rewatch_all();
+                continue;
+            }
+        };
         let now = Instant::now();
-        if now.duration_since(last) < eff.debounce {
+        watch_stats.events += 1;
+        if let Ok(e) = &evt {
+            if !e.kind.is_access() && e.paths.iter().any(|p| eff.is_trigger_file(p)) {
+                // One touch can emit several events (create + attrib); they queue up during the build
+                if trigger_done.is_some_and(|t| now.duration_since(t) < TRIGGER_SETTLE) {
+                    continue;
+                }
+                log_info("trigger_file changed; rebuilding now");
+                exited_at = None;
+                watch_stats.rebuilds += 1;
+                stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+                trigger_done = Some(Instant::now());
+                debouncer.cycle(now, Instant::now());
+                continue;
+            }
+        }
+        if let Ok(e) = &evt {
+            let is_config = |p: &PathBuf| config_source.is_config_file(p);
+            if eff.reload_config_on_change && !e.kind.is_access() && e.paths.iter().any(is_config) {
+                // A config change applies from the next rebuild instead of causing one
+                match config_source.reload() {
+                    Ok(Some(new)) => {
+                        let new_symlinks = SymlinkMap::scan(&new.watch, new.follow_symlinks);
+                        watch_followed(&mut watcher, symlinks.followed(), &new_symlinks);
+                        symlinks = new_symlinks;
+                        if new.watch != eff.watch || new.non_recursive != eff.non_recursive {
+                            rewatch(&mut watcher, &eff, &new, &mut missing);
+                            // the old watches may have shared a directory with these
+                            if let Some(dir) = new.trigger_watch_dir() {
+                                let _ = watcher.watch(&dir, RecursiveMode::NonRecursive);
+                            }
+                            if let Some((dir, mode)) = &config_watch {
+                                let _ = watcher.watch(dir, *mode);
+                            }
+                            extra_watches = true;
+                            watch_set = WatchSet::new(&new.watch).with_extra_watches(extra_watches);
+                        }
+                        eff = new;
+                        debouncer = DebounceState::new(eff.debounce_mode, eff.debounce);
+                        burst = new_burst(&eff);
+                        cooldown = eff.per_path_cooldown.map(PathCooldown::new);
+                        rate_limit = eff.min_build_interval.map(BuildRateLimit::new);
+                        content = eff.skip_unchanged.then(ContentHashes::default);
+                        cargo_target = None;
+                        log_info("config reloaded; changes apply from the next rebuild");
+                    }
+                    Ok(None) => {}
+                    Err(e) => log_info(&format!(
+                        "config reload failed; keeping the current config: {:#}",
+                        e
+                    )),
+                }
+                continue;
+            }
+        }
+        // while paused, changes are only noted, for a catch-up rebuild on resume
+        let noted = match &evt {
+            Ok(e) if !e.kind.is_access() => rair::event_paths(e)
+                .iter()
+                .map(|p| symlinks.link_path(p))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if !pause.admit(noted) {
             continue;
         }
-        last = now;
 
-        let event = match evt {
+        let mut event = match evt {
             Ok(e) => e,
             Err(e) => {
-                eprintln!("[{}] watch error: {:#}", ts(), e);
+                eprintln!("[{}] watch error: {}", ts(), rair::describe_watch_error(&e));
                 continue;
             }
         };
 
-        // ignore + relevance filter
-        let mut relevant = false;
-        for p in &event.paths {
-            if eff.ignore_set.is_match(p) {
-                continue;
+        // paths under a symlinked directory's target, as seen through the link
+        for p in &mut event.paths {
+            *p = symlinks.link_path(p);
+        }
+
+        if let Some(g) = git_tracked.as_mut() {
+            if event.paths.iter().any(|p| g.needs_refresh(p)) {
+                if let Err(e) = g.refresh() {
+                    log_info(&format!("git ls-files refresh failed: {:#}", e));
+                }
             }
-            if rair::is_relevant_path(p, &eff.include_ext, &eff.exclude_ext) {
-                relevant = true;
-                break;
+        }
+
+        if eff.ignore_files.is_some() && event.paths.iter().any(|p| IgnoreFiles::needs_rescan(p)) {
+            eff.ignore_files = Some(IgnoreFiles::scan(&eff.root));
+        }
+
+        if !eff.allows_event(&event.kind) {
+            watch_stats.record_filtered(FilterReason::EventKind);
+            continue;
+        }
+
+        // the filters that don't depend on when or how often a path changed
+        let filtered = |p: &Path| {
+            if eff.is_ignored(p) {
+                return Some(FilterReason::Ignored);
+            }
+            if git_tracked.as_ref().is_some_and(|g| !g.contains(p)) {
+                return Some(FilterReason::Untracked);
+            }
+            if !watch_set.contains(p) && !build_inputs.borrow().contains(p) {
+                return Some(FilterReason::Unwatched);
             }
+            eff.relevance_filter(p)
+                .filter(|_| !build_inputs.borrow().contains(p))
+        };
+        if !debouncer.accept(arrived) {
+            // not built now, but still a change: it counts toward the burst and `{changed}`
+            pending_changes
+                .borrow_mut()
+                .record_event(&event, |p| !eff.is_env_file(p) && filtered(p).is_none());
+            watch_stats.debounced += 1;
+            continue;
         }
-        if !relevant {
+
+        // transform: regenerate sources from a changed input; the files it writes come back
+        // as events of their own and go through the filters like any other change
+        if !eff.transform.is_empty() && !event.kind.is_access() {
+            let inputs = rair::event_paths(&event).iter().map(PathBuf::as_path);
+            match eff.run_transforms(inputs.filter(|p| !eff.is_ignored(p))) {
+                Ok(ran) => {
+                    for (glob, ok) in ran {
+                        let result = if ok { "done" } else { "failed" };
+                        log_step(&format!("transform {}: {}", glob, result));
+                    }
+                }
+                Err(e) => log_info(&format!("transform failed: {:#}", e)),
+            }
+        }
+
+        // asset_sync: copy changed assets into place; on their own they only restart the run
+        let mut synced = false;
+        if !eff.asset_sync.is_empty() {
+            for p in rair::event_paths(&event) {
+                if eff.is_ignored(p) {
+                    continue;
+                }
+                match eff.sync_asset(p) {
+                    Ok(Some(dest)) => {
+                        log_verbose(
+                            &eff,
+                            &format!("asset synced: {} -> {}", p.display(), dest.display()),
+                        );
+                        synced = true;
+                    }
+                    Ok(None) => {}
+                    Err(e) => log_info(&format!("asset_sync failed: {:#}", e)),
+                }
+            }
+        }
+
+        // ignore + relevance filter
+        let mut env_changed = false;
+        let relevant = pending_changes.borrow_mut().record_event(&event, |p| {
+            if eff.is_env_file(p) {
+                env_changed = true;
+                return false;
+            }
+            if let Some(reason) = filtered(p) {
+                if reason == FilterReason::Ignored && eff.verbose {
+                    let why = eff.explain_ignore(p).unwrap_or_default();
+                    log_verbose(&eff, &format!("ignored {} (matched {})", p.display(), why));
+                }
+                watch_stats.record_filtered(reason);
+                return false;
+            }
+            if let Some(h) = content.as_mut() {
+                if !h.changed(p) {
+                    log_verbose(&eff, &format!("unchanged contents: {}", p.display()));
+                    watch_stats.record_filtered(FilterReason::Unchanged);
+                    return false;
+                }
+            }
+            if let Some(c) = cooldown.as_mut() {
+                if !c.allow(p, now) {
+                    watch_stats.record_filtered(FilterReason::Cooldown);
+                    return false;
+                }
+            }
+            true
+        });
+        if !relevant && !synced && !env_changed {
             continue;
         }
 
+        if cargo_target.is_some() && event.paths.iter().any(|p| rair::is_manifest_path(p)) {
+            log_verbose(&eff, "manifest changed; refreshing cargo metadata");
+            cargo_target = None;
+        }
+
         // rebuild + restart policy
-        start_app(&eff, &child)?;
+        if let Some(at) = exited_at.take() {
+            if let ExitRestart::Wait(left) =
+                rair::exit_restart(at, Instant::now(), eff.crash_cooldown, false)
+            {
+                log_verbose(
+                    &eff,
+                    &format!(
+                        "file changed with {} of crash cooldown left; restarting now",
+                        rair::format_duration(left)
+                    ),
+                );
+            }
+        }
+        if !relevant {
+            log_info(if synced {
+                "assets changed; restarting without a build"
+            } else {
+                "env_file changed; restarting without a build"
+            });
+            start_app(&eff, &child, &mut cargo_target, false, true)?;
+            debouncer.cycle(now, Instant::now());
+            continue;
+        }
+        // burst_threshold: look at what's queued behind this change; a checkout or pull
+        // delivers most of it at once
+        if let Some(b) = burst.as_mut() {
+            while let Ok(ev) = rx.try_recv() {
+                backlog.push_back(ev);
+            }
+            let queued: usize = backlog
+                .iter()
+                .map(|ev| match ev {
+                    LoopEvent::Fs(_, Ok(e)) => e.paths.len(),
+                    _ => 0,
+                })
+                .sum();
+            if b.on_change(arrived, pending_changes.borrow().len() + queued) {
+                continue;
+            }
+        }
+        // debounce_mode trailing/both: the rest of a burst waits for it to settle
+        if !debouncer.on_event(arrived) {
+            continue;
+        }
+        if let Some(limit) = rate_limit.as_mut() {
+            if let Some(wait) = limit.wait(now) {
+                if limit.defer() {
+                    log_verbose(
+                        &eff,
+                        &format!(
+                            "min_build_interval_ms: deferring the build by {}",
+                            rair::format_duration(wait)
+                        ),
+                    );
+                }
+                continue;
+            }
+            limit.started(now);
+        }
+        watch_stats.rebuilds += 1;
+        stats.record(start_app(&eff, &child, &mut cargo_target, true, true)?);
+        debouncer.cycle(now, Instant::now());
 
         io::stdout().flush().ok();
     }
+
+    if let Some(mut ch) = child.lock().unwrap().take() {
+        kill_group(&mut ch);
+    }
+    for svc in services {
+        let _ = svc.join();
+    }
+    if let Some(path) = &eff.control_socket {
+        let _ = std::fs::remove_file(path);
+    }
+    set_status_off();
+    log_info(&format!(
+        "shutting down: {}",
+        rair::format_summary(&stats, started.elapsed())
+    ));
+    std::process::exit(stats.exit_code(eff.strict_exit));
 }
@@ -1,17 +1,57 @@
+use notify::RecursiveMode;
 use rair::{
-    build_globset, effective_config, exe_name, exe_path, is_relevant_path, load_config,
-    run_hook_list, Config,
+    add_watch_paths, anchor_globs, build_command, build_fail_env, build_globset,
+    cached_latest_version, cargo_target, check_watch_setup, clippy_warning_count, default_run_argv,
+    dep_info_inputs, describe_watch_error, effective_config, env_config, event_paths, exe_name,
+    exe_path, exit_restart, expand_bin_glob, expand_build_argv, explain_match, files_to_recompile,
+    format_build_ok, format_burst_digest, format_cycle_summary, format_duration, format_status,
+    format_summary, is_cargo_lock_wait, is_editor_temp, is_hidden, is_manifest_path,
+    is_newer_version, is_relevant_path, is_watch_limit_error, load_config, load_rairignore,
+    merge_config, on_fail_decision, path_key, probe_tcp_port, resolve_bin_name, resolve_exe,
+    resolve_program, resolve_run_argv, restart_decision, restart_steps, retry_initial_build,
+    run_captured, run_command, run_hook_list, run_hook_list_timed, run_hook_list_with_env,
+    run_post_ready, runs_post_hooks, take_cargo_messages, target_names, validate, wait_timeout,
+    wait_until_ready, watch_registration, workspace_watch_paths, write_cached_latest_version,
+    ArtifactStamp, AssetSync, Bell, BuildOutcome, BuildRateLimit, BuildScriptInputs, CargoTarget,
+    ChangeBatch, ChangeBurst, Check, ClearBefore, Config, ConfigSource, ContentHashes,
+    ControlCommand, DebounceMode, DebounceState, Debouncer, EventOp, ExitRestart, FailureCache,
+    FilterReason, GitTracked, Hook, IgnoreEngine, IgnoreFiles, IncrementalFiles, MissingWatches,
+    Mode, OnFailAction, OutputBuffer, OutputLine, PathCooldown, PauseState, PostReadyGeneration,
+    ProcessSample, RairError, RestartStep, RunDecision, RunStdin, ServiceConfig, Stats, Status,
+    SymlinkMap, TestRunner, Timeline, TimestampFormat, TransformConfig, WaitOutcome, WatchSet,
+    WatchStats, WatcherRestarts, EXE_PLACEHOLDER, REPEATED_FAILURE_MSG, WATCH_LIMIT_HINT,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
-use std::{collections::HashSet, fs, path::PathBuf};
 use tempfile::TempDir;
 
 // ============================================================================
 // Glob Pattern Tests
 // ============================================================================
 
+#[test]
+fn test_bad_glob_error_kind() {
+    match build_globset(&["src/**".into(), "src/[".into()]).unwrap_err() {
+        RairError::BadGlob { glob, .. } => assert_eq!(glob, "src/["),
+        other => panic!("expected BadGlob, got {:?}", other),
+    }
+
+    // Surfaces unchanged through effective_config
+    let cli = Config {
+        trigger_globs: Some(vec!["{unclosed".into()]),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(matches!(err, RairError::BadGlob { .. }), "{:?}", err);
+}
+
 #[test]
 fn test_ignore_globs() {
-    let set = build_globset(&vec!["**/target/**".into(), "**/.git/**".into()]).unwrap();
+    let set = build_globset(&["**/target/**".into(), "**/.git/**".into()]).unwrap();
     assert!(set.is_match("foo/target/debug/app"));
     assert!(set.is_match(".git/index"));
     assert!(!set.is_match("src/main.rs"));
@@ -19,7 +59,7 @@ fn test_ignore_globs() {
 
 #[test]
 fn test_globset_multiple_patterns() {
-    let set = build_globset(&vec![
+    let set = build_globset(&[
         "*.tmp".into(),
         "**/node_modules/**".into(),
         "**/.DS_Store".into(),
@@ -31,9 +71,129 @@ fn test_globset_multiple_patterns() {
     assert!(!set.is_match("src/main.rs"));
 }
 
+#[test]
+fn test_root_anchored_ignore_glob() {
+    let root = PathBuf::from("/work/proj");
+    let globs = anchor_globs(&["/logs/**".into(), "**/target/**".into()], &root);
+    assert_eq!(globs, vec!["/work/proj/logs/**", "**/target/**"]);
+
+    let set = build_globset(&globs).unwrap();
+    assert!(set.is_match(root.join("logs/x")));
+    assert!(!set.is_match(root.join("src/logs/x")));
+    assert!(set.is_match(root.join("src/target/debug/app")));
+}
+
+#[test]
+fn test_root_anchored_ignore_in_effective_config() {
+    let cli = Config {
+        ignore: Some(vec!["/logs/**".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    let cwd = std::env::current_dir().unwrap();
+
+    assert!(eff.ignore_set.is_match(cwd.join("logs/x")));
+    assert!(!eff.ignore_set.is_match(cwd.join("src/logs/x")));
+    // Verbose output names the pattern as the user wrote it
+    assert_eq!(
+        explain_match(&cwd.join("logs/x"), &eff.ignore_globs, &eff.ignore_set).as_deref(),
+        Some("/logs/**")
+    );
+}
+
+#[test]
+fn test_rairignore_suppresses_matching_changes() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join(".rairignore"), "# scratch output\n*.log\n/tmp/\n").unwrap();
+    fs::create_dir_all(root.join("tmp")).unwrap();
+
+    let mut eff = effective_config(Config::default(), None).unwrap();
+    eff.rairignore = load_rairignore(&root).unwrap();
+
+    assert!(eff.is_ignored(&root.join("server.log")));
+    assert!(eff.is_ignored(&root.join("src/nested/debug.log")));
+    assert!(eff.is_ignored(&root.join("tmp/gen.rs")));
+    assert!(!eff.is_ignored(&root.join("src/tmp/gen.rs")));
+    assert!(!eff.is_ignored(&root.join("src/main.rs")));
+    // Paths outside the project root are left to the `ignore` globs
+    assert!(!eff.is_ignored(Path::new("/elsewhere/x.log")));
+
+    assert_eq!(
+        eff.explain_ignore(&root.join("server.log")).as_deref(),
+        Some("*.log in .rairignore")
+    );
+    // `ignore` globs still apply alongside it
+    assert!(eff.is_ignored(&root.join("target/debug/app")));
+}
+
+#[test]
+fn test_ignore_engine_applies_gitignore_hierarchy() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join(".gitignore"), "*.log\n/out/\n").unwrap();
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(root.join("sub/.ignore"), "!keep.log\n").unwrap();
+
+    assert_eq!(IgnoreEngine::parse("ignore").unwrap(), IgnoreEngine::Ignore);
+    assert!(matches!(
+        IgnoreEngine::parse("ripgrep"),
+        Err(RairError::InvalidConfig(_))
+    ));
+
+    // The default glob engine doesn't read .gitignore
+    let mut eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(eff.ignore_engine, IgnoreEngine::Glob);
+    assert!(eff.ignore_files.is_none());
+    assert!(!eff.is_ignored(&root.join("server.log")));
+
+    eff.ignore_files = Some(IgnoreFiles::scan(&root));
+    assert!(eff.is_ignored(&root.join("server.log")));
+    assert!(eff.is_ignored(&root.join("out/gen.rs")));
+    // A deeper ignore file overrides the root .gitignore
+    assert!(!eff.is_ignored(&root.join("sub/keep.log")));
+    assert!(eff.is_ignored(&root.join("sub/other.log")));
+    assert!(!eff.is_ignored(&root.join("src/main.rs")));
+    let why = eff.explain_ignore(&root.join("server.log")).unwrap();
+    assert!(
+        why.starts_with("*.log in ") && why.ends_with(".gitignore"),
+        "{}",
+        why
+    );
+
+    assert!(IgnoreFiles::needs_rescan(&root.join("sub/.ignore")));
+    assert!(!IgnoreFiles::needs_rescan(&root.join("src/main.rs")));
+}
+
+#[test]
+fn test_rairignore_missing_is_none() {
+    let dir = TempDir::new().unwrap();
+    assert!(load_rairignore(dir.path()).unwrap().is_none());
+}
+
+#[test]
+fn test_explain_match_returns_pattern() {
+    let globs: Vec<String> = vec!["**/target/**".into(), "*.tmp".into(), "**/*.tmp".into()];
+    let set = build_globset(&globs).unwrap();
+
+    assert_eq!(
+        explain_match(&PathBuf::from("foo/target/x"), &globs, &set).as_deref(),
+        Some("**/target/**")
+    );
+    // First listed pattern wins when several match
+    assert_eq!(
+        explain_match(&PathBuf::from("a.tmp"), &globs, &set).as_deref(),
+        Some("*.tmp")
+    );
+    assert_eq!(
+        explain_match(&PathBuf::from("src/main.rs"), &globs, &set),
+        None
+    );
+}
+
 #[test]
 fn test_globset_empty() {
-    let set = build_globset(&vec![]).unwrap();
+    let set = build_globset(&[]).unwrap();
     assert!(!set.is_match("anything"));
 }
 
@@ -55,7 +215,7 @@ fn test_config_merge_cli_wins() {
     };
     let eff = effective_config(cli, Some(file)).unwrap();
     assert_eq!(eff.debounce.as_millis(), 123);
-    assert_eq!(eff.clear, true);
+    assert!(eff.clear);
 }
 
 #[test]
@@ -72,7 +232,7 @@ fn test_config_merge_file_fallback() {
     };
     let eff = effective_config(cli, Some(file)).unwrap();
     assert_eq!(eff.debounce.as_millis(), 500); // From file
-    assert_eq!(eff.clear, true); // From CLI
+    assert!(eff.clear); // From CLI
     assert_eq!(eff.bin.as_deref(), Some("from_file")); // From file
 }
 
@@ -81,11 +241,480 @@ fn test_config_all_defaults() {
     let cli = Config::default();
     let eff = effective_config(cli, None).unwrap();
     assert_eq!(eff.debounce.as_millis(), 250);
-    assert_eq!(eff.clear, true);
+    assert!(eff.clear);
     assert!(eff.include_ext.contains("rs"));
     assert!(eff.include_ext.contains("toml"));
 }
 
+#[test]
+fn test_should_clear_decision() {
+    use BuildOutcome::*;
+
+    let clearing = |clear, clear_on_fail| {
+        let cfg = Config {
+            clear: Some(clear),
+            clear_on_fail: Some(clear_on_fail),
+            ..Default::default()
+        };
+        effective_config(cfg, None).unwrap()
+    };
+
+    // Default: clear only right before a new run
+    let eff = clearing(true, false);
+    assert!(!eff.clears_at(Pending));
+    assert!(eff.clears_at(Succeeded));
+    assert!(!eff.clears_at(Failed));
+
+    // clear_on_fail: failing builds also start on a clean screen
+    let eff = clearing(true, true);
+    assert!(eff.clears_at(Pending));
+    assert!(eff.clears_at(Succeeded));
+    assert!(!eff.clears_at(Failed));
+
+    // clear = false never clears before a run
+    let eff = clearing(false, false);
+    assert!(!eff.clears_at(Succeeded));
+    assert!(!eff.clears_at(Pending));
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert!(!eff.clear_on_fail);
+}
+
+#[test]
+fn test_clear_before_timing() {
+    use BuildOutcome::*;
+
+    assert_eq!(ClearBefore::parse("build").unwrap(), ClearBefore::Build);
+    assert_eq!(ClearBefore::parse(" Run ").unwrap(), ClearBefore::Run);
+    assert_eq!(ClearBefore::parse("never").unwrap(), ClearBefore::Never);
+    assert!(matches!(
+        ClearBefore::parse("sometimes"),
+        Err(RairError::InvalidConfig(_))
+    ));
+
+    // Default keeps clearing right before the run
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(eff.clear_before, ClearBefore::Run);
+    assert!(!eff.clears_at(Pending));
+    assert!(eff.clears_at(Succeeded));
+
+    let build = Config {
+        clear_before: Some("build".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(build, None).unwrap();
+    assert_eq!(eff.clear_before, ClearBefore::Build);
+    assert!(eff.clears_at(Pending));
+    assert!(!eff.clears_at(Succeeded));
+    assert!(!eff.clears_at(Failed));
+
+    let never = Config {
+        clear_before: Some("never".into()),
+        clear_on_fail: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(never, None).unwrap();
+    assert!(!eff.clears_at(Pending));
+    assert!(!eff.clears_at(Succeeded));
+
+    let bad = Config {
+        clear_before: Some("later".into()),
+        ..Default::default()
+    };
+    assert!(effective_config(bad, None).is_err());
+}
+
+#[test]
+fn test_bell_cue_decision() {
+    const BEL: &str = "\x07";
+    const RECOVERED: &str = "\x07\x07";
+
+    for bell in [Bell::OnFail, Bell::OnSuccess, Bell::Always] {
+        // A fixed build gets the recovery cue whatever the setting
+        assert_eq!(bell.cue(true, true), RECOVERED, "{:?}", bell);
+    }
+    assert_eq!(Bell::OnFail.cue(false, false), BEL);
+    assert_eq!(Bell::OnFail.cue(false, true), BEL);
+    assert_eq!(Bell::OnFail.cue(true, false), "");
+    assert_eq!(Bell::OnSuccess.cue(true, false), BEL);
+    assert_eq!(Bell::OnSuccess.cue(false, false), "");
+    assert_eq!(Bell::Always.cue(true, false), BEL);
+    assert_eq!(Bell::Always.cue(false, true), BEL);
+    for (succeeded, after_failure) in [(true, true), (true, false), (false, false)] {
+        assert_eq!(Bell::Off.cue(succeeded, after_failure), "");
+    }
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(eff.bell, Bell::Off);
+    let cli = Config {
+        bell: Some("On_Fail".into()),
+        ..Default::default()
+    };
+    assert_eq!(effective_config(cli, None).unwrap().bell, Bell::OnFail);
+    let cli = Config {
+        bell: Some("loud".into()),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(err.to_string().contains("unknown bell"), "{}", err);
+}
+
+#[test]
+fn test_is_newer_version() {
+    assert!(is_newer_version("0.3.0", "0.2.0"));
+    assert!(is_newer_version("0.2.1", "0.2.0"));
+    assert!(is_newer_version("1.0.0", "0.9.12"));
+    assert!(is_newer_version("0.10.0", "0.9.0"));
+    // Older or equal
+    assert!(!is_newer_version("0.2.0", "0.2.0"));
+    assert!(!is_newer_version("0.1.9", "0.2.0"));
+    assert!(!is_newer_version("0.2.0+build.5", "0.2.0"));
+    // Pre-releases come before their release
+    assert!(is_newer_version("1.0.0", "1.0.0-rc.1"));
+    assert!(!is_newer_version("1.0.0-rc.1", "1.0.0"));
+    assert!(is_newer_version("1.0.0-rc.2", "1.0.0-rc.1"));
+    // ...and compare identifier by identifier, numbers by value
+    assert!(is_newer_version("1.0.0-rc.10", "1.0.0-rc.9"));
+    assert!(!is_newer_version("1.0.0-rc.9", "1.0.0-rc.10"));
+    assert!(is_newer_version("1.0.0-beta.11", "1.0.0-beta.2"));
+    assert!(is_newer_version("1.0.0-rc.1", "1.0.0-beta.11"));
+    // numeric identifiers sort before alphanumeric ones, and more identifiers win
+    assert!(is_newer_version("1.0.0-alpha.beta", "1.0.0-alpha.1"));
+    assert!(is_newer_version("1.0.0-alpha.1", "1.0.0-alpha"));
+    assert!(!is_newer_version("1.0.0-alpha", "1.0.0-alpha.1"));
+    assert!(!is_newer_version("1.0.0-rc.1", "1.0.0-rc.1"));
+    // Garbage is never newer
+    assert!(!is_newer_version("latest", "0.2.0"));
+    assert!(!is_newer_version("1.2", "0.2.0"));
+}
+
+#[test]
+fn test_update_check_cache_expires() {
+    let dir = TempDir::new().unwrap();
+    let cache = dir.path().join("rair-update-check");
+    let now = std::time::SystemTime::now();
+    let hour = Duration::from_secs(3600);
+
+    assert_eq!(cached_latest_version(&cache, now, hour), None);
+    write_cached_latest_version(&cache, now, "0.3.0").unwrap();
+    assert_eq!(
+        cached_latest_version(&cache, now + hour / 2, hour).as_deref(),
+        Some("0.3.0")
+    );
+    assert_eq!(cached_latest_version(&cache, now + hour * 2, hour), None);
+}
+
+#[test]
+fn test_format_status() {
+    let ts = "2024-01-01 00:00:00";
+    assert_eq!(
+        format_status(&Status::Building, ts),
+        "[2024-01-01 00:00:00] rair: building..."
+    );
+    assert_eq!(
+        format_status(&Status::Running { pid: 1234 }, ts),
+        "[2024-01-01 00:00:00] rair: running (pid 1234)"
+    );
+    assert_eq!(
+        format_status(&Status::BuildFailed, ts),
+        "[2024-01-01 00:00:00] rair: build failed; keeping existing process"
+    );
+    assert_eq!(
+        format_status(&Status::StepFailed("pre_run".into()), ts),
+        "[2024-01-01 00:00:00] rair: pre_run failed; keeping existing process"
+    );
+    assert!(!format_status(&Status::Built, ts).contains('\n'));
+}
+
+#[test]
+fn test_timestamp_format() {
+    // 2024-01-01 12:34:56.789 UTC
+    let t = chrono::DateTime::from_timestamp_millis(1_704_112_496_789).unwrap();
+
+    let ts = TimestampFormat::new("%H:%M:%S%.3f", true).unwrap();
+    assert_eq!(ts.format(t), "12:34:56.789");
+    assert_eq!(
+        TimestampFormat::default().now().len(),
+        "2024-01-01 00:00:00".len()
+    );
+
+    let cli = Config {
+        timestamp_format: Some("%Y-%m-%dT%H:%M:%SZ".into()),
+        timestamp_utc: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.timestamp.format(t), "2024-01-01T12:34:56Z");
+
+    // Bad patterns are rejected when the config is resolved
+    assert!(TimestampFormat::new("%Q", false).is_err());
+    let cli = Config {
+        timestamp_format: Some("%H:%".into()),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(err.to_string().contains("timestamp_format"));
+}
+
+#[test]
+fn test_shutdown_summary() {
+    let mut stats = Stats::default();
+    stats.record(BuildOutcome::Succeeded);
+    stats.record(BuildOutcome::Failed);
+    stats.record(BuildOutcome::Pending); // no build ran
+    stats.record(BuildOutcome::Failed);
+
+    assert_eq!(stats.builds, 3);
+    assert_eq!(stats.build_failures, 2);
+    assert_eq!(
+        format_summary(&stats, Duration::from_secs(3723)),
+        "3 builds (2 failed), up 1h 02m 03s; last build failed"
+    );
+    assert_eq!(stats.exit_code(true), 1);
+    assert_eq!(stats.exit_code(false), 0);
+
+    stats.record(BuildOutcome::Succeeded);
+    assert_eq!(
+        format_summary(&stats, Duration::from_secs(65)),
+        "4 builds (2 failed), up 1m 05s"
+    );
+    assert_eq!(stats.exit_code(true), 0);
+}
+
+#[test]
+fn test_timeline_format() {
+    let mut timeline = Timeline::default();
+    assert!(timeline.is_empty());
+    timeline.record("pre_build", Duration::from_millis(300));
+    timeline.record("build", Duration::from_millis(4120));
+    timeline.record("run", Duration::from_millis(20));
+    assert_eq!(
+        timeline.format(),
+        "pre_build 0.3s | build 4.1s | run spawned in 0.02s"
+    );
+
+    // Several hooks in one list are timed one by one
+    let hooks: Vec<Hook> = vec![
+        Hook::Command(vec!["true".into()]),
+        Hook::Command(vec!["true".into()]),
+    ];
+    timeline.clear();
+    assert!(run_hook_list_timed("pre_run", &hooks, &mut timeline).unwrap());
+    let line = timeline.format();
+    assert!(
+        line.starts_with("pre_run[0] ") && line.contains(" | pre_run[1] "),
+        "{}",
+        line
+    );
+
+    timeline.clear();
+    assert!(run_hook_list_timed("pre_run", &hooks[..1], &mut timeline).unwrap());
+    assert!(timeline.format().starts_with("pre_run 0.0"));
+}
+
+#[test]
+fn test_concise_cycle_summary() {
+    let line = format_cycle_summary(true, Some(1234), Duration::from_millis(812));
+    assert_eq!(line, "rebuilt & restarted (pid 1234) in 0.8s");
+    assert!(!line.contains('\n'));
+    assert_eq!(
+        format_cycle_summary(false, Some(7), Duration::from_millis(40)),
+        "restarted (pid 7) in 0.0s"
+    );
+    assert_eq!(
+        format_cycle_summary(true, None, Duration::from_secs(2)),
+        "rebuilt in 2.0s"
+    );
+
+    let cli = Config {
+        concise: Some(true),
+        ..Default::default()
+    };
+    assert!(effective_config(cli, None).unwrap().concise);
+}
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+    assert_eq!(format_duration(Duration::from_millis(300)), "300ms");
+    assert_eq!(format_duration(Duration::from_millis(12_900)), "12s");
+    assert_eq!(format_duration(Duration::from_secs(3600)), "1h 00m 00s");
+}
+
+#[test]
+fn test_control_command_parse() {
+    assert_eq!(
+        ControlCommand::parse("restart\n"),
+        Some(ControlCommand::Restart)
+    );
+    assert_eq!(
+        ControlCommand::parse("trigger"),
+        Some(ControlCommand::Restart)
+    );
+    assert_eq!(ControlCommand::parse("quit"), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_control_socket_restart_fires_trigger() {
+    let dir = TempDir::new().unwrap();
+    let sock = dir.path().join("rair.sock");
+    // A stale socket file from a crashed instance is replaced
+    fs::write(&sock, "").unwrap();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    rair::listen_control_socket(&sock, move |cmd| tx.lock().unwrap().send(cmd).unwrap()).unwrap();
+
+    {
+        use std::io::Write;
+        let mut stream = std::os::unix::net::UnixStream::connect(&sock).unwrap();
+        writeln!(stream, "bogus").unwrap();
+        writeln!(stream, "restart").unwrap();
+    }
+    assert_eq!(
+        rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ControlCommand::Restart
+    );
+
+    rair::send_control_command(&sock, ControlCommand::Restart).unwrap();
+    assert_eq!(
+        rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ControlCommand::Restart
+    );
+
+    // A second listener on a live socket is refused
+    assert!(rair::listen_control_socket(&sock, |_| {}).is_err());
+}
+
+#[cfg(feature = "http-trigger")]
+#[test]
+fn test_http_trigger_post_enqueues_restart() {
+    use std::io::{Read, Write};
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = std::sync::Mutex::new(tx);
+    let addr = rair::listen_http_trigger("127.0.0.1:0", Some("s3cret".into()), move |cmd| {
+        tx.lock().unwrap().send(cmd).unwrap()
+    })
+    .unwrap();
+    let request = |head: &str| {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            head
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    // no token, wrong method, wrong path: nothing is enqueued
+    assert!(request("POST /trigger HTTP/1.1").starts_with("HTTP/1.1 401"));
+    assert!(
+        request("GET /trigger HTTP/1.1\r\nAuthorization: Bearer s3cret")
+            .starts_with("HTTP/1.1 405")
+    );
+    assert!(
+        request("POST /other HTTP/1.1\r\nAuthorization: Bearer s3cret").starts_with("HTTP/1.1 404")
+    );
+    assert!(rx.try_recv().is_err());
+
+    let ok = request("POST /trigger HTTP/1.1\r\nAuthorization: Bearer s3cret");
+    assert!(ok.starts_with("HTTP/1.1 202"), "{}", ok);
+    assert_eq!(
+        rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ControlCommand::Restart
+    );
+}
+
+#[test]
+fn test_trigger_file_detection() {
+    let cli = Config {
+        watch: Some(vec!["src".into()]),
+        trigger_file: Some(".rair-trigger".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    let cwd = std::env::current_dir().unwrap();
+
+    assert!(eff.is_trigger_file(&cwd.join(".rair-trigger")));
+    assert!(eff.is_trigger_file(&PathBuf::from("./.rair-trigger")));
+    assert!(!eff.is_trigger_file(&cwd.join("src/.rair-trigger")));
+    assert!(!eff.is_trigger_file(&cwd.join("src/main.rs")));
+
+    // Its directory isn't under `src`, so it gets its own watch
+    assert_eq!(eff.trigger_watch_dir(), Some(cwd));
+
+    let no_trigger = effective_config(Config::default(), None).unwrap();
+    assert!(!no_trigger.is_trigger_file(&PathBuf::from(".rair-trigger")));
+    assert_eq!(no_trigger.trigger_watch_dir(), None);
+}
+
+#[test]
+fn test_trigger_file_under_watch_path_needs_no_extra_watch() {
+    let cli = Config {
+        watch: Some(vec!["src".into()]),
+        trigger_file: Some("src/.rair-trigger".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.trigger_watch_dir(), None);
+}
+
+#[cfg(feature = "monitor")]
+#[test]
+fn test_process_monitor_samples() {
+    let mut m = rair::ProcessMonitor::new();
+    assert!(m.sample(999_999_999).is_none());
+
+    let sample = m.sample(std::process::id()).unwrap();
+    assert!(sample.rss_bytes > 0);
+}
+
+#[cfg(all(feature = "pty", unix))]
+#[test]
+fn test_pty_child_sees_a_terminal() {
+    use std::io::Read;
+
+    let isatty = "[ -t 1 ] && echo out:tty || echo out:pipe; [ -t 2 ] && echo err:tty >&2";
+    let pty = rair::Pty::open().unwrap();
+    let (stdout, stderr) = pty.stdio().unwrap();
+    let mut child = std::process::Command::new("sh")
+        .args(["-c", isatty])
+        .stdout(stdout)
+        .stderr(stderr)
+        .spawn()
+        .unwrap();
+    let mut reader = pty.reader().unwrap();
+    drop(pty);
+    assert!(child.wait().unwrap().success());
+
+    // the reader ends once the child, the last holder of the terminal side, exits
+    let mut out = String::new();
+    reader.read_to_string(&mut out).unwrap();
+    assert!(out.contains("out:tty"), "{:?}", out);
+    assert!(out.contains("err:tty"), "{:?}", out);
+
+    // through a pipe, the same check says otherwise
+    let piped = std::process::Command::new("sh")
+        .args(["-c", isatty])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&piped.stdout), "out:pipe\n");
+}
+
+#[test]
+fn test_process_sample_describe() {
+    let sample = ProcessSample {
+        cpu_percent: 12.34,
+        rss_bytes: 45 * 1024 * 1024 + 512 * 1024,
+    };
+    assert_eq!(sample.describe(), "cpu 12.3%, rss 45.5 MiB");
+}
+
 // ============================================================================
 // Smart Default Watch Paths Tests
 // ============================================================================
@@ -145,6 +774,65 @@ fn test_explicit_watch_overrides_defaults() {
     assert_eq!(eff.watch[1].to_string_lossy(), "paths");
 }
 
+#[test]
+fn test_missing_watch_retried_once_present() {
+    let dir = TempDir::new().unwrap();
+    let lock = dir.path().join("Cargo.lock");
+    let never = dir.path().join("never");
+
+    let mut missing = MissingWatches::default();
+    missing.push(lock.clone());
+    missing.push(never.clone());
+    missing.push(lock.clone()); // duplicates are ignored
+
+    assert!(missing.take_present().is_empty());
+    assert_eq!(missing.paths().len(), 2);
+
+    fs::write(&lock, "").unwrap();
+    assert_eq!(missing.take_present(), vec![lock]);
+    assert_eq!(missing.paths(), &[never]);
+    assert!(!missing.is_empty());
+}
+
+#[test]
+fn test_watch_setup_abort_decision() {
+    let bad = vec![(
+        PathBuf::from("/proc/1/root"),
+        "permission denied".to_string(),
+    )];
+
+    // Good and bad paths: only --keep-going carries on
+    let err = check_watch_setup(2, &bad, false, false).unwrap_err();
+    assert!(err.to_string().contains("permission denied"), "{}", err);
+    assert!(check_watch_setup(2, &bad, true, false).is_ok());
+
+    // Nothing registered
+    assert!(check_watch_setup(0, &bad, true, false).is_err());
+    assert!(check_watch_setup(0, &[], false, false).is_err());
+    assert!(check_watch_setup(0, &[], false, true).is_err());
+    // ... unless rair can still be triggered through the control socket or trigger file
+    assert!(check_watch_setup(0, &bad, true, true).is_ok());
+
+    assert!(check_watch_setup(1, &[], false, false).is_ok());
+}
+
+#[test]
+fn test_watch_limit_errors_get_a_hint() {
+    let enospc = notify::Error::io(std::io::Error::from_raw_os_error(28));
+    let generic = notify::Error::generic("No space left on device (os error 28)");
+    let max = notify::Error::new(notify::ErrorKind::MaxFilesWatch);
+    for err in [&enospc, &generic, &max] {
+        assert!(is_watch_limit_error(err), "{:?}", err);
+        let msg = describe_watch_error(err);
+        assert!(msg.contains("fs.inotify.max_user_watches"), "{}", msg);
+    }
+
+    let denied = notify::Error::io(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+    assert!(!is_watch_limit_error(&denied));
+    assert!(!describe_watch_error(&denied).contains(WATCH_LIMIT_HINT));
+    assert!(!is_watch_limit_error(&notify::Error::path_not_found()));
+}
+
 // ============================================================================
 // Extension Filter Tests
 // ============================================================================
@@ -153,432 +841,3653 @@ fn test_explicit_watch_overrides_defaults() {
 fn test_ext_filters() {
     let include: HashSet<String> = ["rs".into(), "toml".into()].into_iter().collect();
     let exclude: HashSet<String> = ["lock".into()].into_iter().collect();
+    let none = HashSet::new();
 
     assert!(is_relevant_path(
         PathBuf::from("src/main.rs").as_path(),
+        &none,
         &include,
         &exclude
     ));
     assert!(is_relevant_path(
         PathBuf::from("Cargo.toml").as_path(),
+        &none,
         &include,
         &exclude
     ));
     assert!(is_relevant_path(
         PathBuf::from("Cargo.lock").as_path(),
+        &none,
         &include,
         &exclude
     ));
     assert!(!is_relevant_path(
         PathBuf::from("foo.lock").as_path(),
+        &none,
         &include,
         &exclude
     ));
 }
 
 #[test]
-fn test_ext_normalization() {
-    // Test that extensions are normalized (dots removed, lowercase)
+fn test_editor_temp_files_not_relevant() {
     let cli = Config {
-        include_ext: Some(vec![".RS".into(), "TOML".into(), ".lock".into()]),
+        trigger_globs: Some(vec!["**/*".into()]),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
 
-    assert!(eff.include_ext.contains("rs"));
-    assert!(eff.include_ext.contains("toml"));
-    assert!(eff.include_ext.contains("lock"));
-    assert!(!eff.include_ext.contains(".rs"));
-    assert!(!eff.include_ext.contains("RS"));
+    for temp in [
+        "src/.main.rs.swp",
+        "src/.main.rs.swx",
+        "src/4913",
+        "src/main.rs~",
+        "src/.#main.rs",
+        "src/#main.rs#",
+    ] {
+        assert!(is_editor_temp(Path::new(temp)), "{}", temp);
+        assert!(!eff.is_relevant(Path::new(temp)), "{}", temp);
+    }
+    assert!(!is_editor_temp(Path::new("src/main.rs")));
+    assert!(!is_editor_temp(Path::new("src/#")));
+    assert!(eff.is_relevant(Path::new("src/main.rs")));
+
+    // Opt-out
+    let cli = Config {
+        trigger_globs: Some(vec!["**/*".into()]),
+        ignore_editor_temp: Some(false),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.is_relevant(Path::new("src/.#main.rs")));
+}
+
+#[test]
+fn test_hidden_files_need_include_hidden() {
+    let eff = effective_config(Config::default(), None).unwrap();
+    let root = eff.root.clone();
+    let env_toml = root.join(".config/.env.toml");
+
+    assert!(is_hidden(&env_toml, &root));
+    assert!(is_hidden(Path::new("src/.env.toml"), &root));
+    assert!(!is_hidden(&root.join("src/main.rs"), &root));
+    // Only paths below the project root are judged by their directories
+    assert!(!is_hidden(
+        Path::new("/home/u/.local/app/src/main.rs"),
+        &root
+    ));
+    assert!(is_hidden(Path::new("/home/u/.local/app/.env.toml"), &root));
+
+    assert!(!eff.is_relevant(&env_toml));
+    assert_eq!(eff.relevance_filter(&env_toml), Some(FilterReason::Hidden));
+    assert!(eff.is_relevant(&root.join("src/main.rs")));
+
+    let cli = Config {
+        include_hidden: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.is_relevant(&env_toml));
+    // A leading dot isn't an extension
+    assert!(!eff.is_relevant(&root.join(".config/.toml")));
+    // .git is never un-ignored
+    assert!(!eff.is_relevant(&root.join(".git/config.toml")));
+
+    // Trigger globs name hidden files explicitly
+    let cli = Config {
+        trigger_globs: Some(vec!["**/.config/*.toml".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.is_relevant(&env_toml));
+}
+
+#[test]
+fn test_single_file_watch_entries() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    fs::create_dir(root.join("src")).unwrap();
+    fs::write(root.join("config.yaml"), "a: 1").unwrap();
+    fs::write(root.join("src/app.yaml"), "b: 2").unwrap();
+
+    let entries = vec![root.join("config.yaml"), root.join("src")];
+    assert_eq!(
+        watch_registration(&entries[0], &entries),
+        Some((root.to_path_buf(), RecursiveMode::NonRecursive))
+    );
+    assert_eq!(
+        watch_registration(&entries[1], &entries),
+        Some((root.join("src"), RecursiveMode::Recursive))
+    );
+    // Already covered by the recursive watch on src
+    let with_nested = vec![root.join("src"), root.join("src/app.yaml")];
+    assert_eq!(watch_registration(&with_nested[1], &with_nested), None);
+
+    let set = WatchSet::new(&entries);
+    assert!(set.contains(&root.join("config.yaml")));
+    assert!(set.contains(&root.join("src/deep/main.rs")));
+    // Siblings reported by the parent directory's watch
+    assert!(!set.contains(&root.join("config.yaml.bak")));
+    assert!(!set.contains(&root.join("build.rs")));
+    assert!(!set.contains(&root.join("target/debug/app")));
+
+    // Only directories: nothing to narrow down
+    let set = WatchSet::new(&[root.join("src")]);
+    assert!(set.contains(&root.join("build.rs")));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlinked_directory_changes_are_relevant() {
+    let dir = TempDir::new().unwrap();
+    let shared = dir.path().join("shared");
+    let src = dir.path().join("app/src");
+    fs::create_dir_all(&shared).unwrap();
+    fs::create_dir_all(&src).unwrap();
+    std::os::unix::fs::symlink(&shared, src.join("shared")).unwrap();
+    let real = shared.canonicalize().unwrap();
+
+    let cli = Config {
+        watch: Some(vec![src.display().to_string().into()]),
+        ignore: Some(vec!["**/src/shared/generated.rs".into()]),
+        follow_symlinks: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    let links = SymlinkMap::scan(&eff.watch, eff.follow_symlinks);
+    assert_eq!(links.followed(), std::slice::from_ref(&real));
+
+    // a change under the target is seen through the link, below the watch path
+    let changed = links.link_path(&real.join("lib.rs"));
+    assert!(changed.starts_with(&src), "{}", changed.display());
+    assert!(eff.is_relevant(&changed) && !eff.is_ignored(&changed));
+    // so globs written against the link apply to it
+    assert!(eff.is_ignored(&links.link_path(&real.join("generated.rs"))));
+
+    // a watch path that is itself a link is resolved without follow_symlinks
+    let linked_root = dir.path().join("linked");
+    std::os::unix::fs::symlink(&shared, &linked_root).unwrap();
+    let links = SymlinkMap::scan(std::slice::from_ref(&linked_root), false);
+    assert!(links.followed().is_empty());
+    assert_eq!(
+        links.link_path(&real.join("lib.rs")),
+        linked_root.join("lib.rs")
+    );
+    let unrelated = dir.path().join("elsewhere/lib.rs");
+    assert_eq!(links.link_path(&unrelated), unrelated);
+}
+
+/// Records what rair asks a watcher to watch.
+#[derive(Default)]
+struct RecordingWatcher {
+    watched: Vec<(PathBuf, RecursiveMode)>,
+}
+
+impl notify::Watcher for RecordingWatcher {
+    fn new<F: notify::EventHandler>(_: F, _: notify::Config) -> notify::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn watch(&mut self, path: &Path, mode: RecursiveMode) -> notify::Result<()> {
+        self.watched.push((path.to_path_buf(), mode));
+        Ok(())
+    }
+
+    fn unwatch(&mut self, _: &Path) -> notify::Result<()> {
+        Ok(())
+    }
+
+    fn kind() -> notify::WatcherKind {
+        notify::WatcherKind::NullWatcher
+    }
+}
+
+#[test]
+fn test_non_recursive_watch_entry() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("config/large")).unwrap();
+    fs::create_dir(root.join("src")).unwrap();
+    fs::write(root.join("config/app.toml"), "").unwrap();
+    let config_path = root.join(".rair.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "watch = [{:?}, {{ path = {:?}, recursive = false }}]\n",
+            root.join("src"),
+            root.join("config")
+        ),
+    )
+    .unwrap();
+
+    let eff =
+        effective_config(Config::default(), Some(load_config(&config_path).unwrap())).unwrap();
+    assert_eq!(eff.non_recursive, [root.join("config")]);
+
+    let mut watcher = RecordingWatcher::default();
+    for p in &eff.watch {
+        assert!(eff.register_watch(&mut watcher, p).unwrap());
+    }
+    assert_eq!(
+        watcher.watched,
+        [
+            (root.join("src"), RecursiveMode::Recursive),
+            (root.join("config"), RecursiveMode::NonRecursive)
+        ]
+    );
+
+    // only the files directly inside count as watched
+    assert!(eff.is_watched(&root.join("config/app.toml")));
+    assert!(!eff.is_watched(&root.join("config/large/data.toml")));
+    assert!(eff.is_watched(&root.join("src/deep/main.rs")));
+}
+
+#[test]
+fn test_watch_stats_counts_each_filter_reason() {
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(
+        eff.relevance_filter(Path::new("src/.main.rs.swp")),
+        Some(FilterReason::EditorTemp)
+    );
+    assert_eq!(
+        eff.relevance_filter(Path::new("README.md")),
+        Some(FilterReason::Extension)
+    );
+    assert_eq!(eff.relevance_filter(Path::new("src/main.rs")), None);
+
+    let mut stats = WatchStats::default();
+    for reason in [
+        FilterReason::Ignored,
+        FilterReason::Ignored,
+        FilterReason::Untracked,
+        FilterReason::EditorTemp,
+        FilterReason::Extension,
+        FilterReason::Unchanged,
+        FilterReason::Cooldown,
+        FilterReason::Unwatched,
+    ] {
+        stats.record_filtered(reason);
+    }
+    assert_eq!(
+        stats,
+        WatchStats {
+            ignored: 2,
+            untracked: 1,
+            editor_temp: 1,
+            extension: 1,
+            unchanged: 1,
+            cooldown: 1,
+            unwatched: 1,
+            ..Default::default()
+        }
+    );
+
+    let stats = WatchStats {
+        events: 12,
+        debounced: 1,
+        ignored: 8,
+        extension: 3,
+        rebuilds: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        stats.report(Duration::from_secs(10)),
+        "12 events (1.2/s), 1 rebuild; filtered: 1 debounced, 8 ignored, 3 extension"
+    );
+    assert_eq!(
+        WatchStats::default().report(Duration::from_secs(10)),
+        "0 events (0.0/s), 0 rebuilds"
+    );
+}
+
+#[test]
+fn test_events_filter_by_kind() {
+    use notify::event::{CreateKind, DataChange, EventKind, ModifyKind, RemoveKind, RenameMode};
+
+    let create = EventKind::Create(CreateKind::File);
+    let modify = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+    let rename = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+    let remove = EventKind::Remove(RemoveKind::File);
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    for kind in [create, modify, rename, remove] {
+        assert!(eff.allows_event(&kind), "{:?}", kind);
+    }
+
+    let cli = Config {
+        events: Some(vec!["modify".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(!eff.allows_event(&create));
+    assert!(eff.allows_event(&modify));
+    assert!(!eff.allows_event(&rename));
+    assert!(!eff.allows_event(&remove));
+    // Kinds that don't say what happened are let through
+    assert!(eff.allows_event(&EventKind::Any));
+
+    let cli = Config {
+        events: Some(vec!["modify".into(), "touch".into()]),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(matches!(err, RairError::InvalidConfig(_)), "{:?}", err);
+    assert!(err.to_string().contains("\"touch\""), "{}", err);
+}
+
+#[test]
+fn test_watch_metadata_opts_into_metadata_changes() {
+    use notify::event::{EventKind, MetadataKind, ModifyKind};
+
+    let chmod = EventKind::Modify(ModifyKind::Metadata(MetadataKind::Permissions));
+    let touch = EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any));
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert!(!eff.watch_metadata);
+    assert!(!eff.allows_event(&chmod));
+    assert!(!eff.allows_event(&touch));
+
+    let cli = Config {
+        watch_metadata: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.allows_event(&chmod));
+    assert!(eff.allows_event(&touch));
+    assert!(eff.is_relevant(Path::new("src/main.rs")));
+
+    // They are modifications, so `events` still applies
+    let cli = Config {
+        watch_metadata: Some(true),
+        events: Some(vec!["create".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(!eff.allows_event(&chmod));
+}
+
+#[test]
+fn test_rename_considers_only_the_target() {
+    use notify::event::{EventKind, ModifyKind, RenameMode};
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    let atomic_save = notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+        .add_path(PathBuf::from("src/main.rs.tmp.1234"))
+        .add_path(PathBuf::from("src/main.rs"));
+
+    assert_eq!(event_paths(&atomic_save), &[PathBuf::from("src/main.rs")]);
+    assert!(event_paths(&atomic_save).iter().any(|p| eff.is_relevant(p)));
+
+    let write = notify::Event::new(EventKind::Create(notify::event::CreateKind::File))
+        .add_path(PathBuf::from("src/4913"));
+    assert_eq!(event_paths(&write).len(), 1);
+    assert!(!event_paths(&write).iter().any(|p| eff.is_relevant(p)));
+}
+
+#[test]
+fn test_ext_normalization() {
+    // Test that extensions are normalized (dots removed, lowercase)
+    let cli = Config {
+        include_ext: Some(vec![".RS".into(), "TOML".into(), ".lock".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.include_ext.contains("rs"));
+    assert!(eff.include_ext.contains("toml"));
+    assert!(eff.include_ext.contains("lock"));
+    assert!(!eff.include_ext.contains(".rs"));
+    assert!(!eff.include_ext.contains("RS"));
+}
+
+#[test]
+fn test_cli_include_ext_adds_to_file() {
+    let file = Config {
+        include_ext: Some(vec!["rs".into()]),
+        exclude_ext: Some(vec!["bak".into()]),
+        ..Default::default()
+    };
+    let cli = Config {
+        include_ext: Some(vec!["html".into(), ".RS".into()]),
+        exclude_ext: Some(vec!["tmp".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, Some(file)).unwrap();
+
+    let expected: HashSet<String> = ["rs".into(), "html".into()].into_iter().collect();
+    assert_eq!(eff.include_ext, expected);
+    assert!(eff.exclude_ext.contains("bak"));
+    assert!(eff.exclude_ext.contains("tmp"));
+}
+
+#[test]
+fn test_cli_include_ext_adds_to_defaults() {
+    let cli = Config {
+        include_ext: Some(vec!["html".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.include_ext.contains("rs"));
+    assert!(eff.include_ext.contains("toml"));
+    assert!(eff.include_ext.contains("html"));
+}
+
+#[test]
+fn test_only_ext_overrides_include_ext() {
+    let file = Config {
+        include_ext: Some(vec!["rs".into()]),
+        ..Default::default()
+    };
+    let cli = Config {
+        only_ext: Some(vec!["md".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, Some(file)).unwrap();
+
+    let expected: HashSet<String> = ["md".into()].into_iter().collect();
+    assert_eq!(eff.include_ext, expected);
+}
+
+#[test]
+fn test_trigger_globs() {
+    let cli = Config {
+        trigger_globs: Some(vec!["**/*.proto".into(), "**/test_*.rs".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.is_relevant(&PathBuf::from("proto/user.proto")));
+    assert!(eff.is_relevant(&PathBuf::from("/abs/project/proto/user.proto")));
+    assert!(eff.is_relevant(&PathBuf::from("src/main.rs"))); // extension check still applies
+    assert!(!eff.is_relevant(&PathBuf::from("notes/todo.txt")));
+}
+
+#[test]
+fn test_always_rebuild_bypasses_extension_filters() {
+    let cli = Config {
+        always_rebuild: Some(vec![
+            "**/diesel.toml".into(),
+            "**/.sqlx/**".into(),
+            "/vendor/*/schema.graphql".into(),
+        ]),
+        include_ext: Some(vec!["rs".into()]),
+        exclude_ext: Some(vec!["toml".into()]),
+        ignore: Some(vec!["**/generated/**".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    // neither an included extension nor a visible path, but always relevant
+    assert!(eff.is_relevant(&eff.root.join("diesel.toml")));
+    assert!(eff.is_relevant(&eff.root.join(".sqlx/query-1a2b.json")));
+    // a leading `/` is the project root
+    assert!(eff.is_relevant(&eff.root.join("vendor/dep/schema.graphql")));
+    assert!(!eff.is_relevant(&PathBuf::from("/elsewhere/vendor/dep/schema.graphql")));
+    assert!(!eff.is_relevant(&eff.root.join("notes.toml")));
+    // ignore still wins
+    let generated = eff.root.join("generated/diesel.toml");
+    assert!(eff.is_relevant(&generated) && eff.is_ignored(&generated));
+}
+
+#[test]
+fn test_trigger_globs_invalid_pattern_errors() {
+    let cli = Config {
+        trigger_globs: Some(vec!["[bad".into()]),
+        ..Default::default()
+    };
+    assert!(effective_config(cli, None).is_err());
 }
 
 #[test]
 fn test_cargo_files_always_relevant() {
     let include: HashSet<String> = ["rs".into()].into_iter().collect();
     let exclude: HashSet<String> = ["toml".into(), "lock".into()].into_iter().collect();
+    let none = HashSet::new();
+
+    // Even though toml and lock are excluded, Cargo.toml and Cargo.lock are always relevant
+    assert!(is_relevant_path(
+        PathBuf::from("Cargo.toml").as_path(),
+        &none,
+        &include,
+        &exclude
+    ));
+    assert!(is_relevant_path(
+        PathBuf::from("Cargo.lock").as_path(),
+        &none,
+        &include,
+        &exclude
+    ));
+
+    // But other .toml files should be excluded
+    assert!(!is_relevant_path(
+        PathBuf::from("config.toml").as_path(),
+        &none,
+        &include,
+        &exclude
+    ));
+}
+
+#[test]
+fn test_extra_manifests_always_relevant() {
+    let temp = TempDir::new().unwrap();
+    let other = temp.path().join("other");
+    fs::create_dir_all(&other).unwrap();
+    fs::write(other.join("Cargo.toml"), "[package]\nname = \"other\"\n").unwrap();
+
+    let cfg = Config {
+        extra_manifests: Some(vec![other.to_string_lossy().into()]),
+        ..Config::default()
+    };
+    let eff = effective_config(cfg, None).unwrap();
+    // the manifest is watched; its lock file counts even before it exists
+    assert!(eff.watch.contains(&other.join("Cargo.toml")));
+    assert!(!eff.watch.contains(&other.join("Cargo.lock")));
+    assert!(eff
+        .extra_manifests
+        .contains(&path_key(&other.join("Cargo.lock"))));
+
+    let include: HashSet<String> = ["rs".into()].into_iter().collect();
+    let exclude: HashSet<String> = ["toml".into()].into_iter().collect();
+    // a manifest listed by path is relevant even when named differently
+    let renamed = other.join("Other.toml");
+    fs::write(&renamed, "").unwrap();
+    let manifests: HashSet<PathBuf> = [path_key(&renamed)].into_iter().collect();
+    assert!(is_relevant_path(&renamed, &manifests, &include, &exclude));
+    assert!(!is_relevant_path(
+        &other.join("config.toml"),
+        &manifests,
+        &include,
+        &exclude
+    ));
+    // an unrelated Cargo.toml still matches by name
+    assert!(is_relevant_path(
+        Path::new("elsewhere/Cargo.toml"),
+        &manifests,
+        &include,
+        &exclude
+    ));
+
+    let missing = Config {
+        extra_manifests: Some(vec![temp.path().join("nope").to_string_lossy().into()]),
+        ..Config::default()
+    };
+    assert!(effective_config(missing, None).is_err());
+}
+
+#[test]
+fn test_no_extension_ignored() {
+    let include: HashSet<String> = ["rs".into()].into_iter().collect();
+    let exclude: HashSet<String> = HashSet::new();
+    let none = HashSet::new();
+
+    assert!(!is_relevant_path(
+        PathBuf::from("README").as_path(),
+        &none,
+        &include,
+        &exclude
+    ));
+    assert!(!is_relevant_path(
+        PathBuf::from("Makefile").as_path(),
+        &none,
+        &include,
+        &exclude
+    ));
+}
+
+#[test]
+fn test_pause_drops_events_until_resumed() {
+    let mut pause = PauseState::default();
+    assert!(pause.admit(vec![PathBuf::from("src/main.rs")]));
+
+    assert_eq!(pause.toggle(), None);
+    assert!(pause.is_paused());
+    assert!(!pause.admit(vec![PathBuf::from("src/lib.rs")]));
+    assert!(!pause.admit(vec![
+        PathBuf::from("src/lib.rs"),
+        PathBuf::from("README.md"),
+    ]));
+    assert!(!pause.admit(Vec::new()));
+
+    // resuming hands back what was missed, once
+    assert_eq!(
+        pause.toggle(),
+        Some(vec![
+            PathBuf::from("README.md"),
+            PathBuf::from("src/lib.rs")
+        ])
+    );
+    assert!(pause.admit(vec![PathBuf::from("src/lib.rs")]));
+    pause.toggle();
+    assert_eq!(pause.toggle(), Some(Vec::new()));
+}
+
+#[test]
+fn test_min_build_interval_decision() {
+    let mut limit = BuildRateLimit::new(Duration::from_secs(2));
+    let t0 = Instant::now();
+    // nothing built yet
+    assert_eq!(limit.wait(t0), None);
+    assert!(!limit.due(t0));
+
+    limit.started(t0);
+    assert_eq!(
+        limit.wait(t0 + Duration::from_millis(500)),
+        Some(Duration::from_millis(1500))
+    );
+    // a change too soon defers the build once, however many follow
+    assert!(limit.defer());
+    assert!(!limit.defer());
+    assert!(!limit.due(t0 + Duration::from_millis(1999)));
+    assert!(limit.due(t0 + Duration::from_secs(2)));
+    assert_eq!(limit.wait(t0 + Duration::from_secs(3)), None);
+
+    limit.started(t0 + Duration::from_secs(3));
+    assert!(!limit.due(t0 + Duration::from_secs(10)));
+
+    let cli = Config {
+        min_build_interval_ms: Some(0),
+        ..Default::default()
+    };
+    assert_eq!(
+        effective_config(cli, None).unwrap().min_build_interval,
+        None
+    );
+}
+
+#[test]
+fn test_per_path_cooldown() {
+    let mut c = PathCooldown::new(Duration::from_millis(500));
+    let t0 = Instant::now();
+    let a = PathBuf::from("src/a.rs");
+    let b = PathBuf::from("src/b.rs");
+
+    assert!(c.allow(&a, t0));
+    assert!(!c.allow(&a, t0 + Duration::from_millis(100))); // still cooling down
+    assert!(c.allow(&b, t0 + Duration::from_millis(100))); // other paths unaffected
+    assert!(c.allow(&a, t0 + Duration::from_millis(600))); // cooldown elapsed
+}
+
+#[test]
+fn test_per_path_cooldown_evicts_oldest() {
+    let mut c = PathCooldown::with_capacity(Duration::from_secs(60), 2);
+    let t0 = Instant::now();
+    let a = PathBuf::from("a.rs");
+
+    assert!(c.allow(&a, t0));
+    assert!(c.allow(&PathBuf::from("b.rs"), t0 + Duration::from_millis(1)));
+    assert!(c.allow(&PathBuf::from("c.rs"), t0 + Duration::from_millis(2)));
+    assert_eq!(c.len(), 2);
+
+    // `a` was evicted, so it's allowed again despite the long cooldown
+    assert!(c.allow(&a, t0 + Duration::from_millis(3)));
+}
+
+#[test]
+fn test_per_path_cooldown_config() {
+    let cli = Config {
+        per_path_cooldown_ms: Some(750),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.per_path_cooldown, Some(Duration::from_millis(750)));
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert!(eff.per_path_cooldown.is_none());
+}
+
+fn git(dir: &std::path::Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdout(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+#[test]
+fn test_git_tracked_filter() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    git(&root, &["init", "-q"]);
+    fs::write(root.join("tracked.rs"), "").unwrap();
+    fs::write(root.join("scratch.rs"), "").unwrap();
+    fs::write(root.join("debug.log"), "").unwrap();
+    fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+    git(&root, &["add", "tracked.rs", ".gitignore"]);
+
+    let tracked = GitTracked::load(&root, false).unwrap();
+    assert!(tracked.contains(&root.join("tracked.rs")));
+    assert!(!tracked.contains(&root.join("scratch.rs")));
+    assert!(!tracked.contains(&root.join("debug.log")));
+
+    let with_untracked = GitTracked::load(&root, true).unwrap();
+    assert!(with_untracked.contains(&root.join("tracked.rs")));
+    assert!(with_untracked.contains(&root.join("scratch.rs")));
+    assert!(!with_untracked.contains(&root.join("debug.log")));
+}
+
+#[test]
+fn test_git_tracked_refresh_after_add() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    git(&root, &["init", "-q"]);
+    fs::write(root.join("new.rs"), "").unwrap();
+
+    let mut tracked = GitTracked::load(&root, false).unwrap();
+    assert!(!tracked.contains(&root.join("new.rs")));
+
+    git(&root, &["add", "new.rs"]);
+    assert!(tracked.needs_refresh(&root.join(".git/index")));
+    tracked.refresh().unwrap();
+    assert!(tracked.contains(&root.join("new.rs")));
+}
+
+#[test]
+fn test_git_tracked_outside_repo_errors() {
+    let dir = TempDir::new().unwrap();
+    // A fresh temp dir may still sit inside some repo; only assert when it doesn't.
+    if std::process::Command::new("git")
+        .args(["rev-parse", "--git-dir"])
+        .current_dir(dir.path())
+        .stderr(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .status()
+        .map(|s| !s.success())
+        .unwrap_or(false)
+    {
+        assert!(GitTracked::load(dir.path(), false).is_err());
+    }
+}
+
+// ============================================================================
+// Executable Path Tests
+// ============================================================================
+
+#[test]
+fn test_exe_name_and_path() {
+    let name = exe_name("mybin");
+    #[cfg(windows)]
+    assert_eq!(name, "mybin.exe");
+    #[cfg(not(windows))]
+    assert_eq!(name, "mybin");
+
+    let td = PathBuf::from("target");
+    let p1 = exe_path(&td, false, "mybin");
+    let p2 = exe_path(&td, true, "mybin");
+    assert!(p1.to_string_lossy().contains("debug"));
+    assert!(p2.to_string_lossy().contains("release"));
+}
+
+#[test]
+fn test_exe_path_different_bins() {
+    let td = PathBuf::from("target");
+    let p1 = exe_path(&td, false, "server");
+    let p2 = exe_path(&td, false, "client");
+
+    assert!(p1.to_string_lossy().contains("server"));
+    assert!(p2.to_string_lossy().contains("client"));
+    assert_ne!(p1, p2);
+}
+
+// ============================================================================
+// Hook Execution Tests
+// ============================================================================
+
+fn ok_cmd() -> Vec<String> {
+    #[cfg(windows)]
+    {
+        vec!["cmd".into(), "/C".into(), "exit".into(), "0".into()]
+    }
+    #[cfg(not(windows))]
+    {
+        vec!["sh".into(), "-c".into(), "true".into()]
+    }
+}
+
+fn fail_cmd() -> Vec<String> {
+    #[cfg(windows)]
+    {
+        vec!["cmd".into(), "/C".into(), "exit".into(), "1".into()]
+    }
+    #[cfg(not(windows))]
+    {
+        vec!["sh".into(), "-c".into(), "false".into()]
+    }
+}
+
+#[test]
+fn test_hooks_stop_on_failure() {
+    let hooks: Vec<Hook> = vec![ok_cmd().into(), fail_cmd().into(), ok_cmd().into()];
+    let ok = run_hook_list("test", &hooks).unwrap();
+    assert!(!ok);
+}
+
+#[test]
+fn test_hooks_all_ok() {
+    let hooks: Vec<Hook> = vec![ok_cmd().into(), ok_cmd().into()];
+    let ok = run_hook_list("test", &hooks).unwrap();
+    assert!(ok);
+}
+
+#[test]
+fn test_hooks_empty() {
+    let hooks: Vec<Hook> = vec![];
+    let ok = run_hook_list("test", &hooks).unwrap();
+    assert!(ok); // Empty hooks should succeed
+}
+
+#[test]
+fn test_hooks_single_command() {
+    let hooks = vec![Hook::from(ok_cmd())];
+    let ok = run_hook_list("test", &hooks).unwrap();
+    assert!(ok);
+}
+
+#[test]
+fn test_hook_empty_argv_errors() {
+    let hooks = vec![Hook::Command(vec![])]; // Empty command
+    let result = run_hook_list("test", &hooks);
+    assert!(result.is_err());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_parallel_hooks_overlap() {
+    let sleepy = || vec!["sh".into(), "-c".into(), "sleep 0.3".into()];
+    let hooks = vec![Hook::Parallel {
+        parallel: vec![sleepy(), sleepy()],
+    }];
+    let start = Instant::now();
+    assert!(run_hook_list("pre_build", &hooks).unwrap());
+    // Sequentially this would take at least 600ms
+    assert!(start.elapsed() < Duration::from_millis(550));
+}
+
+#[test]
+fn test_parallel_hooks_fail_if_any_fails() {
+    let hooks = vec![
+        Hook::Parallel {
+            parallel: vec![ok_cmd(), fail_cmd()],
+        },
+        Hook::Command(ok_cmd()),
+    ];
+    assert!(!run_hook_list("pre_build", &hooks).unwrap());
+}
+
+#[test]
+fn test_parallel_hook_empty_argv_errors() {
+    let hooks = vec![Hook::Parallel {
+        parallel: vec![ok_cmd(), vec![]],
+    }];
+    assert!(run_hook_list("pre_build", &hooks).is_err());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_post_build_exe_placeholder() {
+    let dir = TempDir::new().unwrap();
+    let copy = dir.path().join("deployed");
+    let cli = Config {
+        runner: Some(vec!["gdb".into()]),
+        post_build: Some(vec![vec![
+            "sh".into(),
+            "-c".into(),
+            format!("echo {{exe}} > {}", copy.display()),
+        ]
+        .into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    let hook = &eff.post_build[0];
+    assert!(hook.uses(EXE_PLACEHOLDER));
+
+    // The built binary, without the runner
+    let built = dir.path().join("target/debug/app");
+    let exe = resolve_exe(&eff, |_| Ok(vec![built.to_string_lossy().into_owned()])).unwrap();
+    assert_eq!(exe, built.to_string_lossy());
+    let expanded = hook.expand(EXE_PLACEHOLDER, &exe);
+    assert!(!expanded.uses(EXE_PLACEHOLDER));
+    assert!(run_hook_list("post_build", &[expanded]).unwrap());
+    assert_eq!(
+        fs::read_to_string(&copy).unwrap().trim(),
+        built.to_string_lossy()
+    );
+
+    // Parallel groups are expanded too
+    let group = Hook::Parallel {
+        parallel: vec![vec!["cp".into(), "{exe}".into(), "/srv/app".into()]],
+    };
+    assert_eq!(
+        group.expand(EXE_PLACEHOLDER, "/t/app"),
+        Hook::Parallel {
+            parallel: vec![vec!["cp".into(), "/t/app".into(), "/srv/app".into()]]
+        }
+    );
+
+    // An explicit run names the binary itself
+    let cli = Config {
+        run: Some(vec!["/opt/app".into(), "--port".into(), "80".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(resolve_exe(&eff, |_| unreachable!()).unwrap(), "/opt/app");
+}
+
+#[test]
+fn test_load_config_parallel_hook_group() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join(".rair.toml");
+    fs::write(
+        &config_path,
+        r#"
+pre_build = [
+  { parallel = [["cargo", "fmt"], ["./generate-protos"]] },
+  ["cargo", "check"],
+]
+"#,
+    )
+    .unwrap();
+
+    let cfg = load_config(&config_path).unwrap();
+    assert_eq!(
+        cfg.pre_build.unwrap(),
+        vec![
+            Hook::Parallel {
+                parallel: vec![
+                    vec!["cargo".to_string(), "fmt".to_string()],
+                    vec!["./generate-protos".to_string()],
+                ],
+            },
+            Hook::Command(vec!["cargo".to_string(), "check".to_string()]),
+        ]
+    );
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_buffered_build_output_released_on_success_only() {
+    let build = |script: &str| {
+        let mut c = std::process::Command::new("sh");
+        c.args(["-c", script]);
+        c
+    };
+
+    let (status, output) =
+        run_captured(build("echo compiling; echo 'error: oops' >&2; exit 1")).unwrap();
+    assert!(!status.success());
+    assert_eq!(output.len(), 2);
+    assert!(output.release(status.success()).is_empty());
+
+    let (status, output) = run_captured(build("echo compiling; echo warning >&2")).unwrap();
+    assert!(status.success());
+    let mut lines = output.release(status.success());
+    lines.sort_by(|a, b| a.text.cmp(&b.text));
+    assert_eq!(
+        lines,
+        vec![
+            OutputLine {
+                text: "compiling".into(),
+                stdout: true
+            },
+            OutputLine {
+                text: "warning".into(),
+                stdout: false
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_cargo_lock_wait_classifier() {
+    assert!(is_cargo_lock_wait(
+        "    Blocking waiting for file lock on build directory"
+    ));
+    assert!(is_cargo_lock_wait(
+        "    Blocking waiting for file lock on package cache"
+    ));
+    assert!(is_cargo_lock_wait(
+        "Blocking waiting for file lock on artifact directory"
+    ));
+
+    // other progress lines and real failures aren't waits
+    assert!(!is_cargo_lock_wait("   Compiling app v0.1.0 (/src/app)"));
+    assert!(!is_cargo_lock_wait("error: could not compile `app`"));
+    assert!(!is_cargo_lock_wait(
+        "error: failed to acquire file lock: Blocking waiting for file lock"
+    ));
+    assert!(!is_cargo_lock_wait(""));
+}
+
+#[test]
+fn test_failed_build_output_tail() {
+    let output = |text: &str| {
+        let mut out = OutputBuffer::default();
+        for line in text.lines() {
+            out.push(line, false);
+        }
+        out
+    };
+    let text =
+        "   Compiling app v0.1.0\nerror[E0425]: cannot find value `x`\n --> src/main.rs:2:5\n\
+                error: could not compile `app`";
+
+    let (omitted, tail) = output(text).into_tail(2);
+    assert_eq!(omitted, 2);
+    let tail: Vec<&str> = tail.iter().map(|l| l.text.as_str()).collect();
+    assert_eq!(
+        tail,
+        [" --> src/main.rs:2:5", "error: could not compile `app`"]
+    );
+    // fewer lines than asked for: all of them
+    assert_eq!(output(text).into_tail(10).0, 0);
+    assert_eq!(output(text).into_tail(10).1.len(), 4);
+    assert!(output(text).into_tail(0).1.is_empty());
+}
+
+#[test]
+fn test_repeated_build_failure_is_collapsed() {
+    let failure = |err: &str| {
+        let mut out = OutputBuffer::default();
+        out.push("   Compiling app v0.1.0 (/src/app)", false);
+        out.push(err, false);
+        out.push("error: could not compile `app`", false);
+        out
+    };
+    let mut cache = FailureCache::default();
+
+    assert!(!cache.is_repeat(&failure("error[E0425]: cannot find value `x`")));
+    // Same errors; progress lines don't count
+    let mut again = failure("error[E0425]: cannot find value `x`");
+    again.push(
+        "    Blocking waiting for file lock on build directory",
+        false,
+    );
+    assert!(cache.is_repeat(&again));
+    assert_eq!(REPEATED_FAILURE_MSG, "build failed (same errors as before)");
+
+    // Different errors are shown again, and become the new baseline
+    assert!(!cache.is_repeat(&failure("error[E0308]: mismatched types")));
+    assert!(cache.is_repeat(&failure("error[E0308]: mismatched types")));
+
+    // A successful build resets it
+    cache.clear();
+    assert!(!cache.is_repeat(&failure("error[E0308]: mismatched types")));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_on_build_fail_hook_sees_exit_code() {
+    let env = build_fail_env(&["cargo".into(), "build".into()], Some(101), Some(3));
+    let hooks = vec![Hook::Command(vec![
+        "sh".into(),
+        "-c".into(),
+        r#"test "$RAIR_EXIT_CODE" = 101 && test "$RAIR_BUILD_CMD" = "cargo build" && test "$RAIR_ERROR_COUNT" = 3"#.into(),
+    ])];
+    assert!(run_hook_list_with_env("on_build_fail", &hooks, &env).unwrap());
+
+    // Hooks run without the extra env don't see it
+    assert!(!run_hook_list("on_build_fail", &hooks).unwrap());
+}
+
+#[test]
+fn test_build_fail_env_without_exit_code() {
+    // A build killed by a signal has no exit code
+    let env = build_fail_env(&["cargo".into(), "build".into()], None, None);
+    assert!(env.contains(&("RAIR_EXIT_CODE".into(), String::new())));
+    // Without parse_diagnostics the errors weren't counted
+    assert!(env.contains(&("RAIR_ERROR_COUNT".into(), String::new())));
+}
+
+// ============================================================================
+// Readiness Tests
+// ============================================================================
+
+#[test]
+fn test_wait_until_ready_retries() {
+    let mut calls = 0;
+    let ready = wait_until_ready(
+        || {
+            calls += 1;
+            calls == 3
+        },
+        Duration::from_millis(1),
+        5,
+    );
+    assert!(ready);
+    assert_eq!(calls, 3);
+
+    let mut calls = 0;
+    let ready = wait_until_ready(
+        || {
+            calls += 1;
+            false
+        },
+        Duration::from_millis(1),
+        4,
+    );
+    assert!(!ready);
+    assert_eq!(calls, 4);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_post_ready_runs_after_health_check_passes() {
+    let dir = TempDir::new().unwrap();
+    let marker = dir.path().join("ready");
+    let probe = vec![
+        "sh".into(),
+        "-c".into(),
+        format!("test -f '{}'", marker.display()),
+    ];
+    let cli = Config {
+        health_check: Some(probe.clone()),
+        health_interval_ms: Some(10),
+        health_retries: Some(200),
+        // Fails unless the server signalled readiness before the hook ran
+        post_ready: Some(vec![probe.into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    let signal = {
+        let marker = marker.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            fs::write(marker, "").unwrap();
+        })
+    };
+    assert!(run_post_ready(&eff, None, || true).unwrap());
+    signal.join().unwrap();
+}
+
+#[test]
+fn test_post_ready_skipped_when_never_ready() {
+    let cli = Config {
+        health_check: Some(fail_cmd()),
+        health_interval_ms: Some(1),
+        health_retries: Some(2),
+        post_ready: Some(vec![ok_cmd().into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(!run_post_ready(&eff, None, || true).unwrap());
+
+    // a readiness already known isn't probed again
+    assert!(run_post_ready(&eff, Some(true), || true).unwrap());
+    // ...nor when it failed: the probe and the hook would both pass now
+    let passing = Config {
+        health_check: Some(ok_cmd()),
+        post_ready: Some(vec![ok_cmd().into()]),
+        ..Default::default()
+    };
+    assert!(!run_post_ready(
+        &effective_config(passing, None).unwrap(),
+        Some(false),
+        || true
+    )
+    .unwrap());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_post_ready_skipped_for_stale_cycle() {
+    let dir = TempDir::new().unwrap();
+    let marker = dir.path().join("ready");
+    let log = dir.path().join("hooks.log");
+    let cli = Config {
+        health_check: Some(vec![
+            "sh".into(),
+            "-c".into(),
+            format!("test -f '{}'", marker.display()),
+        ]),
+        health_interval_ms: Some(10),
+        health_retries: Some(200),
+        post_ready: Some(vec![vec![
+            "sh".into(),
+            "-c".into(),
+            format!("echo ran >> '{}'", log.display()),
+        ]
+        .into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    // the first cycle's process never gets ready before the next restart
+    let generations = PostReadyGeneration::default();
+    let first = generations.next();
+    let probing = {
+        let (eff, generations) = (eff.clone(), generations.clone());
+        std::thread::spawn(move || run_post_ready(&eff, None, || generations.is_current(first)))
+    };
+    std::thread::sleep(Duration::from_millis(30));
+
+    // the second cycle's process is ready: its hooks run, the first probe gives up
+    let second = generations.next();
+    fs::write(&marker, "").unwrap();
+    assert!(run_post_ready(&eff, None, || generations.is_current(second)).unwrap());
+    assert!(!probing.join().unwrap().unwrap());
+    assert_eq!(fs::read_to_string(&log).unwrap(), "ran\n");
+}
+
+#[test]
+fn test_ready_port_probe() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    assert!(probe_tcp_port(
+        "127.0.0.1",
+        port,
+        Duration::from_millis(200)
+    ));
+    let cli = Config {
+        ready_port: Some(port),
+        health_interval_ms: Some(10),
+        health_retries: Some(3),
+        post_ready: Some(vec![ok_cmd().into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.ready_host, "127.0.0.1");
+    assert!(eff.has_readiness_probe());
+    assert!(run_post_ready(&eff, None, || true).unwrap());
+
+    // Nothing listens once the listener is dropped: the probe gives up after the retries
+    drop(listener);
+    assert!(!probe_tcp_port(
+        "127.0.0.1",
+        port,
+        Duration::from_millis(200)
+    ));
+    let started = Instant::now();
+    assert!(!run_post_ready(&eff, None, || true).unwrap());
+    assert!(started.elapsed() >= Duration::from_millis(20));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_run_timeout_kills_sleepy_run() {
+    let mut child = std::process::Command::new("sh")
+        .args(["-c", "sleep 10"])
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    let outcome = wait_timeout(
+        || child.try_wait(),
+        Some(Duration::from_millis(100)),
+        || false,
+    )
+    .unwrap();
+    assert_eq!(outcome, WaitOutcome::TimedOut);
+    assert!(!outcome.success());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_wait_timeout_passes_exit_code_through() {
+    let mut child = std::process::Command::new("sh")
+        .args(["-c", "exit 3"])
+        .spawn()
+        .unwrap();
+    let outcome =
+        wait_timeout(|| child.try_wait(), Some(Duration::from_secs(10)), || false).unwrap();
+    assert!(matches!(outcome, WaitOutcome::Exited(_)));
+    assert_eq!(outcome.code(), Some(3));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_wait_timeout_interrupted() {
+    let mut child = std::process::Command::new("sh")
+        .args(["-c", "sleep 10"])
+        .spawn()
+        .unwrap();
+    let outcome = wait_timeout(|| child.try_wait(), None, || true).unwrap();
+    assert_eq!(outcome, WaitOutcome::Interrupted);
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_initial_build_retried_until_success() {
+    // Fails twice, then succeeds
+    let mut results = vec![
+        BuildOutcome::Succeeded,
+        BuildOutcome::Failed,
+        BuildOutcome::Failed,
+    ];
+    let mut attempts = 0;
+    let mut retries = Vec::new();
+    let outcome = retry_initial_build(
+        3,
+        || {
+            attempts += 1;
+            Ok(results.pop().unwrap())
+        },
+        |n| retries.push(n),
+    )
+    .unwrap();
+    assert_eq!(outcome, BuildOutcome::Succeeded);
+    assert_eq!(attempts, 3);
+    assert_eq!(retries, [1, 2]);
+
+    // Gives up after the configured retries
+    let mut attempts = 0;
+    let outcome = retry_initial_build(
+        1,
+        || {
+            attempts += 1;
+            Ok(BuildOutcome::Failed)
+        },
+        |_| {},
+    )
+    .unwrap();
+    assert_eq!(outcome, BuildOutcome::Failed);
+    assert_eq!(attempts, 2);
+
+    // Without retries the first build runs once
+    let mut attempts = 0;
+    retry_initial_build(
+        0,
+        || {
+            attempts += 1;
+            Ok(BuildOutcome::Failed)
+        },
+        |_| {},
+    )
+    .unwrap();
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn test_exit_restart_waits_out_cooldown_unless_a_file_changed() {
+    let ms = Duration::from_millis;
+    let exited = Instant::now();
+    let cooldown = ms(1000);
+
+    assert_eq!(
+        exit_restart(exited, exited + ms(200), cooldown, false),
+        ExitRestart::Wait(ms(800))
+    );
+    assert_eq!(
+        exit_restart(exited, exited + ms(200), cooldown, true),
+        ExitRestart::Now
+    );
+    assert_eq!(
+        exit_restart(exited, exited + ms(1000), cooldown, false),
+        ExitRestart::Now
+    );
+    assert_eq!(
+        exit_restart(exited, exited, Duration::ZERO, false),
+        ExitRestart::Now
+    );
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert!(!eff.restart_on_exit);
+    assert_eq!(eff.crash_cooldown, ms(1000));
+}
+
+#[test]
+fn test_restart_decision_matrix() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let stamp = |secs| ArtifactStamp {
+        modified: UNIX_EPOCH + Duration::from_secs(secs),
+        len: 1024,
+    };
+
+    // changed + compiled + different binary -> restart
+    assert_eq!(
+        restart_decision(true, Some(stamp(1)), Some(stamp(2))),
+        RunDecision::Restart
+    );
+    // changed + compiled + same binary -> keep the running process
+    assert_eq!(
+        restart_decision(true, Some(stamp(1)), Some(stamp(1))),
+        RunDecision::KeepUnchanged
+    );
+    // build failed -> keep the old process
+    assert_eq!(
+        restart_decision(false, Some(stamp(1)), Some(stamp(2))),
+        RunDecision::KeepFailed
+    );
+    // nothing running, or the binary can't be located -> restart
+    assert_eq!(
+        restart_decision(true, None, Some(stamp(1))),
+        RunDecision::Restart
+    );
+    assert_eq!(
+        restart_decision(true, Some(stamp(1)), None),
+        RunDecision::Restart
+    );
+}
+
+#[test]
+fn test_validate_lists_every_problem() {
+    let cfg = Config {
+        ignore: Some(vec!["**/target/**".into(), "a[b".into()]),
+        post_build: Some(vec![
+            vec!["rair-no-such-program".to_string()].into(),
+            vec!["echo".to_string(), "{exe}".to_string()].into(),
+        ]),
+        ..Default::default()
+    };
+    let problems = validate(&cfg);
+    let settings: Vec<&str> = problems.iter().map(|p| p.setting.as_str()).collect();
+    assert_eq!(settings, ["ignore", "post_build"]);
+    assert!(problems[0].message.contains("\"a[b\""));
+    assert_eq!(
+        problems[1].to_string(),
+        "post_build: program \"rair-no-such-program\" not found"
+    );
+
+    let cfg = Config {
+        mode: Some("bogus".into()),
+        on_fail_action: Some("keep".into()),
+        ..Default::default()
+    };
+    let problems = validate(&cfg);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].setting, "mode");
+    assert!(validate(&Config::default()).is_empty());
+}
+
+#[test]
+fn test_post_hooks_skip_noop_builds() {
+    // an unchanged binary is a no-op: hooks only with hooks_on_noop
+    assert!(!runs_post_hooks(RunDecision::KeepUnchanged, false));
+    assert!(runs_post_hooks(RunDecision::KeepUnchanged, true));
+    // a restart always runs them; a failed build never does
+    assert!(runs_post_hooks(RunDecision::Restart, false));
+    assert!(!runs_post_hooks(RunDecision::KeepFailed, true));
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert!(!eff.hooks_on_noop);
+    let cli = Config {
+        hooks_on_noop: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.hooks_on_noop);
+}
+
+#[test]
+fn test_on_fail_action_decision() {
+    assert_eq!(OnFailAction::parse("keep").unwrap(), OnFailAction::Keep);
+    assert_eq!(OnFailAction::parse(" Stop ").unwrap(), OnFailAction::Stop);
+    assert_eq!(
+        OnFailAction::parse("restart_last_good").unwrap(),
+        OnFailAction::RestartLastGood
+    );
+    assert!(OnFailAction::parse("kill").is_err());
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(eff.on_fail_action, OnFailAction::Keep);
+    let cfg = Config {
+        on_fail_action: Some("bogus".into()),
+        ..Config::default()
+    };
+    assert!(effective_config(cfg, None).is_err());
+
+    // keep never touches the running process
+    assert_eq!(
+        on_fail_decision(OnFailAction::Keep, true, true),
+        RunDecision::KeepFailed
+    );
+    // stop kills a running process; with nothing running there is nothing to do
+    assert_eq!(
+        on_fail_decision(OnFailAction::Stop, true, false),
+        RunDecision::StopFailed
+    );
+    assert_eq!(
+        on_fail_decision(OnFailAction::Stop, false, true),
+        RunDecision::KeepFailed
+    );
+    // restart_last_good needs a good build to fall back to, running or not
+    assert_eq!(
+        on_fail_decision(OnFailAction::RestartLastGood, false, true),
+        RunDecision::RestartLastGood
+    );
+    assert_eq!(
+        on_fail_decision(OnFailAction::RestartLastGood, true, false),
+        RunDecision::KeepFailed
+    );
+}
+
+#[test]
+fn test_run_stdin_defaults_to_null_when_interactive() {
+    assert_eq!(RunStdin::parse(" Pipe ").unwrap(), RunStdin::Pipe);
+    assert!(RunStdin::parse("tty").is_err());
+
+    let stdin = |run_stdin: Option<&str>, interactive: Option<bool>| {
+        let cfg = Config {
+            run_stdin: run_stdin.map(String::from),
+            interactive,
+            ..Default::default()
+        };
+        effective_config(cfg, None).map(|eff| eff.run_stdin)
+    };
+    assert_eq!(stdin(None, None).unwrap(), RunStdin::Inherit);
+    assert_eq!(stdin(None, Some(true)).unwrap(), RunStdin::Null);
+    assert_eq!(stdin(Some("pipe"), Some(true)).unwrap(), RunStdin::Pipe);
+    assert_eq!(stdin(Some("null"), None).unwrap(), RunStdin::Null);
+    // rair and the run process can't both read the terminal
+    let err = stdin(Some("inherit"), Some(true)).unwrap_err();
+    assert!(err.to_string().contains("interactive"), "{}", err);
+}
+
+#[test]
+fn test_content_hashes_skip_unchanged_saves() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("main.rs");
+    fs::write(&file, "fn main() {}").unwrap();
+
+    let mut hashes = ContentHashes::default();
+    assert!(hashes.changed(&file)); // first seen
+    assert!(!hashes.changed(&file)); // saved without edits
+    fs::write(&file, "fn main() { todo!() }").unwrap();
+    assert!(hashes.changed(&file));
+    fs::remove_file(&file).unwrap();
+    assert!(hashes.changed(&file));
+
+    let stamp = ArtifactStamp::of(&dir.path().join("main.rs"));
+    assert_eq!(stamp, None);
+}
+
+#[test]
+fn test_restart_steps_order() {
+    use RestartStep::*;
+
+    assert_eq!(restart_steps(false, false), vec![StartNew]);
+    assert_eq!(restart_steps(true, false), vec![StartNew]);
+    assert_eq!(restart_steps(false, true), vec![StopOld, StartNew]);
+    assert_eq!(
+        restart_steps(true, true),
+        vec![StartNew, AwaitReady, StopOld]
+    );
+}
+
+// ============================================================================
+// Build Command Generation Tests
+// ============================================================================
+
+#[test]
+fn test_build_command_basic() {
+    let cli = Config {
+        bin: Some("myapp".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert_eq!(eff.build[0], "cargo");
+    assert_eq!(eff.build[1], "build");
+    assert!(eff.build.contains(&"--bin".to_string()));
+    assert!(eff.build.contains(&"myapp".to_string()));
+}
+
+#[test]
+fn test_build_command_release() {
+    let cli = Config {
+        bin: Some("myapp".into()),
+        release: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.build.contains(&"--release".to_string()));
+}
+
+#[test]
+fn test_build_command_workspace() {
+    let cli = Config {
+        workspace: Some(true),
+        package: Some("backend".into()),
+        bin: Some("server".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.build.contains(&"--workspace".to_string()));
+    assert!(eff.build.contains(&"-p".to_string()));
+    assert!(eff.build.contains(&"backend".to_string()));
+    assert!(eff.build.contains(&"--bin".to_string()));
+    assert!(eff.build.contains(&"server".to_string()));
+}
+
+#[test]
+fn test_build_command_features() {
+    let cli = Config {
+        bin: Some("myapp".into()),
+        features: Some(vec!["feature1".into(), "feature2".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.build.contains(&"--features".to_string()));
+    assert!(eff.build.contains(&"feature1,feature2".to_string()));
+}
+
+#[test]
+fn test_build_command_all_features() {
+    let cli = Config {
+        bin: Some("myapp".into()),
+        all_features: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.build.contains(&"--all-features".to_string()));
+}
+
+#[test]
+fn test_build_command_no_default_features() {
+    let cli = Config {
+        bin: Some("myapp".into()),
+        no_default_features: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert!(eff.build.contains(&"--no-default-features".to_string()));
+}
+
+#[test]
+fn test_build_command_cargo_extra_args() {
+    let cli = Config {
+        bin: Some("myapp".into()),
+        features: Some(vec!["simd".into()]),
+        cargo_extra_args: Some(vec!["--offline".into(), "--locked".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert_eq!(
+        eff.build,
+        vec![
+            "cargo",
+            "build",
+            "--bin",
+            "myapp",
+            "--features",
+            "simd",
+            "--offline",
+            "--locked"
+        ]
+    );
+
+    // Not added to an explicit build
+    let cli = Config {
+        build: Some(vec!["make".into()]),
+        cargo_extra_args: Some(vec!["--offline".into()]),
+        ..Default::default()
+    };
+    assert_eq!(effective_config(cli, None).unwrap().build, vec!["make"]);
+}
+
+#[test]
+fn test_build_command_locked_frozen_offline() {
+    let with = |locked: bool, frozen: bool, offline: bool| Config {
+        bin: Some("myapp".into()),
+        features: Some(vec!["simd".into()]),
+        locked: locked.then_some(true),
+        frozen: frozen.then_some(true),
+        offline: offline.then_some(true),
+        cargo_extra_args: Some(vec!["--jobs=2".into()]),
+        ..Default::default()
+    };
+    let base = ["cargo", "build", "--bin", "myapp", "--features", "simd"];
+
+    // Each flag once, after the selection flags and before cargo_extra_args
+    for (cli, flag) in [
+        (with(true, false, false), "--locked"),
+        (with(false, true, false), "--frozen"),
+        (with(false, false, true), "--offline"),
+    ] {
+        let mut expected = base.to_vec();
+        expected.extend([flag, "--jobs=2"]);
+        assert_eq!(effective_config(cli, None).unwrap().build, expected);
+    }
+
+    let eff = effective_config(with(true, true, true), None).unwrap();
+    let mut expected = base.to_vec();
+    expected.extend(["--locked", "--frozen", "--offline", "--jobs=2"]);
+    assert_eq!(eff.build, expected);
+
+    // Also for the other derived cargo commands
+    let cli = Config {
+        mode: Some("test".into()),
+        clippy: Some(true),
+        ..with(false, false, true)
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.build[..2], ["cargo", "test"]);
+    assert_eq!(eff.build.iter().filter(|a| *a == "--offline").count(), 1);
+    let clippy = eff.checks()[0].argv(&eff);
+    assert_eq!(clippy.iter().filter(|a| *a == "--offline").count(), 1);
+
+    // Not added to an explicit build
+    let cli = Config {
+        build: Some(vec!["make".into()]),
+        ..with(true, true, true)
+    };
+    assert_eq!(effective_config(cli, None).unwrap().build, vec!["make"]);
+}
+
+#[test]
+fn test_build_command_explicit_overrides_cargo() {
+    let cli = Config {
+        build: Some(vec![
+            "rustc".into(),
+            "main.rs".into(),
+            "-o".into(),
+            "/tmp/app".into(),
+        ]),
+        bin: Some("ignored".into()), // Should be ignored
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert_eq!(eff.build[0], "rustc");
+    assert_eq!(eff.build[1], "main.rs");
+    assert!(!eff.build.contains(&"cargo".to_string()));
+}
+
+#[test]
+fn test_defer_run_skips_only_the_initial_run() {
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert!(eff.runs_after_build(true));
+    assert!(eff.runs_after_build(false));
+
+    let cli = Config {
+        defer_run: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(!eff.runs_after_build(true));
+    assert!(eff.runs_after_build(false));
+
+    // Without a run step there's nothing to defer
+    let cli = Config {
+        defer_run: Some(true),
+        mode: Some("test".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(!eff.runs_after_build(false));
+
+    let cli = Config {
+        defer_run: Some(true),
+        once: Some(true),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(matches!(err, RairError::InvalidConfig(_)), "{:?}", err);
+}
+
+#[test]
+fn test_warm_builds_before_watching() {
+    let warm = |cfg: Config| effective_config(cfg, None).unwrap().warms_before_watching();
+    assert!(!warm(Config::default()));
+    assert!(warm(Config {
+        warm: Some(true),
+        ..Default::default()
+    }));
+    // with defer_run: warm, then a startup build that doesn't run
+    let cli = Config {
+        warm: Some(true),
+        defer_run: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.warms_before_watching() && !eff.runs_after_build(true));
+    // once-mode never watches, and builds once regardless
+    assert!(!warm(Config {
+        warm: Some(true),
+        once: Some(true),
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn test_build_command_bench_mode() {
+    let cli = Config {
+        mode: Some("bench".into()),
+        bench: Some("parser".into()),
+        features: Some(vec!["simd".into()]),
+        release: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert_eq!(eff.mode, Mode::Bench);
+    assert!(!eff.mode.has_run_step());
+    assert_eq!(&eff.build[..2], &["cargo", "bench"]);
+    let pos = eff.build.iter().position(|a| a == "--bench").unwrap();
+    assert_eq!(eff.build[pos + 1], "parser");
+    assert!(eff.build.contains(&"simd".to_string()));
+    assert!(eff.build.contains(&"--release".to_string()));
+}
+
+#[test]
+fn test_build_command_bench_mode_without_target() {
+    let cli = Config {
+        mode: Some("bench".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert_eq!(eff.build, vec!["cargo", "bench"]);
+}
+
+#[test]
+fn test_build_command_test_mode() {
+    let cli = Config {
+        mode: Some("test".into()),
+        package: Some("core".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert_eq!(eff.mode, Mode::Test);
+    assert!(!eff.mode.has_run_step());
+    assert_eq!(eff.build, vec!["cargo", "test", "-p", "core"]);
+}
+
+#[test]
+fn test_build_command_nextest_runner() {
+    let cli = Config {
+        mode: Some("test".into()),
+        test_runner: Some("nextest".into()),
+        package: Some("core".into()),
+        release: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.test_runner, TestRunner::Nextest);
+    assert_eq!(
+        eff.build,
+        vec!["cargo", "nextest", "run", "--release", "-p", "core"]
+    );
+
+    // only test mode uses it
+    let cli = Config {
+        test_runner: Some("nextest".into()),
+        bin: Some("app".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.build[..2], ["cargo", "build"]);
+
+    let cli = Config {
+        test_runner: Some("cargo-nextest".into()),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(err.to_string().contains("test_runner"), "{}", err);
+}
+
+#[test]
+fn test_build_command_doc_mode() {
+    let cli = Config {
+        mode: Some("doc".into()),
+        features: Some(vec!["serde".into()]),
+        doc_args: Some(vec!["--document-private-items".into()]),
+        open_docs: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    assert_eq!(eff.mode, Mode::Doc);
+    assert!(!eff.has_run_step());
+    assert_eq!(
+        eff.build,
+        vec![
+            "cargo",
+            "doc",
+            "--features",
+            "serde",
+            "--no-deps",
+            "--document-private-items"
+        ]
+    );
+    assert_eq!(
+        &eff.open_docs_argv().unwrap()[..4],
+        &["cargo", "doc", "--open", "--features"]
+    );
+
+    // doc_deps documents dependencies too; without open_docs nothing is opened
+    let cli = Config {
+        mode: Some("doc".into()),
+        doc_deps: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.build, vec!["cargo", "doc"]);
+    assert_eq!(eff.open_docs_argv(), None);
+}
+
+#[test]
+fn test_without_cargo_requires_explicit_commands() {
+    let no_cargo = |build: Option<&str>, run: Option<&str>| Config {
+        cargo: Some(false),
+        build: build.map(|b| vec![b.into()]),
+        run: run.map(|r| vec![r.into()]),
+        ..Default::default()
+    };
+
+    let err = effective_config(no_cargo(Some("make"), None), None).unwrap_err();
+    assert!(err.to_string().contains("explicit `run`"), "{}", err);
+    let err = effective_config(no_cargo(None, Some("./app")), None).unwrap_err();
+    assert!(err.to_string().contains("explicit `build`"), "{}", err);
+
+    // The tests run next to a Cargo.toml, but without cargo the default watch is `.`
+    let eff = effective_config(no_cargo(Some("make"), Some("./app")), None).unwrap();
+    assert!(!eff.cargo);
+    assert_eq!(eff.watch, vec![PathBuf::from(".")]);
+    assert_eq!(eff.build, vec!["make"]);
+
+    // No run step, no run command needed
+    let cli = Config {
+        mode: Some("test".into()),
+        ..no_cargo(Some("make"), None)
+    };
+    assert!(effective_config(cli, None).is_ok());
+}
+
+#[test]
+fn test_clippy_check_blocks_build_on_warnings() {
+    let cli = Config {
+        package: Some("core".into()),
+        features: Some(vec!["simd".into()]),
+        clippy: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.checks(), vec![Check::Clippy]);
+    assert_eq!(
+        Check::Clippy.argv(&eff),
+        vec!["cargo", "clippy", "-p", "core", "--features", "simd"]
+    );
+
+    let mut output = OutputBuffer::default();
+    output.push("    Checking core v0.1.0", false);
+    output.push("warning: unused variable: `x`", false);
+    output.push(
+        r#"warning: `core` (lib) generated 2 warnings (run `cargo clippy --fix` to apply 1 suggestion)"#,
+        false,
+    );
+    output.push(r#"warning: `core` (bin "app") generated 1 warning"#, false);
+    assert_eq!(clippy_warning_count(&output), 3);
+    assert_eq!(
+        Check::Clippy.failure(true, &output).as_deref(),
+        Some("skipping build: clippy reported 3 warnings")
+    );
+    assert_eq!(
+        Check::Clippy.failure(false, &output).as_deref(),
+        Some("skipping build: clippy failed")
+    );
+    assert_eq!(Check::Clippy.failure(true, &OutputBuffer::default()), None);
+
+    // fmt_check runs first and only knows the package selection
+    let cli = Config {
+        workspace: Some(true),
+        fmt_check: Some(true),
+        clippy: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.checks(), vec![Check::Fmt, Check::Clippy]);
+    assert_eq!(
+        Check::Fmt.argv(&eff),
+        vec!["cargo", "fmt", "--check", "--all"]
+    );
+    assert!(Check::Fmt
+        .failure(false, &OutputBuffer::default())
+        .is_some());
+}
+
+#[test]
+fn test_unknown_mode_errors() {
+    let cli = Config {
+        mode: Some("sprint".into()),
+        ..Default::default()
+    };
+    assert!(effective_config(cli, None).is_err());
+}
+
+// ============================================================================
+// Environment Config Tests
+// ============================================================================
+
+fn env_vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_env_config_fields() {
+    let cfg = env_config(env_vars(&[
+        ("RAIR_DEBOUNCE_MS", "400"),
+        ("RAIR_CLEAR", "false"),
+        ("RAIR_BIN", "server"),
+        ("RAIR_WATCH", "src, tests,,"),
+        ("RAIR_RELEASE", "YES"),
+        ("RAIR_RUN", "./app --port 8080"),
+        ("RAIR_ACTIVE", "1"),
+        ("PATH", "/usr/bin"),
+    ]))
+    .unwrap();
+
+    assert_eq!(cfg.debounce_ms, Some(400));
+    assert_eq!(cfg.clear, Some(false));
+    assert_eq!(cfg.bin.as_deref(), Some("server"));
+    assert_eq!(cfg.watch, Some(vec!["src".into(), "tests".into()]));
+    assert_eq!(cfg.release, Some(true));
+    assert_eq!(
+        cfg.run,
+        Some(vec!["./app".into(), "--port".into(), "8080".into()])
+    );
+    assert!(cfg.package.is_none());
+}
+
+#[test]
+fn test_env_config_bad_values_error() {
+    assert!(env_config(env_vars(&[("RAIR_CLEAR", "maybe")])).is_err());
+    assert!(env_config(env_vars(&[("RAIR_DEBOUNCE_MS", "soon")])).is_err());
+}
+
+#[test]
+fn test_config_from_env() {
+    // Only this test touches these variables
+    std::env::set_var("RAIR_BENCH", "from_env");
+    std::env::set_var("RAIR_NO_DEFAULT_FEATURES", "1");
+    let cfg = Config::from_env().unwrap();
+    std::env::remove_var("RAIR_BENCH");
+    std::env::remove_var("RAIR_NO_DEFAULT_FEATURES");
+
+    assert_eq!(cfg.bench.as_deref(), Some("from_env"));
+    assert_eq!(cfg.no_default_features, Some(true));
+}
+
+#[test]
+fn test_env_layer_between_file_and_cli() {
+    let file = Config {
+        debounce_ms: Some(100),
+        bin: Some("from_file".into()),
+        clear: Some(true),
+        ..Default::default()
+    };
+    let env = env_config(env_vars(&[
+        ("RAIR_DEBOUNCE_MS", "200"),
+        ("RAIR_BIN", "from_env"),
+        ("RAIR_INCLUDE_EXT", "html"),
+    ]))
+    .unwrap();
+    let cli = Config {
+        bin: Some("from_cli".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, Some(merge_config(file, env))).unwrap();
+
+    assert_eq!(eff.debounce.as_millis(), 200); // env beats file
+    assert_eq!(eff.bin.as_deref(), Some("from_cli")); // cli beats env
+    assert!(eff.clear); // file value survives
+                        // only CLI extensions are additive; the env list replaces the default like any setting
+    assert!(!eff.include_ext.contains("rs"));
+    assert!(eff.include_ext.contains("html"));
+}
+
+// ============================================================================
+// Config File Loading Tests
+// ============================================================================
+
+#[test]
+fn test_load_config_from_file() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join(".rair.toml");
+
+    fs::write(
+        &config_path,
+        r#"
+watch = ["src", "tests"]
+ignore = ["**/target/**"]
+include_ext = ["rs"]
+debounce_ms = 100
+clear = false
+bin = "testapp"
+release = true
+
+pre_build = [
+  ["cargo", "fmt"]
+]
+"#,
+    )
+    .unwrap();
+
+    let cfg = load_config(&config_path).unwrap();
+
+    assert_eq!(cfg.watch.as_ref().unwrap().len(), 2);
+    assert_eq!(cfg.debounce_ms, Some(100));
+    assert_eq!(cfg.clear, Some(false));
+    assert_eq!(cfg.bin.as_deref(), Some("testapp"));
+    assert_eq!(cfg.release, Some(true));
+}
+
+#[test]
+fn test_services_resolve_with_their_own_watch_sets() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join(".rair.toml");
+    fs::write(
+        &config_path,
+        r#"
+[[services]]
+name = "frontend"
+watch = ["web/src"]
+include_ext = ["ts", ".TSX"]
+ignore = ["**/node_modules/**"]
+run = ["npm", "run", "dev"]
+
+[[services]]
+name = "docs"
+watch = ["docs", "book.toml"]
+build = ["mdbook", "build"]
+"#,
+    )
+    .unwrap();
+
+    let cfg = load_config(&config_path).unwrap();
+    let eff = effective_config(Config::default(), Some(cfg)).unwrap();
+    let [web, docs] = &eff.services[..] else {
+        panic!("expected two services, got {:?}", eff.services);
+    };
+
+    assert_eq!(web.name, "frontend");
+    assert_eq!(web.watch, vec![PathBuf::from("web/src")]);
+    assert_eq!(
+        docs.watch,
+        vec![PathBuf::from("docs"), PathBuf::from("book.toml")]
+    );
+    assert!(web.build.is_none());
+    assert_eq!(docs.run, None);
+
+    let root = eff.root.clone();
+    assert!(web.is_relevant(&root.join("web/src/App.tsx"), &eff));
+    assert!(!web.is_relevant(&root.join("web/src/App.css"), &eff));
+    assert!(!web.is_relevant(&root.join("web/src/node_modules/x/index.ts"), &eff));
+    // Without include_ext any file counts, but the top-level ignores still apply
+    assert!(docs.is_relevant(&root.join("docs/intro.md"), &eff));
+    assert!(!docs.is_relevant(&root.join("docs/target/index.html"), &eff));
+}
+
+#[test]
+fn test_services_validation() {
+    let service = |name: &str, watch: &[&str], run: Option<&str>| ServiceConfig {
+        name: name.into(),
+        watch: watch.iter().map(|w| w.to_string()).collect(),
+        run: run.map(|r| vec![r.into()]),
+        ..Default::default()
+    };
+    let resolve = |services| {
+        let cli = Config {
+            services: Some(services),
+            ..Default::default()
+        };
+        effective_config(cli, None)
+    };
+
+    assert!(resolve(vec![service("a", &["x"], Some("./a"))]).is_ok());
+    let err = resolve(vec![
+        service("a", &["x"], Some("./a")),
+        service("a", &["y"], Some("./b")),
+    ])
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("duplicate service name"),
+        "{}",
+        err
+    );
+    assert!(resolve(vec![service("a", &[], Some("./a"))]).is_err());
+    assert!(resolve(vec![service("a", &["x"], None)]).is_err());
+    assert!(resolve(vec![service("", &["x"], Some("./a"))]).is_err());
+}
+
+#[test]
+fn test_cli_debounce_overrides_service_debounce() {
+    let file = Config {
+        debounce_ms: Some(300),
+        services: Some(vec![
+            ServiceConfig {
+                name: "web".into(),
+                watch: vec!["web/src".into()],
+                run: Some(vec!["npm".into(), "run".into(), "dev".into()]),
+                include_ext: Some(vec!["ts".into()]),
+                debounce_ms: Some(2000),
+                ..Default::default()
+            },
+            ServiceConfig {
+                name: "docs".into(),
+                watch: vec!["docs".into()],
+                build: Some(vec!["mdbook".into(), "build".into()]),
+                ..Default::default()
+            },
+        ]),
+        ..Default::default()
+    };
+    let ms = Duration::from_millis;
+
+    // From the config alone: a service's own debounce, else the top-level one
+    let eff = effective_config(Config::default(), Some(file.clone())).unwrap();
+    assert_eq!(eff.services[0].debounce, ms(2000));
+    assert_eq!(eff.services[1].debounce, ms(300));
+
+    let cli = Config {
+        debounce_ms: Some(50),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, Some(file)).unwrap();
+    assert_eq!(eff.debounce, ms(50));
+    let web = &eff.services[0];
+    assert_eq!(web.debounce, ms(50));
+    assert_eq!(eff.services[1].debounce, ms(50));
+    // the rest of the service is untouched
+    assert_eq!(web.watch, [PathBuf::from("web/src")]);
+    assert!(web.include_ext.contains("ts"));
+    assert_eq!(web.run.as_deref().unwrap(), ["npm", "run", "dev"]);
+}
+
+#[test]
+fn test_load_config_extends_base_file() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("shared")).unwrap();
+    fs::write(
+        dir.path().join("shared/base.toml"),
+        "debounce_ms = 250\nclear = true\n",
+    )
+    .unwrap();
+    let child = dir.path().join(".rair.toml");
+    fs::write(&child, "extends = \"shared/base.toml\"\nclear = false\n").unwrap();
+
+    let cfg = load_config(&child).unwrap();
+    assert_eq!(cfg.debounce_ms, Some(250));
+    assert_eq!(cfg.clear, Some(false));
+    assert_eq!(cfg.extends, None);
+
+    // a chain that comes back round is an error, not a hang
+    fs::write(
+        dir.path().join("shared/base.toml"),
+        "extends = \"../.rair.toml\"\ndebounce_ms = 250\n",
+    )
+    .unwrap();
+    let err = load_config(&child).unwrap_err();
+    assert!(
+        err.to_string().starts_with("config extends itself"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_extends_child_include_ext_replaces_base() {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().join("base.toml");
+    let child = dir.path().join(".rair.toml");
+
+    // the base leaves the default list: the child's list replaces it
+    fs::write(&base, "debounce_ms = 250\n").unwrap();
+    fs::write(&child, "extends = \"base.toml\"\ninclude_ext = [\"go\"]\n").unwrap();
+    let eff = effective_config(Config::default(), Some(load_config(&child).unwrap())).unwrap();
+    assert_eq!(eff.include_ext, HashSet::from(["go".to_string()]));
+
+    // ...and the base's own list
+    fs::write(
+        &base,
+        "include_ext = [\"rs\", \"sql\"]\nexclude_ext = [\"bak\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        &child,
+        "extends = \"base.toml\"\ninclude_ext = [\"go\"]\nexclude_ext = [\"tmp\"]\n",
+    )
+    .unwrap();
+    let eff = effective_config(Config::default(), Some(load_config(&child).unwrap())).unwrap();
+    assert_eq!(eff.include_ext, HashSet::from(["go".to_string()]));
+    assert_eq!(eff.exclude_ext, HashSet::from(["tmp".to_string()]));
+}
+
+#[test]
+fn test_load_config_minimal() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join(".rair.toml");
+
+    fs::write(
+        &config_path,
+        r#"
+bin = "myapp"
+"#,
+    )
+    .unwrap();
+
+    let cfg = load_config(&config_path).unwrap();
+    assert_eq!(cfg.bin.as_deref(), Some("myapp"));
+    assert!(cfg.watch.is_none());
+    assert!(cfg.debounce_ms.is_none());
+}
+
+#[test]
+fn test_load_config_with_hooks() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join(".rair.toml");
+
+    fs::write(
+        &config_path,
+        r#"
+bin = "myapp"
+
+pre_build = [
+  ["cargo", "fmt"],
+  ["cargo", "clippy"]
+]
+
+post_build = [
+  ["cargo", "test", "-q"]
+]
+"#,
+    )
+    .unwrap();
+
+    let cfg = load_config(&config_path).unwrap();
+    assert_eq!(cfg.pre_build.as_ref().unwrap().len(), 2);
+    assert_eq!(cfg.post_build.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+fn test_load_config_nonexistent_errors() {
+    let result = load_config(&PathBuf::from("/nonexistent/path/.rair.toml"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_load_config_invalid_toml_errors() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join(".rair.toml");
+
+    fs::write(&config_path, "this is not valid toml {{").unwrap();
+
+    let result = load_config(&config_path);
+    assert!(result.is_err());
+}
+
+#[cfg(all(feature = "json", feature = "yaml"))]
+#[test]
+fn test_load_config_formats_agree() {
+    let dir = TempDir::new().unwrap();
+
+    let toml_path = dir.path().join(".rair.toml");
+    fs::write(
+        &toml_path,
+        r#"
+watch = ["src"]
+debounce_ms = 100
+clear = false
+bin = "myapp"
+pre_build = [["cargo", "fmt"]]
+"#,
+    )
+    .unwrap();
+
+    let json_path = dir.path().join("rair.json");
+    fs::write(
+        &json_path,
+        r#"{
+  "watch": ["src"],
+  "debounce_ms": 100,
+  "clear": false,
+  "bin": "myapp",
+  "pre_build": [["cargo", "fmt"]]
+}"#,
+    )
+    .unwrap();
+
+    let yaml_path = dir.path().join("rair.yaml");
+    fs::write(
+        &yaml_path,
+        r#"
+watch: [src]
+debounce_ms: 100
+clear: false
+bin: myapp
+pre_build:
+  - [cargo, fmt]
+"#,
+    )
+    .unwrap();
+
+    let from_toml = load_config(&toml_path).unwrap();
+    let from_json = load_config(&json_path).unwrap();
+    let from_yaml = load_config(&yaml_path).unwrap();
+
+    assert_eq!(from_toml.bin.as_deref(), Some("myapp"));
+    assert_eq!(from_toml, from_json);
+    assert_eq!(from_toml, from_yaml);
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn test_load_config_yml_extension() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join("rair.yml");
+    fs::write(&config_path, "bin: myapp\n").unwrap();
+
+    let cfg = load_config(&config_path).unwrap();
+    assert_eq!(cfg.bin.as_deref(), Some("myapp"));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_load_config_invalid_json_errors() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join("rair.json");
+    fs::write(&config_path, "{ not json").unwrap();
+
+    let err = load_config(&config_path).unwrap_err();
+    assert!(matches!(err, RairError::ConfigParse { .. }), "{:?}", err);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_print_config_and_list_targets_as_json() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"tool\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(root.join("src/bin")).unwrap();
+    fs::write(root.join("src/lib.rs"), "").unwrap();
+    fs::write(root.join("src/bin/cli.rs"), "fn main() {}\n").unwrap();
+    let manifest = root.join("Cargo.toml");
+
+    let targets = rair::list_targets(Some(&manifest)).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&serde_json::to_string(&targets).unwrap()).unwrap();
+    let entries: Vec<(&str, &str, &str)> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| {
+            let field = |k: &str| t[k].as_str().unwrap();
+            (field("package"), field("name"), field("kind"))
+        })
+        .collect();
+    assert_eq!(entries, [("tool", "cli", "bin"), ("tool", "tool", "lib")]);
+
+    let cli = Config {
+        manifest_path: Some(manifest.to_string_lossy().to_string()),
+        ignore: Some(vec!["**/*.tmp".into()]),
+        events: Some(vec!["modify".into(), "create".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&serde_json::to_string(&eff).unwrap()).unwrap();
+    assert!(json["ignore_globs"]
+        .as_array()
+        .unwrap()
+        .contains(&"**/*.tmp".into()));
+    assert!(json.get("ignore_set").is_none());
+    assert_eq!(json["events"], serde_json::json!(["create", "modify"]));
+    assert_eq!(json["mode"], "run");
+    assert_eq!(json["manifest_path"], manifest.to_string_lossy().as_ref());
+    assert!(json["build"].as_array().is_some_and(|b| !b.is_empty()));
+}
+
+#[test]
+fn test_bin_glob_expands_to_matching_targets() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"jobs\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(root.join("src/bin")).unwrap();
+    for bin in ["worker-mail", "worker-thumbs", "api"] {
+        fs::write(root.join(format!("src/bin/{}.rs", bin)), "fn main() {}\n").unwrap();
+    }
+    let manifest = root.join("Cargo.toml");
+
+    assert_eq!(
+        expand_bin_glob(Some(&manifest), "worker-*").unwrap(),
+        ["worker-mail", "worker-thumbs"]
+    );
+
+    let cfg = |bin: &str, run: Option<&str>| Config {
+        manifest_path: Some(manifest.to_string_lossy().to_string()),
+        bin: Some(bin.into()),
+        run: run.map(|r| vec![r.into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cfg("worker-*", Some("./supervisor")), None).unwrap();
+    assert_eq!(eff.bins, ["worker-mail", "worker-thumbs"]);
+    let bin_flags: Vec<&str> = eff
+        .build
+        .windows(2)
+        .filter(|w| w[0] == "--bin")
+        .map(|w| w[1].as_str())
+        .collect();
+    assert_eq!(bin_flags, ["worker-mail", "worker-thumbs"]);
+
+    // Several matches need `run` to say what to start; one match is just that binary
+    let err = effective_config(cfg("worker-*", None), None).unwrap_err();
+    assert!(err.to_string().contains("set `run`"), "{}", err);
+    let eff = effective_config(cfg("worker-m*", None), None).unwrap();
+    assert_eq!(eff.bins, ["worker-mail"]);
+    let target = CargoTarget {
+        target_dir: root.join("target"),
+        root_package: Some("jobs".into()),
+        package_bins: HashMap::new(),
+    };
+    assert_eq!(resolve_bin_name(&eff, &target).unwrap(), "worker-mail");
+
+    let err = effective_config(cfg("cron-*", None), None).unwrap_err();
+    assert!(err.to_string().contains("matches no binary"), "{}", err);
+}
+
+#[test]
+fn test_config_source_reloads_changed_config_file() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("rair.toml");
+    let src = dir.path().join("src");
+    let write = |body: &str| fs::write(&path, format!("watch = [{:?}]\n{}", src, body)).unwrap();
+    write("debounce_ms = 100\n");
+
+    let cli = Config {
+        verbose: Some(true),
+        ..Default::default()
+    };
+    let mut source = ConfigSource::new(cli, Some(&path), load_config(&path).unwrap());
+    assert!(source.is_config_file(&path));
+    assert!(!source.is_config_file(&dir.path().join("Cargo.toml")));
+    assert!(!ConfigSource::new(Config::default(), None, Config::default()).is_config_file(&path));
+
+    // Saved without changing a setting
+    write("debounce_ms = 100\n# comment\n");
+    assert!(source.reload().unwrap().is_none());
+
+    write("debounce_ms = 300\ninclude_ext = [\"sql\"]\n");
+    let eff = source.reload().unwrap().expect("config changed");
+    assert_eq!(eff.debounce, Duration::from_millis(300));
+    assert!(eff.include_ext.contains("sql"));
+    assert_eq!(eff.watch, vec![src.clone()]);
+    // CLI flags still apply on top
+    assert!(eff.verbose);
+
+    // A broken file is reported and the last good one stays current
+    write("debounce_ms = [\n");
+    assert!(source.reload().is_err());
+    write("debounce_ms = 300\ninclude_ext = [\"sql\"]\n");
+    assert!(source.reload().unwrap().is_none());
+}
+
+#[test]
+fn test_load_config_missing_file_error_kind() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join("absent.toml");
+
+    match load_config(&config_path).unwrap_err() {
+        RairError::ConfigRead { path, source } => {
+            assert_eq!(path, config_path);
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        other => panic!("expected ConfigRead, got {:?}", other),
+    }
+}
+
+// ============================================================================
+// Cargo Metadata Tests
+// ============================================================================
+
+#[test]
+fn test_metadata_smoke_temp_project_inputs() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+
+    fs::write(
+        root.join("Cargo.toml"),
+        r#"
+[package]
+name = "tmp_rair_meta"
+version = "0.1.0"
+edition = "2021"
+"#,
+    )
+    .unwrap();
+
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("src/main.rs"),
+        "fn main() { println!(\"ok\"); }\n",
+    )
+    .unwrap();
+
+    let cli = Config {
+        manifest_path: Some(root.join("Cargo.toml").to_string_lossy().to_string()),
+        bin: Some("tmp_rair_meta".into()),
+        release: Some(false),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.bin.as_deref(), Some("tmp_rair_meta"));
+    assert!(eff.manifest_path.is_some());
+}
+
+fn write_crate(root: &std::path::Path, name: &str) {
+    fs::write(
+        root.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+            name
+        ),
+    )
+    .unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+}
+
+#[test]
+fn test_manifest_rename_changes_run_exe() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    write_crate(root, "alpha");
+    let manifest = root.join("Cargo.toml");
+
+    let cli = Config {
+        manifest_path: Some(manifest.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+
+    let target = cargo_target(Some(&manifest)).unwrap();
+    assert_eq!(target.root_package.as_deref(), Some("alpha"));
+    let before = default_run_argv(&eff, &target).unwrap();
+    assert_eq!(
+        before[0],
+        exe_path(&target.target_dir, false, "alpha").to_string_lossy()
+    );
+
+    // Renaming the package is picked up once the metadata is refreshed
+    write_crate(root, "beta");
+    assert!(is_manifest_path(&manifest));
+    let target = cargo_target(Some(&manifest)).unwrap();
+    let after = default_run_argv(&eff, &target).unwrap();
+    assert_eq!(
+        after[0],
+        exe_path(&target.target_dir, false, "beta").to_string_lossy()
+    );
+    assert_ne!(before, after);
+}
+
+#[test]
+fn test_sole_bin_target_wins_over_package_name() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"my-service\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+         [[bin]]\nname = \"server\"\npath = \"src/main.rs\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+    let manifest = root.join("Cargo.toml");
+
+    let target = cargo_target(Some(&manifest)).unwrap();
+    assert_eq!(target.root_package.as_deref(), Some("my-service"));
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(resolve_bin_name(&eff, &target).unwrap(), "server");
+
+    // an explicit bin still wins
+    let cli = Config {
+        bin: Some("other".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(resolve_bin_name(&eff, &target).unwrap(), "other");
+}
 
-    // Even though toml and lock are excluded, Cargo.toml and Cargo.lock are always relevant
-    assert!(is_relevant_path(
-        PathBuf::from("Cargo.toml").as_path(),
-        &include,
-        &exclude
-    ));
-    assert!(is_relevant_path(
-        PathBuf::from("Cargo.lock").as_path(),
-        &include,
-        &exclude
-    ));
+#[test]
+fn test_resolve_bin_name_precedence() {
+    let target = CargoTarget {
+        target_dir: PathBuf::from("target"),
+        root_package: Some("root_pkg".into()),
+        package_bins: HashMap::new(),
+    };
 
-    // But other .toml files should be excluded
-    assert!(!is_relevant_path(
-        PathBuf::from("config.toml").as_path(),
-        &include,
-        &exclude
-    ));
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(resolve_bin_name(&eff, &target).unwrap(), "root_pkg");
+
+    let cli = Config {
+        package: Some("pkg".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(resolve_bin_name(&eff, &target).unwrap(), "pkg");
+
+    let cli = Config {
+        package: Some("pkg".into()),
+        bin: Some("bin".into()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(resolve_bin_name(&eff, &target).unwrap(), "bin");
 }
 
 #[test]
-fn test_no_extension_ignored() {
-    let include: HashSet<String> = ["rs".into()].into_iter().collect();
-    let exclude: HashSet<String> = HashSet::new();
+fn test_target_dir_override() {
+    let dir = TempDir::new().unwrap();
+    let custom = dir.path().join("build-out");
+    let cli = Config {
+        target_dir: Some(custom.to_string_lossy().to_string()),
+        bin: Some("app".into()),
+        release: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
 
-    assert!(!is_relevant_path(
-        PathBuf::from("README").as_path(),
-        &include,
-        &exclude
-    ));
-    assert!(!is_relevant_path(
-        PathBuf::from("Makefile").as_path(),
-        &include,
-        &exclude
-    ));
+    // Metadata's target dir is ignored in favour of the configured one
+    let target = CargoTarget {
+        target_dir: PathBuf::from("target"),
+        root_package: None,
+        package_bins: HashMap::new(),
+    };
+    let argv = default_run_argv(&eff, &target).unwrap();
+    assert_eq!(
+        PathBuf::from(&argv[0]),
+        custom.join("release").join(exe_name("app"))
+    );
+
+    let build = custom.to_string_lossy().to_string();
+    assert!(eff
+        .build
+        .windows(2)
+        .any(|w| w[0] == "--target-dir" && w[1] == build));
+
+    // Build output there never triggers a rebuild
+    assert!(eff.is_ignored(&custom.join("release/app")));
 }
 
-// ============================================================================
-// Executable Path Tests
-// ============================================================================
+#[test]
+fn test_target_dir_must_be_a_directory() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("file");
+    fs::write(&file, "").unwrap();
+    let cli = Config {
+        target_dir: Some(file.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    assert!(effective_config(cli, None).is_err());
+}
 
 #[test]
-fn test_exe_name_and_path() {
-    let name = exe_name("mybin");
-    #[cfg(windows)]
-    assert_eq!(name, "mybin.exe");
-    #[cfg(not(windows))]
-    assert_eq!(name, "mybin");
+fn test_incremental_files_recompile_selection() {
+    let files: Vec<PathBuf> = ["main.rs", "util.rs", "net.rs"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    let recompile = |changed: &[&str]| {
+        files_to_recompile(&files, |f| changed.iter().any(|c| f == Path::new(c)))
+    };
 
-    let td = PathBuf::from("target");
-    let p1 = exe_path(&td, false, "mybin");
-    let p2 = exe_path(&td, true, "mybin");
-    assert!(p1.to_string_lossy().contains("debug"));
-    assert!(p2.to_string_lossy().contains("release"));
+    assert!(recompile(&[]).is_empty());
+    // Only the root changed: no library is rebuilt
+    assert_eq!(recompile(&["main.rs"]), [PathBuf::from("main.rs")]);
+    // The last library changed: it and the root
+    assert_eq!(
+        recompile(&["net.rs"]),
+        [PathBuf::from("net.rs"), PathBuf::from("main.rs")]
+    );
+    // An earlier library may be used by the later ones, which are rebuilt too
+    assert_eq!(
+        recompile(&["util.rs"]),
+        [
+            PathBuf::from("util.rs"),
+            PathBuf::from("net.rs"),
+            PathBuf::from("main.rs")
+        ]
+    );
 }
 
 #[test]
-fn test_exe_path_different_bins() {
-    let td = PathBuf::from("target");
-    let p1 = exe_path(&td, false, "server");
-    let p2 = exe_path(&td, false, "client");
+fn test_incremental_files_hashes_and_externs() {
+    let dir = TempDir::new().unwrap();
+    let main = dir.path().join("main.rs");
+    let util = dir.path().join("my-util.rs");
+    fs::write(&main, "fn main() {}\n").unwrap();
+    fs::write(&util, "pub fn f() {}\n").unwrap();
+    let build = IncrementalFiles::new(vec![main.clone(), util.clone()], dir.path().join("out"));
+    fs::create_dir_all(&build.cache_dir).unwrap();
 
-    assert!(p1.to_string_lossy().contains("server"));
-    assert!(p2.to_string_lossy().contains("client"));
-    assert_ne!(p1, p2);
+    let argv = build.rustc_argv(&util);
+    assert!(
+        argv.contains(&"--crate-name=my_util".to_string()),
+        "{:?}",
+        argv
+    );
+    assert!(!argv.contains(&"--extern".to_string()), "{:?}", argv);
+    let argv = build.rustc_argv(&main);
+    let rlib = build.cache_dir.join("libmy_util.rlib");
+    assert!(
+        argv.contains(&format!("my_util={}", rlib.display())),
+        "{:?}",
+        argv
+    );
+
+    // Stale until compiled (output present) and recorded; stale again once edited
+    assert!(build.is_stale(&util));
+    fs::write(&rlib, "").unwrap();
+    build.record(&util).unwrap();
+    assert!(!build.is_stale(&util));
+    fs::write(&util, "pub fn f() { }\n").unwrap();
+    assert!(build.is_stale(&util));
 }
 
-// ============================================================================
-// Hook Execution Tests
-// ============================================================================
+#[cfg(not(windows))]
+#[test]
+fn test_incremental_files_follow_dependencies() {
+    let dir = TempDir::new().unwrap();
+    let main = dir.path().join("main.rs");
+    let util = dir.path().join("util.rs");
+    let inner = dir.path().join("inner.rs");
+    fs::write(&main, "fn main() { print!(\"{}\", util::answer()); }\n").unwrap();
+    fs::write(
+        &util,
+        "mod inner;\npub fn answer() -> u32 { inner::VALUE }\n",
+    )
+    .unwrap();
+    // reached only through util.rs's `mod inner;`, so not listed
+    fs::write(&inner, "pub const VALUE: u32 = 1;\n").unwrap();
 
-fn ok_cmd() -> Vec<String> {
-    #[cfg(windows)]
-    {
-        vec!["cmd".into(), "/C".into(), "exit".into(), "0".into()]
-    }
-    #[cfg(not(windows))]
-    {
-        vec!["sh".into(), "-c".into(), "true".into()]
-    }
-}
+    let out = dir.path().join("out");
+    let build = IncrementalFiles::new(vec![main.clone(), util.clone()], out.clone());
+    let compile = || {
+        let mut compiled = Vec::new();
+        let ok = build
+            .compile(|argv| {
+                compiled.push(PathBuf::from(&argv[1]));
+                let status = std::process::Command::new(&argv[0])
+                    .args(&argv[1..])
+                    .status()?;
+                Ok(status.success())
+            })
+            .unwrap();
+        assert!(ok);
+        compiled
+    };
+    let run = || {
+        let output = std::process::Command::new(&out).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
 
-fn fail_cmd() -> Vec<String> {
-    #[cfg(windows)]
-    {
-        vec!["cmd".into(), "/C".into(), "exit".into(), "1".into()]
-    }
-    #[cfg(not(windows))]
-    {
-        vec!["sh".into(), "-c".into(), "false".into()]
-    }
+    assert_eq!(compile(), [util.clone(), main.clone()]);
+    assert_eq!(run(), "1");
+    assert!(compile().is_empty());
+
+    // a change in a library's `mod` file rebuilds the library and the binary using it
+    fs::write(&inner, "pub const VALUE: u32 = 2;\n").unwrap();
+    assert_eq!(compile(), [util.clone(), main.clone()]);
+    assert_eq!(run(), "2");
+
+    // the binary alone changed: the library is kept
+    fs::write(&main, "fn main() { print!(\"{}!\", util::answer()); }\n").unwrap();
+    assert_eq!(compile(), [main]);
+    assert_eq!(run(), "2!");
 }
 
 #[test]
-fn test_hooks_stop_on_failure() {
-    let hooks = vec![ok_cmd(), fail_cmd(), ok_cmd()];
-    let ok = run_hook_list("test", &hooks).unwrap();
-    assert!(!ok);
+fn test_dep_info_inputs() {
+    let dep_info = "/tmp/out.d/libutil.rlib: src/util.rs src/util/inner.rs my\\ dir/x.rs\n\nsrc/util.rs:\nsrc/util/inner.rs:\n";
+    assert_eq!(
+        dep_info_inputs(dep_info),
+        [
+            PathBuf::from("src/util.rs"),
+            PathBuf::from("src/util/inner.rs"),
+            PathBuf::from("my dir/x.rs"),
+        ]
+    );
+    assert!(dep_info_inputs("").is_empty());
 }
 
 #[test]
-fn test_hooks_all_ok() {
-    let hooks = vec![ok_cmd(), ok_cmd()];
-    let ok = run_hook_list("test", &hooks).unwrap();
-    assert!(ok);
+fn test_complete_target_names() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[package]\nname = \"tool\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(root.join("src/bin")).unwrap();
+    fs::create_dir_all(root.join("examples")).unwrap();
+    for path in ["src/bin/b.rs", "src/bin/a.rs", "examples/demo.rs"] {
+        fs::write(root.join(path), "fn main() {}\n").unwrap();
+    }
+
+    let manifest = root.join("Cargo.toml");
+    let bins = target_names(Some(&manifest), "bin").unwrap();
+    assert_eq!(bins.join("\n"), "a\nb");
+    assert_eq!(target_names(Some(&manifest), "example").unwrap(), ["demo"]);
 }
 
 #[test]
-fn test_hooks_empty() {
-    let hooks: Vec<Vec<String>> = vec![];
-    let ok = run_hook_list("test", &hooks).unwrap();
-    assert!(ok); // Empty hooks should succeed
+fn test_watch_workspace_members() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"a\", \"b\"]\nresolver = \"2\"\n",
+    )
+    .unwrap();
+    for name in ["a", "b"] {
+        fs::create_dir_all(root.join(name)).unwrap();
+        write_crate(&root.join(name), name);
+    }
+
+    let members = workspace_watch_paths(Some(&root.join("Cargo.toml"))).unwrap();
+
+    // Explicitly configured paths aren't duplicated
+    let mut watch = vec![root.join("a/src")];
+    add_watch_paths(&mut watch, members);
+
+    assert!(watch.contains(&root.join("a/src")));
+    assert!(watch.contains(&root.join("b/src")));
+    assert!(watch.contains(&root.join("a/Cargo.toml")));
+    assert!(watch.contains(&root.join("b/Cargo.toml")));
+    assert_eq!(watch.iter().filter(|p| p.ends_with("a/src")).count(), 1);
 }
 
 #[test]
-fn test_hooks_single_command() {
-    let hooks = vec![ok_cmd()];
-    let ok = run_hook_list("test", &hooks).unwrap();
-    assert!(ok);
+fn test_watcher_restarts_give_up_after_limit() {
+    let mut restarts = WatcherRestarts::new(2);
+    assert_eq!(restarts.attempt(), Some(1));
+    assert_eq!(restarts.attempt(), Some(2));
+    assert_eq!(restarts.attempt(), None);
+    assert_eq!(restarts.used(), 2);
+
+    // a watcher that delivers events again starts a fresh count
+    restarts.recovered();
+    assert_eq!(restarts.attempt(), Some(1));
+
+    // 0 never recreates
+    assert_eq!(WatcherRestarts::new(0).attempt(), None);
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(eff.watcher_restarts, 3);
 }
 
 #[test]
-fn test_hook_empty_argv_errors() {
-    let hooks = vec![vec![]]; // Empty command
-    let result = run_hook_list("test", &hooks);
-    assert!(result.is_err());
+fn test_build_script_inputs_become_watched() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    let app = root.join("my app");
+    let build_dir = root.join("target/debug/build/app-0123abcd");
+    fs::create_dir_all(build_dir.join("out")).unwrap();
+    fs::write(
+        build_dir.join("output"),
+        "cargo:rerun-if-changed=proto/api.proto\ncargo:rustc-env=X=1\ncargo::rerun-if-changed=schema\n",
+    )
+    .unwrap();
+
+    let msg = format!(
+        r#"{{"reason":"build-script-executed","package_id":"path+file://{}#app@0.1.0","linked_libs":[],"linked_paths":[],"cfgs":[],"env":[],"out_dir":"{}"}}"#,
+        app.to_string_lossy().replace(' ', "%20"),
+        build_dir.join("out").to_string_lossy()
+    );
+    let mut output = OutputBuffer::default();
+    output.push(&msg, true);
+    output.push(r#"{"reason":"build-finished","success":true}"#, true);
+    output.push("warning: unused variable", false);
+    output.push("plain stdout", true);
+
+    let mut inputs = BuildScriptInputs::default();
+    inputs.extend(take_cargo_messages(&mut output).build_script_inputs);
+
+    // Only cargo's JSON messages are taken out of the output
+    let texts: Vec<_> = output.into_lines().into_iter().map(|l| l.text).collect();
+    assert_eq!(texts, ["warning: unused variable", "plain stdout"]);
+
+    assert!(inputs.contains(&app.join("proto/api.proto")));
+    assert!(inputs.contains(&app.join("schema/v1.json")));
+    assert!(!inputs.contains(&app.join("src/main.rs")));
+    assert_eq!(
+        inputs.take_new(),
+        [app.join("proto/api.proto"), app.join("schema")]
+    );
+
+    // Paths reported again by the next build are already watched
+    inputs.extend([app.join("schema")]);
+    assert!(inputs.take_new().is_empty());
 }
 
-// ============================================================================
-// Build Command Generation Tests
-// ============================================================================
+#[test]
+fn test_artifacts_from_cargo_messages() {
+    let artifact = |package_id: &str, kind: &str, filenames: &[&str], exe: Option<&str>| {
+        format!(
+            r#"{{"reason":"compiler-artifact","package_id":"{}","manifest_path":"/src/app/Cargo.toml","target":{{"kind":["{}"],"crate_types":["{}"],"name":"app","src_path":"/src/app/src/main.rs","edition":"2021","doctest":false,"test":true}},"profile":{{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false}},"features":[],"filenames":{:?},"executable":{},"fresh":false}}"#,
+            package_id,
+            kind,
+            kind,
+            filenames,
+            exe.map_or("null".to_string(), |e| format!("{:?}", e))
+        )
+    };
+    let mut output = OutputBuffer::default();
+    output.push(
+        &artifact(
+            "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0",
+            "lib",
+            &["/src/app/target/debug/deps/libserde-1.rlib"],
+            None,
+        ),
+        true,
+    );
+    output.push(
+        &artifact(
+            "path+file:///src/app#0.1.0",
+            "custom-build",
+            &["/src/app/target/debug/build/app-1/build-script-build"],
+            Some("/src/app/target/debug/build/app-1/build-script-build"),
+        ),
+        true,
+    );
+    output.push(
+        &artifact(
+            "path+file:///src/app#0.1.0",
+            "lib",
+            &[
+                "/src/app/target/debug/libapp.rlib",
+                "/src/app/target/debug/deps/libapp-2.rmeta",
+            ],
+            None,
+        ),
+        true,
+    );
+    output.push(
+        &artifact(
+            "path+file:///src/app#0.1.0",
+            "bin",
+            &["/src/app/target/debug/app"],
+            Some("/src/app/target/debug/app"),
+        ),
+        true,
+    );
+    output.push("   Compiling app v0.1.0 (/src/app)", false);
+
+    let messages = take_cargo_messages(&mut output);
+    assert_eq!(
+        messages.artifacts,
+        [
+            PathBuf::from("/src/app/target/debug/libapp.rlib"),
+            PathBuf::from("/src/app/target/debug/app")
+        ]
+    );
+    assert!(messages.build_script_inputs.is_empty());
+    assert_eq!(output.len(), 1);
+
+    let cli = Config {
+        list_artifacts: Some(true),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.reads_cargo_messages());
+    assert_eq!(
+        eff.build.last().unwrap(),
+        "--message-format=json-render-diagnostics"
+    );
+}
 
 #[test]
-fn test_build_command_basic() {
+fn test_parse_diagnostics_counts_warnings() {
+    let diagnostic = |level: &str, text: &str| {
+        format!(
+            r#"{{"reason":"compiler-message","package_id":"path+file:///src/app#0.1.0","manifest_path":"/src/app/Cargo.toml","target":{{"kind":["bin"],"crate_types":["bin"],"name":"app","src_path":"/src/app/src/main.rs","edition":"2021","doctest":false,"test":true}},"message":{{"message":"{}","code":null,"level":"{}","spans":[],"children":[],"rendered":"{}: {}\n  --> src/main.rs:1:5\n"}}}}"#,
+            text, level, level, text
+        )
+    };
+    let warning = |text: &str| diagnostic("warning", text);
+    let mut output = OutputBuffer::default();
+    output.push(&warning("unused variable: `x`"), true);
+    output.push(&warning("function `f` is never used"), true);
+    output.push(r#"{"reason":"build-finished","success":true}"#, true);
+    output.push("plain stdout", true);
+
+    let messages = take_cargo_messages(&mut output);
+    assert_eq!(messages.warnings, 2);
+    assert_eq!(messages.errors, 0);
+    assert_eq!(format_build_ok(messages.warnings), "build ok (2 warnings)");
+    assert_eq!(format_build_ok(1), "build ok (1 warning)");
+    // the JSON gives way to the rendered diagnostics
+    let lines = output.into_lines();
+    assert_eq!(lines[0].text, "warning: unused variable: `x`");
+    assert!(!lines[0].stdout);
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[4].text, "plain stdout");
+
     let cli = Config {
-        bin: Some("myapp".into()),
+        parse_diagnostics: Some(true),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
+    assert!(eff.reads_cargo_messages());
+    assert!(eff.counts_warnings());
+    assert_eq!(eff.build.last().unwrap(), "--message-format=json");
 
-    assert_eq!(eff.build[0], "cargo");
-    assert_eq!(eff.build[1], "build");
-    assert!(eff.build.contains(&"--bin".to_string()));
-    assert!(eff.build.contains(&"myapp".to_string()));
+    // an explicit build isn't cargo's JSON: nothing to count
+    let cli = Config {
+        parse_diagnostics: Some(true),
+        build: Some(vec!["make".into()]),
+        ..Default::default()
+    };
+    assert!(!effective_config(cli, None).unwrap().counts_warnings());
+
+    // errors are counted too, without rustc's closing summary
+    let mut output = OutputBuffer::default();
+    output.push(&diagnostic("error", "mismatched types"), true);
+    output.push(
+        &diagnostic("error", "cannot find value `y` in this scope"),
+        true,
+    );
+    output.push(&warning("unused variable: `x`"), true);
+    output.push(
+        &diagnostic("error", "aborting due to 2 previous errors"),
+        true,
+    );
+    output.push(r#"{"reason":"build-finished","success":false}"#, true);
+    let messages = take_cargo_messages(&mut output);
+    assert_eq!(messages.errors, 2);
+    assert_eq!(messages.warnings, 1);
 }
 
 #[test]
-fn test_build_command_release() {
+fn test_watch_build_script_inputs_requests_json_messages() {
     let cli = Config {
-        bin: Some("myapp".into()),
-        release: Some(true),
+        watch_build_script_inputs: Some(true),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
+    assert_eq!(
+        eff.build.last().unwrap(),
+        "--message-format=json-render-diagnostics"
+    );
 
-    assert!(eff.build.contains(&"--release".to_string()));
+    // An explicit build command is left alone
+    let cli = Config {
+        watch_build_script_inputs: Some(true),
+        build: Some(vec!["make".into()]),
+        ..Default::default()
+    };
+    assert_eq!(effective_config(cli, None).unwrap().build, ["make"]);
 }
 
+// ============================================================================
+// Run Command Tests
+// ============================================================================
+
 #[test]
-fn test_build_command_workspace() {
+fn test_explicit_run_command() {
     let cli = Config {
-        workspace: Some(true),
-        package: Some("backend".into()),
-        bin: Some("server".into()),
+        run: Some(vec!["/tmp/myapp".into(), "--arg".into()]),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
 
-    assert!(eff.build.contains(&"--workspace".to_string()));
-    assert!(eff.build.contains(&"-p".to_string()));
-    assert!(eff.build.contains(&"backend".to_string()));
-    assert!(eff.build.contains(&"--bin".to_string()));
-    assert!(eff.build.contains(&"server".to_string()));
+    assert_eq!(eff.run.as_ref().unwrap()[0], "/tmp/myapp");
+    assert_eq!(eff.run.as_ref().unwrap()[1], "--arg");
 }
 
+#[cfg(not(windows))]
 #[test]
-fn test_build_command_features() {
+fn test_run_cwd_applied_to_run_command() {
+    let dir = TempDir::new().unwrap();
     let cli = Config {
-        bin: Some("myapp".into()),
-        features: Some(vec!["feature1".into(), "feature2".into()]),
+        run_cwd: Some(dir.path().to_string_lossy().to_string()),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
 
-    assert!(eff.build.contains(&"--features".to_string()));
-    assert!(eff.build.contains(&"feature1,feature2".to_string()));
+    let argv = vec!["sh".into(), "-c".into(), "pwd -P".into()];
+    let out = run_command(&argv, &eff).unwrap().output().unwrap();
+    let printed = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(
+        PathBuf::from(printed.trim()),
+        dir.path().canonicalize().unwrap()
+    );
 }
 
 #[test]
-fn test_build_command_all_features() {
+fn test_backtrace_sets_rust_backtrace_for_run() {
+    use std::ffi::OsStr;
+
+    let argv = vec!["./app".to_string()];
+    let backtrace = |eff: &rair::EffectiveConfig| {
+        run_command(&argv, eff)
+            .unwrap()
+            .get_envs()
+            .find(|(k, _)| *k == "RUST_BACKTRACE")
+            .map(|(_, v)| v.map(OsStr::to_os_string))
+    };
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(backtrace(&eff), None); // inherited
+
     let cli = Config {
-        bin: Some("myapp".into()),
-        all_features: Some(true),
+        backtrace: Some("full".into()),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
+    assert_eq!(backtrace(&eff), Some(Some("full".into())));
 
-    assert!(eff.build.contains(&"--all-features".to_string()));
+    let cli = Config {
+        backtrace: Some("yes".into()),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(matches!(err, RairError::InvalidConfig(_)), "{:?}", err);
 }
 
 #[test]
-fn test_build_command_no_default_features() {
+fn test_env_file_reaches_run_command() {
+    let dir = TempDir::new().unwrap();
+    let env = dir.path().join(".env");
+    fs::write(
+        &env,
+        "# local settings\nGREETING=\"hello from env\"\nRUST_BACKTRACE=0\n",
+    )
+    .unwrap();
     let cli = Config {
-        bin: Some("myapp".into()),
-        no_default_features: Some(true),
+        env_file: Some(env.to_string_lossy().into()),
+        backtrace: Some("1".into()),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
+    assert!(eff.is_env_file(&env));
+    assert!(eff.watch.contains(&env));
 
-    assert!(eff.build.contains(&"--no-default-features".to_string()));
+    let argv: Vec<String> = ["sh", "-c", "echo \"$GREETING/$RUST_BACKTRACE\""]
+        .map(String::from)
+        .to_vec();
+    let echo = |eff: &rair::EffectiveConfig| {
+        let out = run_command(&argv, eff).unwrap().output().unwrap();
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+    // `backtrace` wins over the file
+    assert_eq!(echo(&eff), "hello from env/1\n");
+    // read again for the next start
+    fs::write(&env, "GREETING=edited\n").unwrap();
+    assert_eq!(echo(&eff), "edited/1\n");
+
+    let off = Config {
+        env_file: Some(String::new()),
+        ..Default::default()
+    };
+    assert_eq!(effective_config(off, None).unwrap().env_file, None);
+    let missing = Config {
+        env_file: Some(dir.path().join("nope.env").to_string_lossy().into()),
+        ..Default::default()
+    };
+    assert!(effective_config(missing, None).is_err());
 }
 
 #[test]
-fn test_build_command_explicit_overrides_cargo() {
+fn test_transform_runs_for_matching_inputs() {
+    let dir = TempDir::new().unwrap();
+    let archive = dir.path().join("gen/sources.tar");
+    let extracted = dir.path().join("src/generated.rs");
+    fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    fs::create_dir_all(extracted.parent().unwrap()).unwrap();
+    fs::write(&archive, "").unwrap();
+
+    let extract = format!("echo \"// from $RAIR_TRANSFORM_PATH\" > {:?}", extracted);
     let cli = Config {
-        build: Some(vec![
-            "rustc".into(),
-            "main.rs".into(),
-            "-o".into(),
-            "/tmp/app".into(),
+        transform: Some(vec![
+            TransformConfig {
+                glob: "**/gen/*.tar".into(),
+                run: vec!["sh".into(), "-c".into(), extract],
+            },
+            TransformConfig {
+                glob: "**/*.proto".into(),
+                run: vec!["false".into()],
+            },
         ]),
-        bin: Some("ignored".into()), // Should be ignored
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
+    assert!(!eff.is_relevant(&archive));
 
-    assert_eq!(eff.build[0], "rustc");
-    assert_eq!(eff.build[1], "main.rs");
-    assert!(!eff.build.contains(&"cargo".to_string()));
-}
+    // Other changes run nothing
+    let ran = eff.run_transforms([dir.path().join("src/main.rs").as_path()]);
+    assert!(ran.unwrap().is_empty());
+    assert!(!extracted.exists());
 
-// ============================================================================
-// Config File Loading Tests
-// ============================================================================
+    // The archive changed: the transform writes the sources that the filters then pick up
+    let lib = dir.path().join("src/lib.rs");
+    let ran = eff
+        .run_transforms([lib.as_path(), archive.as_path()])
+        .unwrap();
+    assert_eq!(ran, [("**/gen/*.tar", true)]);
+    let written = fs::read_to_string(&extracted).unwrap();
+    assert_eq!(written.trim(), format!("// from {}", archive.display()));
+    assert!(eff.is_relevant(&extracted));
+
+    let ran = eff.run_transforms([Path::new("api/v1.proto")]).unwrap();
+    assert_eq!(ran, [("**/*.proto", false)]);
+
+    let cli = Config {
+        transform: Some(vec![TransformConfig {
+            glob: "*.tar".into(),
+            run: vec![],
+        }]),
+        ..Default::default()
+    };
+    assert!(matches!(
+        effective_config(cli, None).unwrap_err(),
+        RairError::InvalidConfig(_)
+    ));
+}
 
 #[test]
-fn test_load_config_from_file() {
+fn test_asset_sync_copies_changed_files() {
     let dir = TempDir::new().unwrap();
-    let config_path = dir.path().join(".rair.toml");
-
-    fs::write(
-        &config_path,
-        r#"
-watch = ["src", "tests"]
-ignore = ["**/target/**"]
-include_ext = ["rs"]
-debounce_ms = 100
-clear = false
-bin = "testapp"
-release = true
+    let root = dir.path();
+    fs::create_dir_all(root.join("static/css")).unwrap();
+    fs::write(root.join("static/css/site.css"), "body {}").unwrap();
 
-pre_build = [
-  ["cargo", "fmt"]
-]
-"#,
-    )
-    .unwrap();
+    let rule = AssetSync::new("static/**/*.css", "public").unwrap();
+    assert_eq!(
+        rule.target(Path::new("static/css/site.css")),
+        Some(PathBuf::from("public/css/site.css"))
+    );
+    assert_eq!(rule.target(Path::new("static/app.js")), None);
+    // Copies don't feed back into the rule
+    let rule_in_tree = AssetSync::new("**/*.css", "public").unwrap();
+    assert_eq!(rule_in_tree.target(Path::new("public/css/site.css")), None);
 
-    let cfg = load_config(&config_path).unwrap();
+    let dest = rule
+        .sync(root, &root.join("static/css/site.css"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(dest, root.join("public/css/site.css"));
+    assert_eq!(fs::read_to_string(&dest).unwrap(), "body {}");
 
-    assert_eq!(cfg.watch.as_ref().unwrap().len(), 2);
-    assert_eq!(cfg.debounce_ms, Some(100));
-    assert_eq!(cfg.clear, Some(false));
-    assert_eq!(cfg.bin.as_deref(), Some("testapp"));
-    assert_eq!(cfg.release, Some(true));
+    // Modified: copied again
+    fs::write(root.join("static/css/site.css"), "body { margin: 0 }").unwrap();
+    rule.sync(root, &root.join("static/css/site.css")).unwrap();
+    assert_eq!(fs::read_to_string(&dest).unwrap(), "body { margin: 0 }");
 }
 
 #[test]
-fn test_load_config_minimal() {
+fn test_asset_sync_removes_deleted_files() {
     let dir = TempDir::new().unwrap();
-    let config_path = dir.path().join(".rair.toml");
+    let root = dir.path();
+    fs::create_dir_all(root.join("static")).unwrap();
+    fs::write(root.join("static/logo.svg"), "<svg/>").unwrap();
 
-    fs::write(
-        &config_path,
-        r#"
-bin = "myapp"
-"#,
-    )
-    .unwrap();
+    let rule = AssetSync::new("static/*", "public").unwrap();
+    let src = root.join("static/logo.svg");
+    let dest = rule.sync(root, &src).unwrap().unwrap();
+    assert!(dest.is_file());
 
-    let cfg = load_config(&config_path).unwrap();
-    assert_eq!(cfg.bin.as_deref(), Some("myapp"));
-    assert!(cfg.watch.is_none());
-    assert!(cfg.debounce_ms.is_none());
+    fs::remove_file(&src).unwrap();
+    assert_eq!(rule.sync(root, &src).unwrap(), Some(dest.clone()));
+    assert!(!dest.exists());
+    // Already gone is fine
+    assert_eq!(rule.sync(root, &src).unwrap(), Some(dest));
+    assert_eq!(rule.sync(root, &root.join("src/main.rs")).unwrap(), None);
 }
 
 #[test]
-fn test_load_config_with_hooks() {
-    let dir = TempDir::new().unwrap();
-    let config_path = dir.path().join(".rair.toml");
+fn test_once_build_only_skips_the_run_step() {
+    let build_only = |build: &str| Config {
+        cargo: Some(false),
+        once: Some(true),
+        build_only: Some(true),
+        build: Some(vec!["sh".into(), "-c".into(), build.into()]),
+        ..Default::default()
+    };
 
-    fs::write(
-        &config_path,
-        r#"
-bin = "myapp"
+    // Without a run step there's no need for an explicit `run`
+    let eff = effective_config(build_only("echo built"), None).unwrap();
+    assert!(eff.mode.has_run_step());
+    assert!(!eff.has_run_step());
+    assert!(eff.run.is_none());
+    let out = build_command(&eff.build, &eff).unwrap().output().unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "built\n");
 
-pre_build = [
-  ["cargo", "fmt"],
-  ["cargo", "clippy"]
-]
+    let eff = effective_config(build_only("echo broken >&2; exit 3"), None).unwrap();
+    let status = build_command(&eff.build, &eff).unwrap().status().unwrap();
+    assert_eq!(status.code(), Some(3));
 
-post_build = [
-  ["cargo", "test", "-q"]
-]
-"#,
+    let cli = Config {
+        once: None,
+        ..build_only("echo built")
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(matches!(err, RairError::InvalidConfig(_)));
+    assert!(err.to_string().contains("--once"), "{}", err);
+}
+
+#[test]
+fn test_no_run_builds_without_a_run_step() {
+    // a non-cargo project normally needs an explicit `run`; with no_run it's dropped
+    let cli = Config {
+        cargo: Some(false),
+        no_run: Some(true),
+        build: Some(vec!["echo".into(), "built".into()]),
+        run: Some(vec!["./never-started".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert!(eff.mode.has_run_step());
+    assert!(!eff.has_run_step());
+    assert!(!eff.runs_after_build(true));
+    assert!(!eff.runs_after_build(false));
+    assert!(eff.run.is_none());
+    assert!(!eff.once);
+
+    // unlike build_only it isn't tied to once-mode, and the cargo build is unchanged
+    let eff = effective_config(
+        Config {
+            no_run: Some(true),
+            ..Default::default()
+        },
+        None,
     )
     .unwrap();
+    assert!(!eff.has_run_step());
+    assert_eq!(eff.build[..2], ["cargo", "build"]);
+}
 
-    let cfg = load_config(&config_path).unwrap();
-    assert_eq!(cfg.pre_build.as_ref().unwrap().len(), 2);
-    assert_eq!(cfg.post_build.as_ref().unwrap().len(), 1);
+#[test]
+fn test_build_argv_placeholders_expand_changed_paths() {
+    let argv: Vec<String> = [
+        "./mybuild",
+        "--root={project_root}",
+        "{changed}",
+        "--list",
+        "{changed_list}",
+    ]
+    .map(String::from)
+    .to_vec();
+    let changed = [
+        PathBuf::from("/proj/src/a.rs"),
+        PathBuf::from("/proj/src/b.rs"),
+    ];
+    assert_eq!(
+        expand_build_argv(&argv, &changed, Path::new("/proj")),
+        [
+            "./mybuild",
+            "--root=/proj",
+            "/proj/src/a.rs",
+            "/proj/src/b.rs",
+            "--list",
+            "/proj/src/a.rs /proj/src/b.rs",
+        ]
+    );
+
+    // startup build: `{changed}` drops out, `{changed_list}` is empty
+    assert_eq!(
+        expand_build_argv(&argv, &[], Path::new("/proj")),
+        ["./mybuild", "--root=/proj", "--list", ""]
+    );
+
+    // a derived cargo build is left alone
+    let cargo: Vec<String> = ["cargo", "build"].map(String::from).to_vec();
+    assert_eq!(
+        expand_build_argv(&cargo, &changed, Path::new("/proj")),
+        cargo
+    );
 }
 
 #[test]
-fn test_load_config_nonexistent_errors() {
-    let result = load_config(&PathBuf::from("/nonexistent/path/.rair.toml"));
-    assert!(result.is_err());
+fn test_changed_list_covers_every_path_of_an_event() {
+    use notify::event::{CreateKind, EventKind};
+
+    // one event can carry several files, e.g. a backend that coalesces changes
+    let event = notify::Event::new(EventKind::Create(CreateKind::File))
+        .add_path(PathBuf::from("/proj/src/a.rs"))
+        .add_path(PathBuf::from("/proj/target/debug/app"))
+        .add_path(PathBuf::from("/proj/src/b.rs"));
+    let mut batch = ChangeBatch::default();
+    assert!(batch.record_event(&event, |p| !p.starts_with("/proj/target")));
+
+    let argv: Vec<String> = ["./mybuild", "{changed_list}"].map(String::from).to_vec();
+    assert_eq!(
+        expand_build_argv(&argv, &batch.paths(), Path::new("/proj")),
+        ["./mybuild", "/proj/src/a.rs /proj/src/b.rs"]
+    );
+    assert_eq!(
+        batch.changes()[1],
+        (PathBuf::from("/proj/src/b.rs"), Some(EventOp::Create))
+    );
+
+    // nothing relevant, nothing recorded
+    assert!(!batch.record_event(&event, |_| false));
+    assert_eq!(batch.len(), 2);
 }
 
 #[test]
-fn test_load_config_invalid_toml_errors() {
-    let dir = TempDir::new().unwrap();
-    let config_path = dir.path().join(".rair.toml");
+fn test_build_cwd_applied_to_build_command() {
+    let build_dir = TempDir::new().unwrap();
+    let run_dir = TempDir::new().unwrap();
+    let cli = Config {
+        build_cwd: Some(build_dir.path().to_string_lossy().to_string()),
+        run_cwd: Some(run_dir.path().to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
 
-    fs::write(&config_path, "this is not valid toml {{").unwrap();
+    let argv = vec!["sh".into(), "-c".into(), "pwd -P".into()];
+    let pwd = |mut cmd: std::process::Command| {
+        let out = cmd.output().unwrap();
+        PathBuf::from(String::from_utf8(out.stdout).unwrap().trim())
+    };
+    assert_eq!(
+        pwd(build_command(&argv, &eff).unwrap()),
+        build_dir.path().canonicalize().unwrap()
+    );
+    assert_eq!(
+        pwd(run_command(&argv, &eff).unwrap()),
+        run_dir.path().canonicalize().unwrap()
+    );
 
-    let result = load_config(&config_path);
-    assert!(result.is_err());
+    let cli = Config {
+        build_cwd: Some("/nonexistent/build/dir".into()),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(err.to_string().contains("build_cwd"), "{}", err);
 }
 
-// ============================================================================
-// Cargo Metadata Tests
-// ============================================================================
+#[test]
+fn test_run_cwd_missing_errors() {
+    let cli = Config {
+        run_cwd: Some("/nonexistent/run/dir".into()),
+        ..Default::default()
+    };
+    let err = effective_config(cli, None).unwrap_err();
+    assert!(format!("{:#}", err).contains("run_cwd"));
+}
 
 #[test]
-fn test_metadata_smoke_temp_project_inputs() {
-    let dir = TempDir::new().unwrap();
-    let root = dir.path();
+fn test_runner_prepended_to_run_argv() {
+    let cli = Config {
+        runner: Some(vec!["time".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
 
-    fs::write(
-        root.join("Cargo.toml"),
-        r#"
-[package]
-name = "tmp_rair_meta"
-version = "0.1.0"
-edition = "2021"
-"#,
-    )
-    .unwrap();
+    let argv = resolve_run_argv(&eff, |_| Ok(vec!["target/debug/app".into()])).unwrap();
+    assert_eq!(argv, vec!["time", "target/debug/app"]);
 
-    fs::create_dir_all(root.join("src")).unwrap();
-    fs::write(
-        root.join("src/main.rs"),
-        "fn main() { println!(\"ok\"); }\n",
-    )
-    .unwrap();
+    // Hooks are unaffected
+    assert!(eff.pre_run.is_empty());
+}
 
+#[test]
+fn test_runner_with_explicit_run() {
     let cli = Config {
-        manifest_path: Some(root.join("Cargo.toml").to_string_lossy().to_string()),
-        bin: Some("tmp_rair_meta".into()),
-        release: Some(false),
+        runner: Some(vec!["gdb".into(), "--args".into()]),
+        run: Some(vec!["./app".into(), "--port".into(), "80".into()]),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
-    assert_eq!(eff.bin.as_deref(), Some("tmp_rair_meta"));
-    assert!(eff.manifest_path.is_some());
-}
 
-// ============================================================================
-// Run Command Tests
-// ============================================================================
+    let argv = resolve_run_argv(&eff, |_| unreachable!()).unwrap();
+    let app = eff.root.join("app").to_string_lossy().into_owned();
+    assert_eq!(argv, vec!["gdb", "--args", &app, "--port", "80"]);
+}
 
 #[test]
-fn test_explicit_run_command() {
+fn test_run_args_follow_the_run_argv() {
     let cli = Config {
-        run: Some(vec!["/tmp/myapp".into(), "--arg".into()]),
+        runner: Some(vec!["gdb".into(), "--args".into()]),
+        run_args: Some(vec!["--port".into(), "80".into()]),
         ..Default::default()
     };
     let eff = effective_config(cli, None).unwrap();
+    let argv = resolve_run_argv(&eff, |_| Ok(vec!["target/debug/app".into()])).unwrap();
+    assert_eq!(argv, ["gdb", "--args", "target/debug/app", "--port", "80"]);
+    // Only the run: the build is untouched
+    assert_eq!(eff.build, ["cargo", "build"]);
+}
 
-    assert_eq!(eff.run.as_ref().unwrap()[0], "/tmp/myapp");
-    assert_eq!(eff.run.as_ref().unwrap()[1], "--arg");
+#[cfg(feature = "json")]
+#[test]
+fn test_cli_splits_build_args_from_run_args() {
+    let out = std::process::Command::new(env!("CARGO_BIN_EXE_rair"))
+        .args(["--print-config", "--format", "json"])
+        .args(["--build-arg", "--offline", "--", "foo", "--verbose"])
+        .env_remove("RAIR_ACTIVE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let eff: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    let build = eff["build"].as_array().unwrap();
+    assert_eq!(build.last().unwrap(), "--offline");
+    assert_eq!(eff["run_args"], serde_json::json!(["foo", "--verbose"]));
+    // --verbose after `--` is the run's, not rair's
+    assert_eq!(eff["verbose"], false);
 }
 
 #[test]
@@ -599,10 +4508,50 @@ fn test_run_defaults_to_none_for_cargo() {
 
 #[test]
 fn test_ignore_globs_with_invalid_pattern() {
-    let result = build_globset(&vec!["[invalid".into()]);
+    let result = build_globset(&["[invalid".into()]);
     assert!(result.is_err());
 }
 
+#[test]
+fn test_relative_programs_resolve_against_project_root() {
+    let cli = Config {
+        manifest_path: Some("/custom/path/Cargo.toml".into()),
+        build: Some(vec!["cargo".into(), "build".into()]),
+        run: Some(vec!["./x".into(), "--port".into(), "8080".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    assert_eq!(eff.project_root(), PathBuf::from("/custom/path"));
+    assert_eq!(
+        eff.run,
+        Some(vec![
+            "/custom/path/x".into(),
+            "--port".into(),
+            "8080".into()
+        ])
+    );
+    assert_eq!(eff.build, vec!["cargo".to_string(), "build".to_string()]);
+
+    // Without a manifest path the project root is the working directory
+    let cli = Config {
+        run: Some(vec!["./scripts/launch.sh".into()]),
+        ..Default::default()
+    };
+    let eff = effective_config(cli, None).unwrap();
+    let expected = eff.root.join("scripts/launch.sh");
+    assert_eq!(eff.run, Some(vec![expected.to_string_lossy().into_owned()]));
+
+    let root = Path::new("/proj");
+    assert_eq!(
+        resolve_program(vec!["../tools/run".into()], root),
+        vec!["/proj/../tools/run".to_string()]
+    );
+    assert_eq!(
+        resolve_program(vec!["bin/app".into()], root),
+        vec!["bin/app".to_string()]
+    );
+}
+
 #[test]
 fn test_manifest_path_preserved() {
     let cli = Config {
@@ -628,3 +4577,142 @@ fn test_debounce_conversion() {
 
     assert_eq!(eff.debounce.as_millis(), 500);
 }
+
+#[test]
+fn test_debounce_measured_from_end_of_build() {
+    let ms = Duration::from_millis;
+    let mut d = Debouncer::new(ms(300));
+    let t0 = Instant::now();
+    assert!(d.accept(t0));
+
+    // A 2s build started by an event at t0
+    d.cycle(t0, t0 + ms(2000));
+    assert!(!d.accept(t0 + ms(100))); // same burst as the triggering save
+    assert!(d.accept(t0 + ms(1000))); // saved mid-build: honored once it finishes
+    assert!(!d.accept(t0 + ms(2100))); // within the window after going idle
+    assert!(d.accept(t0 + ms(2300)));
+
+    // A build shorter than the window
+    d.cycle(t0, t0 + ms(50));
+    assert!(!d.accept(t0 + ms(300)));
+    assert!(d.accept(t0 + ms(350)));
+}
+
+#[test]
+fn test_change_batch_collapses_events_per_path() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("src")).unwrap();
+    let main = root.join("src/main.rs");
+
+    // an editor's save: create, then two modifies, one of them through `..`
+    let mut batch = ChangeBatch::default();
+    batch.record(&main, Some(EventOp::Create));
+    batch.record(&main, Some(EventOp::Modify));
+    batch.record(&root.join("src/../src/main.rs"), Some(EventOp::Modify));
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch.changes(), [(main.clone(), Some(EventOp::Modify))]);
+
+    // an event that doesn't say what happened keeps the last known kind
+    batch.record(&main, None);
+    batch.record(&root.join("build.rs"), Some(EventOp::Create));
+    assert_eq!(batch.paths(), [main, root.join("build.rs")]);
+    assert_eq!(
+        batch.describe(root),
+        format!(
+            "{} (modify), build.rs (create)",
+            Path::new("src").join("main.rs").display()
+        )
+    );
+}
+
+#[test]
+fn test_burst_digest_and_settle() {
+    // a checkout: 187 files over 12 directories
+    let paths: Vec<PathBuf> = (0..187)
+        .map(|i| PathBuf::from(format!("/proj/src/m{}/f{}.rs", i % 12, i)))
+        .collect();
+    assert_eq!(
+        format_burst_digest(&paths),
+        "187 files changed across 12 dirs -> rebuilding once"
+    );
+    assert_eq!(
+        format_burst_digest(&[PathBuf::from("/proj/src/main.rs")]),
+        "1 file changed across 1 dir -> rebuilding once"
+    );
+
+    let ms = Duration::from_millis;
+    let t0 = Instant::now();
+    let mut burst = ChangeBurst::new(50, ms(100));
+    // a single save goes through as usual
+    assert!(!burst.on_change(t0, 1));
+    assert!(!burst.should_fire(t0 + ms(500)));
+    // enough changes at once start a burst; the rest of it waits too
+    assert!(burst.on_change(t0, 187));
+    assert!(burst.on_change(t0 + ms(50), 1));
+    assert!(!burst.should_fire(t0 + ms(120)));
+    assert!(burst.should_fire(t0 + ms(150)));
+    // once built, it's over
+    assert!(!burst.should_fire(t0 + ms(300)));
+    assert!(!burst.on_change(t0 + ms(400), 1));
+
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(eff.burst_threshold, Some(50));
+    let cfg = Config {
+        burst_threshold: Some(0),
+        ..Config::default()
+    };
+    assert_eq!(effective_config(cfg, None).unwrap().burst_threshold, None);
+}
+
+#[test]
+fn test_debounce_modes() {
+    let ms = Duration::from_millis;
+    let t0 = Instant::now();
+
+    assert_eq!(
+        DebounceMode::parse("Trailing").unwrap(),
+        DebounceMode::Trailing
+    );
+    assert!(matches!(
+        DebounceMode::parse("middle"),
+        Err(RairError::InvalidConfig(_))
+    ));
+    let eff = effective_config(Config::default(), None).unwrap();
+    assert_eq!(eff.debounce_mode, DebounceMode::Leading);
+
+    // leading: the Debouncer drops the rest of the burst; what it accepts builds
+    let mut d = DebounceState::new(DebounceMode::Leading, ms(300));
+    assert!(d.accept(t0) && d.on_event(t0));
+    d.cycle(t0, t0 + ms(50));
+    assert!(!d.accept(t0 + ms(100)));
+    assert!(!d.should_fire(t0 + ms(1000)));
+
+    // trailing: every change waits until the burst has been quiet for the window
+    let mut d = DebounceState::new(DebounceMode::Trailing, ms(300));
+    assert!(d.accept(t0 + ms(100)));
+    assert!(!d.on_event(t0));
+    assert!(!d.on_event(t0 + ms(200)));
+    assert!(!d.should_fire(t0 + ms(400)));
+    assert!(d.should_fire(t0 + ms(500)));
+    assert!(!d.should_fire(t0 + ms(600))); // once per burst
+    d.cycle(t0 + ms(500), t0 + ms(600));
+    // a change that arrived before that build started is covered by it
+    assert!(!d.on_event(t0 + ms(450)));
+    assert!(!d.should_fire(t0 + ms(2000)));
+
+    // both: the first change builds right away; more in the burst build at its end
+    let mut d = DebounceState::new(DebounceMode::Both, ms(300));
+    assert!(d.on_event(t0));
+    d.cycle(t0, t0 + ms(50));
+    assert!(!d.should_fire(t0 + ms(1000))); // nothing more came
+    assert!(d.on_event(t0 + ms(1000)));
+    d.cycle(t0 + ms(1000), t0 + ms(1050));
+    assert!(!d.on_event(t0 + ms(1100)));
+    assert!(!d.on_event(t0 + ms(1200)));
+    assert!(!d.should_fire(t0 + ms(1400)));
+    assert!(d.should_fire(t0 + ms(1500)));
+    d.cycle(t0 + ms(1500), t0 + ms(1550));
+    // after a quiet window the next change leads again
+    assert!(d.on_event(t0 + ms(2000)));
+}